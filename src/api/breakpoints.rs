@@ -0,0 +1,42 @@
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+
+use crate::AppState;
+use crate::proxy::breakpoints::{Breakpoint, Decision, HeldMessage, NewBreakpoint};
+use crate::utils::errors::AppError;
+
+/// GET /breakpoints - breakpoints currently set
+pub async fn list_breakpoints(State(state): State<AppState>) -> Json<Vec<Breakpoint>> {
+    Json(state.breakpoints.list())
+}
+
+/// POST /breakpoints - hold the requests or responses matching a filter
+pub async fn add_breakpoint(
+    State(state): State<AppState>,
+    Json(request): Json<NewBreakpoint>,
+) -> Result<(StatusCode, Json<Breakpoint>), AppError> {
+    let breakpoint = state.breakpoints.add(request)?;
+    Ok((StatusCode::CREATED, Json(breakpoint)))
+}
+
+/// DELETE /breakpoints/{id}
+pub async fn remove_breakpoint(State(state): State<AppState>, Path(id): Path<u64>) -> Result<StatusCode, AppError> {
+    state.breakpoints.remove(id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /breakpoints/held - messages waiting for a decision
+pub async fn list_held(State(state): State<AppState>) -> Json<Vec<HeldMessage>> {
+    Json(state.breakpoints.held())
+}
+
+/// POST /breakpoints/held/{id} - resume (with edits), answer or drop a held message
+pub async fn decide(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(decision): Json<Decision>,
+) -> Result<StatusCode, AppError> {
+    state.breakpoints.decide(id, decision)?;
+    Ok(StatusCode::NO_CONTENT)
+}
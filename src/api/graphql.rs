@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+use axum::Json;
+use axum::extract::State;
+use serde::Serialize;
+
+use crate::AppState;
+use crate::storage::models::OperationType;
+
+/// Runs of one GraphQL operation, each operation of a batch counts on its own
+#[derive(Debug, Serialize)]
+pub struct OperationSummary {
+    pub operation_name: Option<String>,
+    pub operation_type: Option<OperationType>,
+    pub count: u64,
+    /// Runs answered with GraphQL errors
+    pub errors: u64,
+    /// Average time until the response headers arrived
+    pub avg_duration_ms: Option<u64>,
+    /// Unix timestamp (milliseconds) of the most recent exchange
+    pub last_seen_ms: u64,
+}
+
+/// GET /graphql/operations - captured GraphQL exchanges grouped by operation, most frequent first
+pub async fn list_operations(State(state): State<AppState>) -> Json<Vec<OperationSummary>> {
+    // Summary of each operation with the total and number of its durations
+    let mut groups = BTreeMap::<_, (OperationSummary, u64, u64)>::new();
+    state.interceptor.store().scan(|exchange| {
+        for graphql in &exchange.graphql {
+            let (summary, total, timed) = groups
+                .entry((graphql.operation_name.clone(), graphql.operation_type))
+                .or_insert_with_key(|(operation_name, operation_type)| {
+                    let summary = OperationSummary {
+                        operation_name: operation_name.clone(),
+                        operation_type: *operation_type,
+                        count: 0,
+                        errors: 0,
+                        avg_duration_ms: None,
+                        last_seen_ms: 0,
+                    };
+                    (summary, 0, 0)
+                });
+            summary.count += 1;
+            summary.errors += u64::from(!graphql.errors.is_empty());
+            summary.last_seen_ms = summary.last_seen_ms.max(exchange.timestamp_ms);
+            if let Some(duration) = exchange.duration_ms {
+                *total += duration;
+                *timed += 1;
+            }
+        }
+    });
+
+    let mut operations: Vec<_> = groups
+        .into_values()
+        .map(|(mut summary, total, timed)| {
+            summary.avg_duration_ms = (timed > 0).then(|| total / timed);
+            summary
+        })
+        .collect();
+    operations.sort_by_key(|o| std::cmp::Reverse(o.count));
+    Json(operations)
+}
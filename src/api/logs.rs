@@ -0,0 +1,53 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use serde::Deserialize;
+
+use crate::AppState;
+use crate::storage::models::{CapturedExchange, OperationType};
+
+/// Number of exchanges returned when no limit is given
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListParams {
+    pub limit: Option<usize>,
+    /// Only GraphQL exchanges (true) or only the others (false)
+    pub graphql: Option<bool>,
+    /// GraphQL `operationName`
+    pub operation: Option<String>,
+    pub operation_type: Option<OperationType>,
+    /// Only GraphQL responses with errors (true) or without (false)
+    pub graphql_errors: Option<bool>,
+}
+
+impl ListParams {
+    /// Operation filters match when any operation of a batch does
+    fn matches(&self, exchange: &CapturedExchange) -> bool {
+        let graphql = &exchange.graphql;
+        let failed = graphql.iter().any(|g| !g.errors.is_empty());
+        self.graphql.is_none_or(|wanted| graphql.is_empty() != wanted)
+            && self.operation.as_ref().is_none_or(|name| {
+                graphql.iter().any(|g| g.operation_name.as_ref() == Some(name))
+            })
+            && self.operation_type.is_none_or(|kind| graphql.iter().any(|g| g.operation_type == Some(kind)))
+            && self.graphql_errors.is_none_or(|wanted| !graphql.is_empty() && failed == wanted)
+    }
+}
+
+/// GET /logs - most recent captured exchanges first, optionally filtered by GraphQL operation
+pub async fn list_logs(
+    State(state): State<AppState>,
+    Query(params): Query<ListParams>,
+) -> Json<Vec<CapturedExchange>> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    Json(state.interceptor.store().recent_matching(limit, |e| params.matches(e)))
+}
+
+/// GET /logs/{id} - a single captured exchange
+pub async fn get_log(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<CapturedExchange>, StatusCode> {
+    state.interceptor.store().get(id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
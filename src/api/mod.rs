@@ -0,0 +1,26 @@
+use axum::Router;
+use axum::routing::{delete, get, post};
+
+use crate::AppState;
+
+pub mod breakpoints;
+pub mod graphql;
+pub mod logs;
+pub mod network;
+
+/// Prefix reserved for the logger's own API
+/// Requests under it are served by the proxy instead of being forwarded
+pub const API_PREFIX: &str = "/__logger/api";
+
+/// Routes of the logger API, to be nested under `API_PREFIX`
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/logs", get(logs::list_logs))
+        .route("/logs/{id}", get(logs::get_log))
+        .route("/graphql/operations", get(graphql::list_operations))
+        .route("/network", get(network::get_network).put(network::select_profile))
+        .route("/breakpoints", get(breakpoints::list_breakpoints).post(breakpoints::add_breakpoint))
+        .route("/breakpoints/{id}", delete(breakpoints::remove_breakpoint))
+        .route("/breakpoints/held", get(breakpoints::list_held))
+        .route("/breakpoints/held/{id}", post(breakpoints::decide))
+}
@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+use axum::Json;
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::config::NetworkProfile;
+use crate::proxy::network::NetworkSelection;
+use crate::utils::errors::AppError;
+
+#[derive(Debug, Serialize)]
+pub struct NetworkStatus {
+    /// Every profile that can be selected
+    pub profiles: BTreeMap<String, NetworkProfile>,
+    #[serde(flatten)]
+    pub selection: NetworkSelection,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SelectProfile {
+    /// Route or host name, the global profile is switched without it
+    pub route: Option<String>,
+    /// Profile name, null turns the simulation off (or back to the global profile for a route)
+    pub profile: Option<String>,
+}
+
+/// GET /network - available profiles and the ones in use
+pub async fn get_network(State(state): State<AppState>) -> Json<NetworkStatus> {
+    Json(status(&state, state.network.selection()))
+}
+
+/// PUT /network - switch the global profile or the profile of one route
+pub async fn select_profile(
+    State(state): State<AppState>,
+    Json(request): Json<SelectProfile>,
+) -> Result<Json<NetworkStatus>, AppError> {
+    let selection = state.network.select(request.route.as_deref(), request.profile)?;
+    Ok(Json(status(&state, selection)))
+}
+
+fn status(state: &AppState, selection: NetworkSelection) -> NetworkStatus {
+    NetworkStatus { profiles: state.network.profiles().clone(), selection }
+}
@@ -1,7 +1,9 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
 use std::path::Path;
-use clap::Parser;
+use std::time::Duration;
+use clap::{Parser, ValueEnum};
 use serde::{Deserialize, Serialize};
 use tracing::info;
 use toml;
@@ -55,6 +57,17 @@ pub struct CliArgs {
     )]
     pub database: Option<String>,
 
+    /// Whether to run as a reverse proxy in front of the target or as a forward proxy
+    ///
+    /// Default: reverse
+    #[arg(
+        short = 'm',
+        long = "mode",
+        value_enum,
+        help = "Proxy mode: reverse (in front of --target) or forward (HTTP_PROXY for an app) [default: reverse]"
+    )]
+    pub mode: Option<ProxyMode>,
+
     /// Path to TOML configuration file
     ///
     /// If specified, loads configuration from this file first,
@@ -73,31 +86,758 @@ pub struct CliArgs {
         help = "Enable verbose logging output"
     )]
     pub verbose: bool,
+
+    /// Write every captured exchange to a cassette file
+    #[arg(
+        long = "record",
+        value_name = "FILE",
+        conflicts_with = "replay",
+        help = "Record captured exchanges to a cassette file"
+    )]
+    pub record: Option<String>,
+
+    /// Answer requests from a cassette file instead of the target
+    #[arg(
+        long = "replay",
+        value_name = "FILE",
+        help = "Replay responses from a cassette file recorded with --record"
+    )]
+    pub replay: Option<String>,
 }
 
 /// TOML configuration file structure
-/// This contains ALL possible configuration options (future: 50+ fields)
-/// For now, we only implement the essential 3 fields
+/// Every field is optional, whatever is set overrides the defaults and is itself overridden by CLI flags
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct TomlConfig {
+    #[serde(default)]
+    pub mode: Option<ProxyMode>,
+
     #[serde(default)]
     pub target_url: Option<String>,
 
+    /// Second target receiving a copy of every request sent to `target_url`
+    #[serde(default)]
+    pub shadow_target_url: Option<String>,
+
     #[serde(default)]
     pub proxy_port: Option<u16>,
 
     #[serde(default)]
     pub database_path: Option<String>,
+
+    /// Forward the client's Host header instead of rewriting it to the target host
+    #[serde(default)]
+    pub preserve_host: Option<bool>,
+
+    #[serde(default)]
+    pub logging: Option<LoggingConfig>,
+
+    #[serde(default)]
+    pub upstream: Option<UpstreamConfig>,
+
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+
+    #[serde(default)]
+    pub forward: Option<ForwardConfig>,
+
+    #[serde(default)]
+    pub routes: Option<Vec<RouteConfig>>,
+
+    /// `[hosts]` table mapping an incoming Host name to its target URL
+    #[serde(default)]
+    pub hosts: Option<BTreeMap<String, String>>,
+
+    /// `[pools.<name>]` sections, referenced from any target as `pool:<name>`
+    #[serde(default)]
+    pub pools: Option<BTreeMap<String, PoolConfig>>,
+
+    #[serde(default)]
+    pub faults: Option<Vec<FaultConfig>>,
+
+    #[serde(default)]
+    pub network: Option<NetworkConfig>,
+
+    #[serde(default)]
+    pub mocks: Option<Vec<MockConfig>>,
+
+    #[serde(default)]
+    pub cassette: Option<CassetteConfig>,
+
+    #[serde(default)]
+    pub rewrites: Option<Vec<RewriteConfig>>,
+
+    #[serde(default)]
+    pub grpc: Option<GrpcConfig>,
+}
+
+/// How the proxy finds the server a request is meant for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyMode {
+    /// Every request is sent to `target_url`
+    #[default]
+    Reverse,
+    /// Clients use the proxy through `HTTP_PROXY`/`HTTPS_PROXY` and name the destination themselves
+    Forward,
+}
+
+/// `[logging]` section of the TOML file
+/// Controls what the capture pipeline keeps for each exchange
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Maximum number of body bytes captured per request/response, in KB
+    /// Bodies are always forwarded in full, the stored copy is marked as truncated
+    #[serde(default = "default_max_body_size_kb")]
+    pub max_body_size_kb: usize,
+}
+
+fn default_max_body_size_kb() -> usize {
+    100
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            max_body_size_kb: default_max_body_size_kb(),
+        }
+    }
+}
+
+/// `[upstream]` section of the TOML file
+/// Controls how the proxy connects to the target application
+/// Timeouts are in milliseconds and 0 disables them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamConfig {
+    /// HTTP version spoken to the target
+    #[serde(default)]
+    pub http_version: UpstreamHttpVersion,
+
+    /// `[upstream.tls]`, how https targets are trusted and authenticated
+    #[serde(default)]
+    pub tls: UpstreamTlsConfig,
+
+    /// Time allowed to open the TCP connection to the target
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+
+    /// Time allowed between forwarding the request and receiving the response headers
+    #[serde(default = "default_response_header_timeout_ms")]
+    pub response_header_timeout_ms: u64,
+
+    /// Time allowed for the whole exchange, response body included
+    /// Off by default since it also cuts long-lived streams such as SSE
+    #[serde(default)]
+    pub request_timeout_ms: u64,
+
+    /// Idle connections kept open per target host for reuse
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+
+    /// How long an idle keep-alive connection stays in the pool before it is closed
+    #[serde(default = "default_keep_alive_ms")]
+    pub keep_alive_ms: u64,
+
+    /// `[upstream.retry]`, how idempotent requests are retried when the target is restarting
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+fn default_connect_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_response_header_timeout_ms() -> u64 {
+    60_000
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    32
+}
+
+fn default_keep_alive_ms() -> u64 {
+    90_000
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            http_version: UpstreamHttpVersion::default(),
+            tls: UpstreamTlsConfig::default(),
+            connect_timeout_ms: default_connect_timeout_ms(),
+            response_header_timeout_ms: default_response_header_timeout_ms(),
+            request_timeout_ms: 0,
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            keep_alive_ms: default_keep_alive_ms(),
+            retry: RetryConfig::default(),
+        }
+    }
+}
+
+/// `[upstream.retry]` section of the TOML file
+/// GET, HEAD, PUT and DELETE requests are sent again when the connection is refused or reset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Total number of attempts, 1 disables retries
+    #[serde(default = "default_retry_attempts")]
+    pub attempts: u32,
+
+    /// Wait before the first retry, doubled after each one
+    #[serde(default = "default_retry_backoff_ms")]
+    pub backoff_ms: u64,
+
+    /// Upper bound of the wait between two attempts
+    #[serde(default = "default_retry_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+fn default_retry_attempts() -> u32 {
+    1
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    100
+}
+
+fn default_retry_max_backoff_ms() -> u64 {
+    2_000
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: default_retry_attempts(),
+            backoff_ms: default_retry_backoff_ms(),
+            max_backoff_ms: default_retry_max_backoff_ms(),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Wait before attempt number `attempt` (the first retry is attempt 2)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(2));
+        Duration::from_millis(self.backoff_ms.saturating_mul(factor).min(self.max_backoff_ms))
+    }
+}
+
+/// `[upstream.tls]` section of the TOML file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpstreamTlsConfig {
+    /// PEM bundle of extra CA certificates trusted in addition to the public roots
+    #[serde(default)]
+    pub ca_bundle: Option<String>,
+
+    /// PEM client certificate chain presented for mutual TLS
+    #[serde(default)]
+    pub client_cert: Option<String>,
+
+    /// PEM private key matching `client_cert`
+    #[serde(default)]
+    pub client_key: Option<String>,
+
+    /// Name sent as SNI and checked against the certificate instead of the target host
+    #[serde(default)]
+    pub server_name: Option<String>,
+
+    /// Accept any upstream certificate, only meant for throwaway local setups
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
+/// HTTP version used for upstream connections
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamHttpVersion {
+    /// HTTP/2 when negotiated through ALPN on https targets, HTTP/1.1 otherwise
+    /// Whatever version the client spoke, plaintext targets get no h2c as their support is unknown
+    #[default]
+    Auto,
+    /// Always HTTP/1.1
+    Http1,
+    /// Always HTTP/2, using prior knowledge (h2c) on http targets
+    Http2,
+}
+
+/// `[tls]` section of the TOML file
+/// When present, the proxy listener serves HTTPS instead of plain HTTP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// PEM certificate chain to serve, generated for localhost when unset
+    #[serde(default)]
+    pub cert_path: Option<String>,
+
+    /// PEM private key matching `cert_path`
+    #[serde(default)]
+    pub key_path: Option<String>,
+
+    /// Where the generated local CA and localhost certificate are kept
+    #[serde(default = "default_cert_dir")]
+    pub cert_dir: String,
+}
+
+fn default_cert_dir() -> String {
+    "./endpoint-logger-certs".to_string()
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert_path: None,
+            key_path: None,
+            cert_dir: default_cert_dir(),
+        }
+    }
+}
+
+/// `[forward]` section of the TOML file, only used in forward-proxy mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForwardConfig {
+    /// Decrypt CONNECT tunnels with certificates issued by the local CA
+    /// Without it, only tunnel metadata is recorded
+    #[serde(default)]
+    pub intercept_tls: bool,
+
+    /// Directory holding the local CA used to intercept tunnels
+    #[serde(default = "default_cert_dir")]
+    pub cert_dir: String,
+}
+
+impl Default for ForwardConfig {
+    fn default() -> Self {
+        Self {
+            intercept_tls: false,
+            cert_dir: default_cert_dir(),
+        }
+    }
+}
+
+/// `[[routes]]` entry of the TOML file
+/// Sends requests under a path prefix to their own target instead of `target_url`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteConfig {
+    /// Label recorded on captured exchanges, defaults to the prefix
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Path prefix matched on segment boundaries, "/api" matches "/api" and "/api/users" but not "/apis"
+    pub prefix: String,
+
+    /// URL requests under `prefix` are forwarded to
+    pub target: String,
+
+    /// Remove `prefix` from the path before forwarding
+    #[serde(default)]
+    pub strip_prefix: bool,
+
+    /// Network profile simulated for this route instead of the global one
+    #[serde(default)]
+    pub network_profile: Option<String>,
+
+    /// Second target receiving a copy of every request sent to `target`
+    #[serde(default)]
+    pub shadow_target: Option<String>,
+}
+
+impl RouteConfig {
+    /// Label recorded on captured exchanges, the name or else the prefix
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.prefix)
+    }
+}
+
+/// `[pools.<name>]` section of the TOML file
+/// A set of interchangeable targets, e.g. replicas of the same service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolConfig {
+    /// Target URLs of the pool members
+    pub members: Vec<String>,
+
+    #[serde(default)]
+    pub strategy: BalanceStrategy,
+
+    /// Active health probes, members are always considered healthy without it
+    #[serde(default)]
+    pub health_check: Option<HealthCheckConfig>,
+}
+
+/// How a pool picks the member for each request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceStrategy {
+    #[default]
+    RoundRobin,
+    /// Member with the fewest requests in flight
+    LeastConnections,
+    Random,
+}
+
+/// `[pools.<name>.health_check]` section of the TOML file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheckConfig {
+    /// Path requested on each member, any 2xx or 3xx answer counts as healthy
+    #[serde(default = "default_health_check_path")]
+    pub path: String,
+
+    #[serde(default = "default_health_check_interval_ms")]
+    pub interval_ms: u64,
+
+    #[serde(default = "default_health_check_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_health_check_path() -> String {
+    "/".to_string()
+}
+
+fn default_health_check_interval_ms() -> u64 {
+    5000
+}
+
+fn default_health_check_timeout_ms() -> u64 {
+    1000
+}
+
+/// `[[faults]]` entry of the TOML file
+/// Injects failures into matching requests to test how clients cope with them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaultConfig {
+    /// Label recorded on affected exchanges, defaults to the path glob
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Only requests with this method are affected, any method when absent
+    #[serde(default)]
+    pub method: Option<String>,
+
+    /// Path glob, `*` matches within a segment and `**` across segments
+    pub path: String,
+
+    /// Chance from 0.0 to 1.0 that a matching request is affected
+    #[serde(default = "default_fault_probability")]
+    pub probability: f64,
+
+    /// Delay before the request is forwarded
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+
+    /// Answer with this status instead of forwarding
+    #[serde(default)]
+    pub status: Option<u16>,
+
+    /// Close the client connection without answering
+    #[serde(default)]
+    pub drop_connection: bool,
+
+    /// Close the connection once this many bytes of the response body were sent
+    #[serde(default)]
+    pub truncate_body: Option<u64>,
+}
+
+fn default_fault_probability() -> f64 {
+    1.0
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            method: None,
+            path: "/**".to_string(),
+            probability: default_fault_probability(),
+            latency_ms: None,
+            status: None,
+            drop_connection: false,
+            truncate_body: None,
+        }
+    }
+}
+
+/// `[[mocks]]` entry of the TOML file
+/// Answers matching requests with a canned response instead of forwarding them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockConfig {
+    /// Label recorded on mocked exchanges, defaults to the path glob
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Only requests with this method are mocked, any method when absent
+    #[serde(default)]
+    pub method: Option<String>,
+
+    /// Path glob, `*` matches within a segment and `**` across segments
+    pub path: String,
+
+    #[serde(default = "default_mock_status")]
+    pub status: u16,
+
+    /// Response headers, values may use templates
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Response body template
+    #[serde(default)]
+    pub body: Option<String>,
+
+    /// File holding the response body template, read at startup
+    #[serde(default)]
+    pub body_file: Option<String>,
+}
+
+fn default_mock_status() -> u16 {
+    200
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            method: None,
+            path: "/**".to_string(),
+            status: default_mock_status(),
+            headers: BTreeMap::new(),
+            body: None,
+            body_file: None,
+        }
+    }
+}
+
+/// `[[rewrites]]` entry of the TOML file
+/// Changes matching requests before they are forwarded, or their responses before they reach the client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewriteConfig {
+    /// Label recorded on rewritten exchanges, defaults to the path glob
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Whether the rule changes the request or the response
+    #[serde(default)]
+    pub phase: RewritePhase,
+
+    /// Only requests with this method are rewritten, any method when absent
+    #[serde(default)]
+    pub method: Option<String>,
+
+    /// Path glob matched against the request as the client sent it
+    pub path: String,
+
+    /// Headers added, or replacing any header of the same name
+    #[serde(default)]
+    pub set_headers: BTreeMap<String, String>,
+
+    /// Headers added next to any existing value
+    #[serde(default)]
+    pub add_headers: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub remove_headers: Vec<String>,
+
+    /// Regex replacement of the request path, requests only
+    #[serde(default)]
+    pub rewrite_path: Option<PathRewrite>,
+
+    /// Status sent to the client instead of the upstream one, responses only
+    #[serde(default)]
+    pub status: Option<u16>,
+
+    /// Changes to a JSON body, applied in order
+    #[serde(default)]
+    pub json_patch: Vec<JsonPatchOp>,
 }
 
+impl Default for RewriteConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            phase: RewritePhase::default(),
+            method: None,
+            path: "/**".to_string(),
+            set_headers: BTreeMap::new(),
+            add_headers: BTreeMap::new(),
+            remove_headers: Vec::new(),
+            rewrite_path: None,
+            status: None,
+            json_patch: Vec::new(),
+        }
+    }
+}
+
+/// Side of the exchange a `[[rewrites]]` rule changes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RewritePhase {
+    #[default]
+    Request,
+    Response,
+}
+
+/// `rewrite_path = { pattern = "^/v1/(.*)", replacement = "/v2/$1" }`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathRewrite {
+    pub pattern: String,
+    /// May refer to captures as `$1` or `${name}`
+    pub replacement: String,
+}
+
+/// Single operation of a `json_patch`, with RFC 6902 semantics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonPatchOp {
+    pub op: JsonPatchKind,
+    /// JSON Pointer to the value, e.g. `/user/tags/0`
+    pub path: String,
+    /// New value, required by `add` and `replace`
+    #[serde(default)]
+    pub value: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonPatchKind {
+    Add,
+    Remove,
+    Replace,
+}
+
+/// `[network]` section of the TOML file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Profile simulated for every request not routed to one of its own
+    #[serde(default)]
+    pub profile: Option<String>,
+
+    /// `[network.profiles.<name>]` custom profiles, on top of the built-in ones
+    #[serde(default)]
+    pub profiles: BTreeMap<String, NetworkProfile>,
+}
+
+impl NetworkConfig {
+    /// Built-in profiles with the custom ones added, a custom profile replaces a built-in one of the same name
+    pub fn all_profiles(&self) -> BTreeMap<String, NetworkProfile> {
+        let mut profiles = BTreeMap::from([
+            ("3g".to_string(), NetworkProfile { latency_ms: 300, bandwidth_kbps: 750 }),
+            ("slow-wifi".to_string(), NetworkProfile { latency_ms: 100, bandwidth_kbps: 2_000 }),
+            ("satellite".to_string(), NetworkProfile { latency_ms: 600, bandwidth_kbps: 5_000 }),
+        ]);
+        profiles.extend(self.profiles.clone());
+        profiles
+    }
+}
+
+/// Simulated network conditions between the client and the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkProfile {
+    /// Round-trip latency added before each request is forwarded
+    #[serde(default)]
+    pub latency_ms: u64,
+
+    /// Response throughput in kilobits per second, 0 leaves it unlimited
+    #[serde(default)]
+    pub bandwidth_kbps: u64,
+}
+
+/// `[cassette]` section of the TOML file
+/// `record` and `replay` are usually given on the command line as `--record` and `--replay`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteConfig {
+    /// Write every captured exchange to this file, replacing its content
+    #[serde(default)]
+    pub record: Option<String>,
+
+    /// Answer requests from this file instead of the target
+    #[serde(default)]
+    pub replay: Option<String>,
+
+    /// Parts of a request compared to find its recorded interaction
+    #[serde(default = "default_cassette_match_on")]
+    pub match_on: Vec<CassetteMatch>,
+
+    /// What happens to requests the cassette has no interaction for
+    #[serde(default)]
+    pub on_miss: CassetteMiss,
+}
+
+fn default_cassette_match_on() -> Vec<CassetteMatch> {
+    vec![CassetteMatch::Method, CassetteMatch::Path, CassetteMatch::Query]
+}
+
+impl CassetteConfig {
+    /// Replaying without ever falling back to the target, which is then not needed
+    pub fn is_offline(&self) -> bool {
+        self.replay.is_some() && self.on_miss == CassetteMiss::Strict
+    }
+}
+
+impl Default for CassetteConfig {
+    fn default() -> Self {
+        Self {
+            record: None,
+            replay: None,
+            match_on: default_cassette_match_on(),
+            on_miss: CassetteMiss::default(),
+        }
+    }
+}
+
+/// `[grpc]` section of the TOML file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GrpcConfig {
+    /// Binary `FileDescriptorSet` used to decode gRPC messages to JSON,
+    /// e.g. from `protoc --include_imports --descriptor_set_out=protos.binpb`
+    #[serde(default)]
+    pub descriptor_set: Option<String>,
+}
+
+/// Request part compared when replaying
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMatch {
+    Method,
+    Path,
+    /// Query parameters, in any order
+    Query,
+    /// SHA-256 of the request body
+    Body,
+}
+
+/// Policy for requests missing from the replayed cassette
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMiss {
+    /// Answer with an error, the target is never contacted
+    #[default]
+    Strict,
+    /// Forward the request to the target as usual
+    Passthrough,
+}
+
+/// Prefix of targets naming a `[pools.<name>]` section instead of a URL
+pub const POOL_PREFIX: &str = "pool:";
+
 /// Main application configuration
 /// This is the complete config that will be used at runtime
 #[derive(Clone, Debug)]
 pub struct AppConfig {
+    pub mode: ProxyMode,
     pub target_url: String,
+    /// Mirror of `target_url`, its responses are compared with the target's but never returned
+    pub shadow_target_url: Option<String>,
     pub proxy_port: u16,
     pub database_path: String,
     pub verbose: bool,
+    pub preserve_host: bool,
+    pub logging: LoggingConfig,
+    pub upstream: UpstreamConfig,
+    pub tls: Option<TlsConfig>,
+    pub forward: ForwardConfig,
+    pub routes: Vec<RouteConfig>,
+    pub hosts: BTreeMap<String, String>,
+    pub pools: BTreeMap<String, PoolConfig>,
+    pub faults: Vec<FaultConfig>,
+    pub network: NetworkConfig,
+    pub mocks: Vec<MockConfig>,
+    pub cassette: CassetteConfig,
+    pub rewrites: Vec<RewriteConfig>,
+    pub grpc: GrpcConfig,
 }
 
 impl AppConfig {
@@ -163,15 +903,63 @@ impl AppConfig {
     /// Merge TOML configuration
     /// TOML values override defaults
     fn merge_toml(mut self, toml: TomlConfig) -> Self {
+        if let Some(mode) = toml.mode {
+            self.mode = mode;
+        }
         if let Some(target) = toml.target_url {
             self.target_url = target;
         }
+        if let Some(shadow) = toml.shadow_target_url {
+            self.shadow_target_url = Some(shadow);
+        }
         if let Some(port) = toml.proxy_port {
             self.proxy_port = port;
         }
         if let Some(database) = toml.database_path {
             self.database_path = database;
         }
+        if let Some(preserve_host) = toml.preserve_host {
+            self.preserve_host = preserve_host;
+        }
+        if let Some(logging) = toml.logging {
+            self.logging = logging;
+        }
+        if let Some(upstream) = toml.upstream {
+            self.upstream = upstream;
+        }
+        if let Some(tls) = toml.tls {
+            self.tls = Some(tls);
+        }
+        if let Some(forward) = toml.forward {
+            self.forward = forward;
+        }
+        if let Some(routes) = toml.routes {
+            self.routes = routes;
+        }
+        if let Some(hosts) = toml.hosts {
+            self.hosts = hosts;
+        }
+        if let Some(pools) = toml.pools {
+            self.pools = pools;
+        }
+        if let Some(faults) = toml.faults {
+            self.faults = faults;
+        }
+        if let Some(network) = toml.network {
+            self.network = network;
+        }
+        if let Some(mocks) = toml.mocks {
+            self.mocks = mocks;
+        }
+        if let Some(cassette) = toml.cassette {
+            self.cassette = cassette;
+        }
+        if let Some(rewrites) = toml.rewrites {
+            self.rewrites = rewrites;
+        }
+        if let Some(grpc) = toml.grpc {
+            self.grpc = grpc;
+        }
         self
     }
 
@@ -200,6 +988,9 @@ impl AppConfig {
     /// Merge CLI arguments
     /// CLI values override everything (ENV, TOML, defaults)
     fn merge_cli(mut self, cli: CliArgs) -> Self {
+        if let Some(mode) = cli.mode {
+            self.mode = mode;
+        }
         if let Some(target) = cli.target {
             self.target_url = target;
         }
@@ -212,12 +1003,112 @@ impl AppConfig {
         if cli.verbose {
             self.verbose = true;
         }
+        // Recording and replaying exclude each other, the flag given wins over the TOML file
+        if let Some(record) = cli.record {
+            self.cassette.record = Some(record);
+            self.cassette.replay = None;
+        }
+        if let Some(replay) = cli.replay {
+            self.cassette.replay = Some(replay);
+            self.cassette.record = None;
+        }
         self
     }
 
     /// Validate the configuration
     /// Checks that required fields are set and values are valid
     pub fn validate(&self) -> Result<(), AppError> {
+        for fault in &self.faults {
+            validate_fault(fault)?;
+        }
+        for mock in &self.mocks {
+            validate_mock(mock)?;
+        }
+        for rewrite in &self.rewrites {
+            validate_rewrite(rewrite)?;
+        }
+        let profiles = self.network.all_profiles();
+        let selected = self.routes.iter().filter_map(|r| r.network_profile.as_ref());
+        for profile in self.network.profile.iter().chain(selected) {
+            if !profiles.contains_key(profile) {
+                return Err(AppError::ValidateConfigError(format!(
+                    "Unknown network profile '{}' - use one of: {}.",
+                    profile,
+                    profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                )));
+            }
+        }
+        if self.cassette.record.is_some() && self.cassette.replay.is_some() {
+            return Err(AppError::ValidateConfigError(
+                "A cassette can't be recorded and replayed at once - set only one of record and replay.".to_string(),
+            ));
+        }
+        if self.cassette.match_on.is_empty() {
+            return Err(AppError::ValidateConfigError(
+                "Cassette match_on is empty - list at least one of method, path, query, body.".to_string(),
+            ));
+        }
+        if self.upstream.tls.insecure_skip_verify && self.upstream.tls.ca_bundle.is_some() {
+            return Err(AppError::ValidateConfigError(
+                "[upstream.tls] ca_bundle is unused with insecure_skip_verify = true - set only one of them.".to_string(),
+            ));
+        }
+
+        // A forward proxy learns the destination from each request, target_url is not used
+        if self.mode == ProxyMode::Forward {
+            return self.validate_port().map_err(|e| AppError::ValidateConfigError(e.to_string()));
+        }
+
+        for (name, pool) in &self.pools {
+            if pool.members.is_empty() {
+                return Err(AppError::ValidateConfigError(format!(
+                    "Pool '{}' has no members - list at least one target URL in members.",
+                    name
+                )));
+            }
+            for member in &pool.members {
+                validate_target_url(member).map_err(|e| AppError::ValidateConfigError(e.to_string()))?;
+            }
+            if let Some(check) = &pool.health_check
+                && (check.interval_ms == 0 || check.timeout_ms == 0)
+            {
+                return Err(AppError::ValidateConfigError(format!(
+                    "Pool '{}' health_check needs interval_ms and timeout_ms above 0.",
+                    name
+                )));
+            }
+        }
+
+        // Routes and hosts get their own targets, target_url is then only the fallback
+        for route in &self.routes {
+            if !route.prefix.starts_with('/') {
+                return Err(AppError::ValidateConfigError(format!(
+                    "Invalid route prefix: '{}' - prefixes must start with '/'.",
+                    route.prefix
+                )));
+            }
+            for target in std::iter::once(&route.target).chain(&route.shadow_target) {
+                self.validate_target(target).map_err(|e| AppError::ValidateConfigError(e.to_string()))?;
+            }
+        }
+        if let Some(shadow) = &self.shadow_target_url {
+            self.validate_target(shadow).map_err(|e| AppError::ValidateConfigError(e.to_string()))?;
+        }
+        for (host, target) in &self.hosts {
+            if host.is_empty() || host.contains([':', '/']) {
+                return Err(AppError::ValidateConfigError(format!(
+                    "Invalid host: '{}' - use a bare host name such as api.localtest.me, without scheme or port.",
+                    host
+                )));
+            }
+            self.validate_target(target).map_err(|e| AppError::ValidateConfigError(e.to_string()))?;
+        }
+        // Strict replay answers everything from the cassette, the target is never contacted
+        let has_targets = !(self.routes.is_empty() && self.hosts.is_empty());
+        if self.target_url.is_empty() && (has_targets || self.cassette.is_offline()) {
+            return self.validate_port().map_err(|e| AppError::ValidateConfigError(e.to_string()));
+        }
+
         // Check that target_url is not empty (it's required)
         if self.target_url.is_empty() {
             return Err(AppError::ValidateConfigError(
@@ -239,34 +1130,20 @@ impl AppConfig {
         Ok(())
     }
 
-    /// Validate URL format (must be http:// or https://)
+    /// Validate URL format (must be http://, https:// or unix://)
     fn validate_url(&self) -> Result<(), AppError> {
-        match Url::parse(&self.target_url) {
-            Ok(url) => {
-                if url.scheme() == "http" || url.scheme() == "https" {
-                    if url.host_str().is_some() {
-                        Ok(())
-                    } else {
-                        Err(AppError::ValidateURLConfig(format!(
-                            "Invalid target URL: '{}' - URL must have a valid host.\n\
-                             Example: http://localhost:8080",
-                            self.target_url
-                        )))
-                    }
-                } else {
-                    Err(AppError::ValidateURLConfig(format!(
-                        "Invalid target URL: '{}' - URL must start with http:// or https://.\n\
-                         Example: http://localhost:8080",
-                        self.target_url
-                    )))
-                }
-            }
-            Err(_) => Err(AppError::ValidateURLConfig(format!(
-                "Invalid target URL format: '{}'.\n\
-                 URL must be valid and start with http:// or https://.\n\
-                 Example: http://localhost:8080",
-                self.target_url
+        self.validate_target(&self.target_url)
+    }
+
+    /// Validate a target: a URL, or `pool:<name>` naming a configured pool
+    fn validate_target(&self, target: &str) -> Result<(), AppError> {
+        match target.strip_prefix(POOL_PREFIX) {
+            Some(name) if self.pools.contains_key(name) => Ok(()),
+            Some(name) => Err(AppError::ValidateURLConfig(format!(
+                "Unknown pool in target '{}' - define it in a [pools.{}] section.",
+                target, name
             ))),
+            None => validate_target_url(target),
         }
     }
 
@@ -281,6 +1158,28 @@ impl AppConfig {
         Ok(())
     }
 
+    /// Optional sections that change the default behaviour, in the order of the TOML file
+    pub fn active_sections(&self) -> Vec<&'static str> {
+        let network = self.network.profile.is_some() || !self.network.profiles.is_empty();
+        let cassette = self.cassette.record.is_some() || self.cassette.replay.is_some();
+        [
+            ("tls", self.tls.is_some()),
+            ("forward", self.mode == ProxyMode::Forward),
+            ("routes", !self.routes.is_empty()),
+            ("hosts", !self.hosts.is_empty()),
+            ("pools", !self.pools.is_empty()),
+            ("faults", !self.faults.is_empty()),
+            ("network", network),
+            ("mocks", !self.mocks.is_empty()),
+            ("cassette", cassette),
+            ("rewrites", !self.rewrites.is_empty()),
+            ("grpc", self.grpc.descriptor_set.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, active)| active.then_some(name))
+        .collect()
+    }
+
     pub fn print_config_used(&self) {
         let cargo_content = fs::read_to_string("./Cargo.toml")
             .map_err(|_| AppError::CargoTomlError);
@@ -291,18 +1190,22 @@ impl AppConfig {
                         .and_then(|p| p.get("version"))
                         .and_then(|v| v.as_str())
                         .unwrap_or("unknown");
+                    let sections = self.active_sections();
+                    let sections = if sections.is_empty() { "none".to_string() } else { sections.join(", ") };
                     println!("HTTP Logger start configuration: \r
                         Semantic Version: {} \r
                         Proxy Port: {} \r
                         Target URL: {} \r
+                        Active sections: {} \r
                         Ready", 
-                        version, self.proxy_port, self.target_url
+                        version, self.proxy_port, self.target_url, sections
                     );
                     info!("HTTP Logger start configuration: : \
                         Semantic Version: {} \
                         Proxy Port: {} \
-                        Target URL: {}", 
-                        version, self.proxy_port, self.target_url
+                        Target URL: {} \
+                        Active sections: {}", 
+                        version, self.proxy_port, self.target_url, sections
                     );
                 } else {
                     info!("Failed to parse Cargo.toml");
@@ -315,15 +1218,181 @@ impl AppConfig {
     }
 }
 
+/// Validate an upstream URL (http:// or https:// with a host, or unix:// with a socket path)
+/// Shared by `target_url` and every other configured target
+fn validate_target_url(target_url: &str) -> Result<(), AppError> {
+    match Url::parse(target_url) {
+        Ok(url) => match url.scheme() {
+            "http" | "https" if url.host_str().is_some() => Ok(()),
+            "http" | "https" => Err(AppError::ValidateURLConfig(format!(
+                "Invalid target URL: '{}' - URL must have a valid host.\n\
+                 Example: http://localhost:8080",
+                target_url
+            ))),
+            "unix" if cfg!(not(unix)) => Err(AppError::ValidateURLConfig(format!(
+                "Invalid target URL: '{}' - unix:// targets are only supported on Unix platforms",
+                target_url
+            ))),
+            "unix" if url.host_str().unwrap_or_default().is_empty() && url.path().len() > 1 => Ok(()),
+            "unix" => Err(AppError::ValidateURLConfig(format!(
+                "Invalid target URL: '{}' - unix:// URLs must name an absolute socket path.\n\
+                 Example: unix:///run/app.sock or unix:///run/app.sock:/api",
+                target_url
+            ))),
+            _ => Err(AppError::ValidateURLConfig(format!(
+                "Invalid target URL: '{}' - URL must start with http://, https:// or unix://.\n\
+                 Example: http://localhost:8080",
+                target_url
+            ))),
+        },
+        Err(_) => Err(AppError::ValidateURLConfig(format!(
+            "Invalid target URL format: '{}'.\n\
+             URL must be valid and start with http://, https:// or unix://.\n\
+             Example: http://localhost:8080",
+            target_url
+        ))),
+    }
+}
+
+/// Validate a `[[faults]]` rule, it must match paths and inject at least one failure
+fn validate_fault(fault: &FaultConfig) -> Result<(), AppError> {
+    let label = fault.name.as_deref().unwrap_or(&fault.path);
+    validate_request_match("fault", label, fault.method.as_deref(), &fault.path)?;
+    if !(0.0..=1.0).contains(&fault.probability) {
+        return Err(AppError::ValidateConfigError(format!(
+            "Invalid probability {} in fault '{}' - use a value between 0.0 and 1.0.",
+            fault.probability, label
+        )));
+    }
+    if let Some(status) = fault.status
+        && !(200..=599).contains(&status)
+    {
+        // A 1xx status can't end an exchange, hyper would leave the client waiting for the final response
+        return Err(AppError::ValidateConfigError(format!(
+            "Invalid status {} in fault '{}' - use a final status between 200 and 599.",
+            status, label
+        )));
+    }
+    if fault.latency_ms.is_none() && fault.status.is_none() && !fault.drop_connection && fault.truncate_body.is_none() {
+        return Err(AppError::ValidateConfigError(format!(
+            "Fault '{}' injects nothing - set latency_ms, status, drop_connection or truncate_body.",
+            label
+        )));
+    }
+    Ok(())
+}
+
+/// Validate a `[[mocks]]` rule and its canned response
+fn validate_mock(mock: &MockConfig) -> Result<(), AppError> {
+    let label = mock.name.as_deref().unwrap_or(&mock.path);
+    validate_request_match("mock", label, mock.method.as_deref(), &mock.path)?;
+    if axum::http::StatusCode::from_u16(mock.status).is_err() {
+        return Err(AppError::ValidateConfigError(format!("Invalid status {} in mock '{}'.", mock.status, label)));
+    }
+    if mock.body.is_some() && mock.body_file.is_some() {
+        return Err(AppError::ValidateConfigError(format!(
+            "Mock '{}' sets both body and body_file - keep only one.",
+            label
+        )));
+    }
+    for name in mock.headers.keys() {
+        if axum::http::HeaderName::from_bytes(name.as_bytes()).is_err() {
+            return Err(AppError::ValidateConfigError(format!("Invalid header '{}' in mock '{}'.", name, label)));
+        }
+    }
+    Ok(())
+}
+
+/// Validate a `[[rewrites]]` rule, each change must make sense for its phase
+fn validate_rewrite(rewrite: &RewriteConfig) -> Result<(), AppError> {
+    let label = rewrite.name.as_deref().unwrap_or(&rewrite.path);
+    let invalid = |reason: String| AppError::ValidateConfigError(format!("Invalid rewrite '{}': {}.", label, reason));
+    validate_request_match("rewrite", label, rewrite.method.as_deref(), &rewrite.path)?;
+    let headers = rewrite.set_headers.iter().chain(&rewrite.add_headers);
+    for (name, value) in headers {
+        if axum::http::HeaderName::from_bytes(name.as_bytes()).is_err()
+            || axum::http::HeaderValue::from_str(value).is_err()
+        {
+            return Err(invalid(format!("header '{}: {}' is not valid", name, value)));
+        }
+    }
+    for name in &rewrite.remove_headers {
+        if axum::http::HeaderName::from_bytes(name.as_bytes()).is_err() {
+            return Err(invalid(format!("header '{}' is not valid", name)));
+        }
+    }
+    match (rewrite.phase, &rewrite.rewrite_path, rewrite.status) {
+        (RewritePhase::Response, Some(_), _) => return Err(invalid("rewrite_path only applies to requests".into())),
+        (RewritePhase::Request, _, Some(_)) => return Err(invalid("status only applies to responses".into())),
+        (_, Some(rewrite_path), _) => {
+            regex::Regex::new(&rewrite_path.pattern).map_err(|e| invalid(e.to_string()))?;
+        }
+        (_, _, Some(status)) if axum::http::StatusCode::from_u16(status).is_err() => {
+            return Err(invalid(format!("status {} is out of range", status)));
+        }
+        _ => {}
+    }
+    for patch in &rewrite.json_patch {
+        if !(patch.path.is_empty() || patch.path.starts_with('/')) {
+            return Err(invalid(format!("'{}' is not a JSON Pointer, start it with '/'", patch.path)));
+        }
+        if patch.op != JsonPatchKind::Remove && patch.value.is_none() {
+            return Err(invalid(format!("json_patch on '{}' needs a value", patch.path)));
+        }
+    }
+    let changes_nothing = rewrite.set_headers.is_empty()
+        && rewrite.add_headers.is_empty()
+        && rewrite.remove_headers.is_empty()
+        && rewrite.rewrite_path.is_none()
+        && rewrite.status.is_none()
+        && rewrite.json_patch.is_empty();
+    if changes_nothing {
+        return Err(invalid("it changes nothing".into()));
+    }
+    Ok(())
+}
+
+/// Validate the method and path glob shared by `[[faults]]`, `[[mocks]]` and `[[rewrites]]` rules
+fn validate_request_match(kind: &str, label: &str, method: Option<&str>, path: &str) -> Result<(), AppError> {
+    if !path.starts_with('/') {
+        return Err(AppError::ValidateConfigError(format!(
+            "Invalid {} path: '{}' - path globs must start with '/'.",
+            kind, path
+        )));
+    }
+    if let Some(method) = method
+        && axum::http::Method::from_bytes(method.as_bytes()).is_err()
+    {
+        return Err(AppError::ValidateConfigError(format!("Invalid method '{}' in {} '{}'.", method, kind, label)));
+    }
+    Ok(())
+}
+
 impl Default for AppConfig {
     /// Provide sensible defaults for all fields
     /// This allows the app to run with minimal configuration
     fn default() -> Self {
         Self {
+            mode: ProxyMode::Reverse,
             target_url: String::new(), // Will be required from env/cli/toml
+            shadow_target_url: None,
             proxy_port: 3000,
             database_path: "./endpoint-logs.db".to_string(),
             verbose: false,
+            preserve_host: false,
+            logging: LoggingConfig::default(),
+            upstream: UpstreamConfig::default(),
+            tls: None,
+            forward: ForwardConfig::default(),
+            routes: Vec::new(),
+            hosts: BTreeMap::new(),
+            pools: BTreeMap::new(),
+            faults: Vec::new(),
+            network: NetworkConfig::default(),
+            mocks: Vec::new(),
+            cassette: CassetteConfig::default(),
+            rewrites: Vec::new(),
+            grpc: GrpcConfig::default(),
         }
     }
 }
@@ -340,6 +1409,7 @@ mod tests {
             proxy_port: 3000,
             database_path: "./test.db".to_string(),
             verbose: false,
+            ..AppConfig::default()
         };
         assert!(config.validate_port().is_ok());
     }
@@ -353,6 +1423,7 @@ mod tests {
             proxy_port: 0,
             database_path: "./test.db".to_string(),
             verbose: false,
+            ..AppConfig::default()
         };
         assert!(config.validate_port().is_err());
     }
@@ -364,6 +1435,7 @@ mod tests {
             proxy_port: 3000,
             database_path: "./test.db".to_string(),
             verbose: false,
+            ..AppConfig::default()
         };
         assert!(config.validate_url().is_ok());
     }
@@ -375,6 +1447,7 @@ mod tests {
             proxy_port: 3000,
             database_path: "./test.db".to_string(),
             verbose: false,
+            ..AppConfig::default()
         };
         assert!(config.validate_url().is_ok());
     }
@@ -386,6 +1459,7 @@ mod tests {
             proxy_port: 3000,
             database_path: "./test.db".to_string(),
             verbose: false,
+            ..AppConfig::default()
         };
         assert!(config.validate_url().is_err());
     }
@@ -397,10 +1471,246 @@ mod tests {
             proxy_port: 3000,
             database_path: "./test.db".to_string(),
             verbose: false,
+            ..AppConfig::default()
         };
         assert!(config.validate_url().is_err());
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn test_validate_url_unix_socket() {
+        for (target_url, valid) in [
+            ("unix:///run/app.sock", true),
+            ("unix:///var/run/docker.sock:/v1.43", true),
+            ("unix://run/app.sock", false),
+            ("unix:///", false),
+        ] {
+            let config = AppConfig { target_url: target_url.to_string(), ..AppConfig::default() };
+            assert_eq!(config.validate_url().is_ok(), valid, "{}", target_url);
+        }
+    }
+
+    #[test]
+    #[cfg(not(unix))]
+    fn test_validate_url_unix_socket_unsupported() {
+        let config = AppConfig { target_url: "unix:///run/app.sock".to_string(), ..AppConfig::default() };
+        assert!(config.validate_url().is_err());
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles_up_to_max() {
+        let retry = RetryConfig { attempts: 6, backoff_ms: 100, max_backoff_ms: 500 };
+
+        let waits: Vec<u64> = (2..=6).map(|attempt| retry.backoff(attempt).as_millis() as u64).collect();
+
+        assert_eq!(waits, vec![100, 200, 400, 500, 500]);
+    }
+
+    #[test]
+    fn test_validate_forward_mode_without_target() {
+        let config = AppConfig {
+            mode: ProxyMode::Forward,
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_routes_without_target() {
+        let route = RouteConfig {
+            name: None,
+            prefix: "/api".to_string(),
+            target: "http://localhost:8081".to_string(),
+            strip_prefix: false,
+            network_profile: None,
+            shadow_target: None,
+        };
+        let config = AppConfig {
+            routes: vec![route.clone()],
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let config = AppConfig {
+            routes: vec![RouteConfig { prefix: "api".to_string(), ..route.clone() }],
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = AppConfig {
+            routes: vec![RouteConfig { target: "localhost:8081".to_string(), ..route.clone() }],
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = AppConfig {
+            routes: vec![RouteConfig { shadow_target: Some("pool:missing".to_string()), ..route }],
+            shadow_target_url: Some("http://localhost:9090".to_string()),
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_hosts() {
+        let mut config = AppConfig::default();
+        config.hosts.insert("api.localtest.me".to_string(), "http://localhost:8081".to_string());
+        assert!(config.validate().is_ok());
+
+        config.hosts.insert("web.localtest.me:3000".to_string(), "http://localhost:5173".to_string());
+        assert!(config.validate().is_err());
+
+        config.hosts.clear();
+        config.hosts.insert("web.localtest.me".to_string(), "ftp://localhost:5173".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_pool_targets() {
+        let pool = PoolConfig {
+            members: vec!["http://localhost:8081".to_string(), "http://localhost:8082".to_string()],
+            strategy: BalanceStrategy::RoundRobin,
+            health_check: None,
+        };
+        let mut config = AppConfig {
+            target_url: "pool:users".to_string(),
+            pools: BTreeMap::from([("users".to_string(), pool.clone())]),
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.target_url = "pool:orders".to_string();
+        assert!(config.validate().is_err());
+
+        config.target_url = "pool:users".to_string();
+        config.pools.insert("users".to_string(), PoolConfig { members: Vec::new(), ..pool.clone() });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_pool_health_check_durations() {
+        let check = HealthCheckConfig { path: "/health".to_string(), interval_ms: 5000, timeout_ms: 1000 };
+        let pool = PoolConfig {
+            members: vec!["http://localhost:8081".to_string()],
+            strategy: BalanceStrategy::RoundRobin,
+            health_check: Some(check.clone()),
+        };
+        let mut config = AppConfig {
+            target_url: "pool:users".to_string(),
+            pools: BTreeMap::from([("users".to_string(), pool.clone())]),
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let no_interval = HealthCheckConfig { interval_ms: 0, ..check.clone() };
+        config.pools.insert("users".to_string(), PoolConfig { health_check: Some(no_interval), ..pool.clone() });
+        assert!(config.validate().is_err());
+
+        let no_timeout = HealthCheckConfig { timeout_ms: 0, ..check };
+        config.pools.insert("users".to_string(), PoolConfig { health_check: Some(no_timeout), ..pool });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_network_profiles() {
+        let mut config = AppConfig {
+            target_url: "http://localhost:8080".to_string(),
+            network: NetworkConfig { profile: Some("3g".to_string()), ..NetworkConfig::default() },
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.network.profile = Some("office-vpn".to_string());
+        assert!(config.validate().is_err());
+
+        let vpn = NetworkProfile { latency_ms: 80, bandwidth_kbps: 4_000 };
+        config.network.profiles.insert("office-vpn".to_string(), vpn);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_mocks() {
+        let mock = MockConfig { path: "/api/users/*".to_string(), ..MockConfig::default() };
+        let config = |mock: MockConfig| AppConfig {
+            target_url: "http://localhost:8080".to_string(),
+            mocks: vec![mock],
+            ..AppConfig::default()
+        };
+
+        assert!(config(mock.clone()).validate().is_ok());
+        assert!(config(MockConfig { status: 1000, ..mock.clone() }).validate().is_err());
+        let both = MockConfig { body: Some("{}".to_string()), body_file: Some("user.json".to_string()), ..mock.clone() };
+        assert!(config(both).validate().is_err());
+        let headers = BTreeMap::from([("content type".to_string(), "application/json".to_string())]);
+        assert!(config(MockConfig { headers, ..mock }).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rewrites() {
+        let rewrite = RewriteConfig { remove_headers: vec!["cookie".to_string()], ..RewriteConfig::default() };
+        let config = |rewrite: RewriteConfig| AppConfig {
+            target_url: "http://localhost:8080".to_string(),
+            rewrites: vec![rewrite],
+            ..AppConfig::default()
+        };
+        let response = RewriteConfig { phase: RewritePhase::Response, ..rewrite.clone() };
+        let path = PathRewrite { pattern: "^/v1/(".to_string(), replacement: "/v2/$1".to_string() };
+        let patch = JsonPatchOp { op: JsonPatchKind::Add, path: "/user".to_string(), value: None };
+
+        assert!(config(rewrite.clone()).validate().is_ok());
+        assert!(config(RewriteConfig { status: Some(200), ..response.clone() }).validate().is_ok());
+        assert!(config(RewriteConfig { status: Some(200), ..rewrite.clone() }).validate().is_err());
+        assert!(config(RewriteConfig { rewrite_path: Some(path.clone()), ..response }).validate().is_err());
+        assert!(config(RewriteConfig { rewrite_path: Some(path), ..rewrite.clone() }).validate().is_err());
+        assert!(config(RewriteConfig { json_patch: vec![patch], ..rewrite.clone() }).validate().is_err());
+        assert!(config(RewriteConfig { remove_headers: Vec::new(), ..rewrite }).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_cassette() {
+        let replay = CassetteConfig { replay: Some("users.jsonl".to_string()), ..CassetteConfig::default() };
+        let config = |cassette: CassetteConfig| AppConfig { cassette, ..AppConfig::default() };
+
+        // Strict replay never reaches the target, so it needs none
+        assert!(config(replay.clone()).validate().is_ok());
+        let passthrough = CassetteConfig { on_miss: CassetteMiss::Passthrough, ..replay.clone() };
+        assert!(config(passthrough).validate().is_err());
+        let both = CassetteConfig { record: Some("users.jsonl".to_string()), ..replay.clone() };
+        assert!(config(both).validate().is_err());
+        assert!(config(CassetteConfig { match_on: Vec::new(), ..replay }).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_ca_bundle_with_insecure_skip_verify() {
+        let config = |ca_bundle: Option<&str>| {
+            let mut config = AppConfig { target_url: "https://localhost:8443".to_string(), ..AppConfig::default() };
+            config.upstream.tls.ca_bundle = ca_bundle.map(str::to_string);
+            config.upstream.tls.insecure_skip_verify = true;
+            config
+        };
+
+        assert!(config(None).validate().is_ok());
+        assert!(config(Some("./internal-ca.pem")).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_faults() {
+        let fault = FaultConfig { path: "/api/**".to_string(), status: Some(503), ..FaultConfig::default() };
+        let config = |fault: FaultConfig| AppConfig {
+            target_url: "http://localhost:8080".to_string(),
+            faults: vec![fault],
+            ..AppConfig::default()
+        };
+
+        assert!(config(fault.clone()).validate().is_ok());
+        assert!(config(FaultConfig { path: "api/**".to_string(), ..fault.clone() }).validate().is_err());
+        assert!(config(FaultConfig { probability: 1.5, ..fault.clone() }).validate().is_err());
+        assert!(config(FaultConfig { status: Some(42), ..fault.clone() }).validate().is_err());
+        assert!(config(FaultConfig { status: Some(103), ..fault.clone() }).validate().is_err());
+        assert!(config(FaultConfig { method: Some("G ET".to_string()), ..fault.clone() }).validate().is_err());
+        assert!(config(FaultConfig { status: None, ..fault }).validate().is_err());
+    }
+
     #[test]
     fn test_from_env_with_valid_env() {
         // Set environment variables
@@ -495,7 +1805,11 @@ mod tests {
         assert_eq!(config.target_url, "");
         assert_eq!(config.proxy_port, 3000);
         assert_eq!(config.database_path, "./endpoint-logs.db");
-        assert_eq!(config.verbose, false);
+        assert!(!config.verbose);
+        assert!(!config.preserve_host);
+        assert_eq!(config.logging.max_body_size_kb, 100);
+        assert_eq!(config.upstream.http_version, UpstreamHttpVersion::Auto);
+        assert!(config.tls.is_none());
     }
 
     #[test]
@@ -522,6 +1836,167 @@ database_path = "./toml-test.db"
         fs::remove_file(test_file).ok();
     }
 
+    #[test]
+    fn test_load_proxy_settings_from_toml() {
+        let toml_content = r#"
+mode = "forward"
+target_url = "http://toml-config:9000"
+shadow_target_url = "http://toml-config:9001"
+preserve_host = true
+
+[logging]
+max_body_size_kb = 16
+
+[upstream]
+http_version = "http2"
+request_timeout_ms = 30000
+
+[upstream.retry]
+attempts = 4
+
+[upstream.tls]
+ca_bundle = "./internal-ca.pem"
+server_name = "api.internal"
+
+[tls]
+
+[forward]
+intercept_tls = true
+
+[hosts]
+"api.localtest.me" = "http://localhost:8081"
+
+[pools.users]
+members = ["http://localhost:8081", "http://localhost:8082"]
+strategy = "least_connections"
+
+[pools.users.health_check]
+path = "/health"
+
+[[routes]]
+prefix = "/api"
+target = "http://localhost:8081"
+strip_prefix = true
+network_profile = "3g"
+shadow_target = "pool:users"
+
+[[routes]]
+name = "frontend"
+prefix = "/"
+target = "http://localhost:5173"
+
+[network]
+profile = "slow-wifi"
+
+[network.profiles.office-vpn]
+latency_ms = 80
+bandwidth_kbps = 4000
+
+[[mocks]]
+method = "POST"
+path = "/api/users"
+status = 201
+headers = { content-type = "application/json" }
+body = '{"name": "{{body.name}}"}'
+
+[[faults]]
+method = "GET"
+path = "/api/users/*"
+probability = 0.25
+latency_ms = 1500
+
+[[rewrites]]
+name = "api-v2"
+path = "/v1/**"
+set_headers = { x-debug = "1" }
+remove_headers = ["cookie"]
+rewrite_path = { pattern = "^/v1/(.*)$", replacement = "/v2/$1" }
+
+[[rewrites]]
+phase = "response"
+path = "/api/users/*"
+status = 200
+json_patch = [
+    { op = "replace", path = "/user/role", value = "admin" },
+    { op = "remove", path = "/user/token" },
+]
+
+[cassette]
+replay = "./fixtures/users.jsonl"
+match_on = ["method", "path", "body"]
+on_miss = "passthrough"
+
+[grpc]
+descriptor_set = "./protos.binpb"
+"#;
+
+        let test_file = "test-proxy-settings.toml";
+        fs::write(test_file, toml_content).expect("Failed to write test file");
+
+        let toml_config = AppConfig::load_from_toml(test_file)
+            .expect("Should load TOML config");
+        let config = AppConfig::default().merge_toml(toml_config);
+
+        assert_eq!(config.mode, ProxyMode::Forward);
+        assert!(config.preserve_host);
+        assert_eq!(config.shadow_target_url.as_deref(), Some("http://toml-config:9001"));
+        assert_eq!(config.logging.max_body_size_kb, 16);
+        assert_eq!(config.upstream.http_version, UpstreamHttpVersion::Http2);
+        assert_eq!(config.upstream.request_timeout_ms, 30000);
+        assert_eq!(config.upstream.connect_timeout_ms, 10_000);
+        assert_eq!(config.upstream.keep_alive_ms, 90_000);
+        assert_eq!(config.upstream.retry.attempts, 4);
+        assert_eq!(config.upstream.retry.backoff_ms, 100);
+        assert_eq!(config.upstream.tls.ca_bundle.as_deref(), Some("./internal-ca.pem"));
+        assert_eq!(config.upstream.tls.server_name.as_deref(), Some("api.internal"));
+        assert!(!config.upstream.tls.insecure_skip_verify);
+        let tls = config.tls.expect("Empty [tls] section should enable TLS");
+        assert!(tls.cert_path.is_none());
+        assert_eq!(tls.cert_dir, "./endpoint-logger-certs");
+        assert!(config.forward.intercept_tls);
+        assert_eq!(config.forward.cert_dir, "./endpoint-logger-certs");
+        assert_eq!(config.routes.len(), 2);
+        assert!(config.routes[0].strip_prefix);
+        assert_eq!(config.routes[0].name, None);
+        assert_eq!(config.routes[0].network_profile.as_deref(), Some("3g"));
+        assert_eq!(config.routes[0].shadow_target.as_deref(), Some("pool:users"));
+        assert_eq!(config.routes[1].name.as_deref(), Some("frontend"));
+        assert!(!config.routes[1].strip_prefix);
+        assert_eq!(config.network.profile.as_deref(), Some("slow-wifi"));
+        assert_eq!(config.network.all_profiles()["office-vpn"].bandwidth_kbps, 4000);
+        assert_eq!(config.network.all_profiles().len(), 4);
+        assert_eq!(config.mocks.len(), 1);
+        assert_eq!(config.mocks[0].status, 201);
+        assert_eq!(config.mocks[0].headers["content-type"], "application/json");
+        assert_eq!(config.mocks[0].body.as_deref(), Some("{\"name\": \"{{body.name}}\"}"));
+        assert_eq!(config.faults.len(), 1);
+        assert_eq!(config.faults[0].method.as_deref(), Some("GET"));
+        assert_eq!(config.faults[0].probability, 0.25);
+        assert_eq!(config.faults[0].latency_ms, Some(1500));
+        assert!(!config.faults[0].drop_connection);
+        assert_eq!(config.rewrites.len(), 2);
+        assert_eq!(config.rewrites[0].phase, RewritePhase::Request);
+        assert_eq!(config.rewrites[0].set_headers["x-debug"], "1");
+        assert_eq!(config.rewrites[0].rewrite_path.as_ref().unwrap().replacement, "/v2/$1");
+        assert_eq!(config.rewrites[1].phase, RewritePhase::Response);
+        assert_eq!(config.rewrites[1].json_patch[0].value, Some(serde_json::Value::from("admin")));
+        assert_eq!(config.rewrites[1].json_patch[1].op, JsonPatchKind::Remove);
+        assert_eq!(config.cassette.replay.as_deref(), Some("./fixtures/users.jsonl"));
+        assert_eq!(config.cassette.match_on, [CassetteMatch::Method, CassetteMatch::Path, CassetteMatch::Body]);
+        assert_eq!(config.cassette.on_miss, CassetteMiss::Passthrough);
+        assert_eq!(config.grpc.descriptor_set.as_deref(), Some("./protos.binpb"));
+        assert_eq!(config.hosts["api.localtest.me"], "http://localhost:8081");
+        let pool = &config.pools["users"];
+        assert_eq!(pool.members.len(), 2);
+        assert_eq!(pool.strategy, BalanceStrategy::LeastConnections);
+        let health_check = pool.health_check.as_ref().expect("Health check should be parsed");
+        assert_eq!(health_check.path, "/health");
+        assert_eq!(health_check.interval_ms, 5000);
+
+        // Clean up
+        fs::remove_file(test_file).ok();
+    }
+
     #[test]
     fn test_merge_priority() {
         // Create TOML file with base config
@@ -572,8 +2047,22 @@ database_path = "./toml.db"
             proxy_port: 4000,
             database_path: "./test-db.db".to_string(),
             verbose: true,
+            ..AppConfig::default()
         };
         config.print_config_used();
         // Test passes if no panic
     }
+
+    #[test]
+    fn test_active_sections() {
+        let mut config = AppConfig::default();
+        assert!(config.active_sections().is_empty());
+
+        config.mode = ProxyMode::Forward;
+        config.mocks = vec![MockConfig::default()];
+        config.cassette.replay = Some("./cassette.jsonl".to_string());
+        config.grpc.descriptor_set = Some("./protos.binpb".to_string());
+
+        assert_eq!(config.active_sections(), ["forward", "mocks", "cassette", "grpc"]);
+    }
 }
@@ -1,27 +1,116 @@
+use std::path::Path;
+use std::sync::Arc;
 use tokio::net::TcpListener;
 use tokio::task::JoinHandle;
 use tokio::signal;
 use axum::{Router, http::StatusCode, response::IntoResponse, routing::get};
 use tracing::info;
 
-mod utils;
+pub mod api;
+pub mod config;
+pub mod proxy;
+pub mod server;
+pub mod storage;
+pub mod tls;
+pub mod utils;
 
+use crate::config::{AppConfig, ProxyMode};
+use crate::proxy::breakpoints::Breakpoints;
+use crate::proxy::cassette::{Player, Recorder};
+use crate::proxy::faults::Faults;
+use crate::proxy::forwarder::{Forwarder, proxy_handler};
+use crate::proxy::grpc::Descriptors;
+use crate::proxy::interceptor::{Interceptor, Rewrites};
+use crate::proxy::mocks::Mocks;
+use crate::proxy::network::NetworkConditions;
+use crate::storage::LogStore;
+use crate::tls::{LocalCa, SiteCertificates};
 use crate::utils::errors::AppError;
 
+/// Shared state handed to every request handler
+#[derive(Clone)]
+pub struct AppState {
+    pub forwarder: Forwarder,
+    pub interceptor: Interceptor,
+    /// Breakpoints holding exchanges until they are released from the logger API
+    pub breakpoints: Arc<Breakpoints>,
+    /// `[[faults]]` rules applied to proxied requests
+    pub faults: Arc<Faults>,
+    /// `[[mocks]]` rules answering requests in place of the target
+    pub mocks: Arc<Mocks>,
+    /// Network profiles simulated on proxied requests
+    pub network: Arc<NetworkConditions>,
+    /// Cassette answering requests in place of the target, set by `--replay`
+    pub player: Option<Arc<Player>>,
+    /// Set in forward-proxy mode when CONNECT tunnels are intercepted
+    pub site_certificates: Option<Arc<SiteCertificates>>,
+}
+
 pub async fn health_check() -> impl IntoResponse {
     info!("Health check handle alive");
     StatusCode::OK
 }
 
-pub async fn run(listener: TcpListener) -> anyhow::Result<JoinHandle<()>> {
+pub async fn run(listener: TcpListener, config: AppConfig) -> anyhow::Result<JoinHandle<()>> {
+
+    let mut interceptor = Interceptor::new(LogStore::default(), config.logging.max_body_size_kb * 1024)
+        .with_rewrites(Arc::new(Rewrites::new(&config.rewrites)?));
+    let mut site_certificates = None;
+    if config.mode == ProxyMode::Forward {
+        interceptor = interceptor.with_absolute_uris();
+        if config.forward.intercept_tls {
+            let ca = LocalCa::load_or_create(Path::new(&config.forward.cert_dir))?;
+            site_certificates = Some(Arc::new(SiteCertificates::new(ca)));
+        }
+    }
+    if let Some(path) = &config.grpc.descriptor_set {
+        interceptor = interceptor.with_descriptors(Arc::new(Descriptors::load(path)?));
+    }
+    if let Some(path) = &config.cassette.record {
+        interceptor = interceptor.with_recorder(Arc::new(Recorder::create(path)?));
+    }
+    let player = match &config.cassette.replay {
+        Some(path) => Some(Arc::new(Player::load(path, &config.cassette, config.logging.max_body_size_kb * 1024)?)),
+        None => None,
+    };
+    let forwarder = Forwarder::new(&config)?;
+    forwarder.spawn_health_checks();
+    let state = AppState {
+        forwarder,
+        interceptor,
+        breakpoints: Arc::new(Breakpoints::default()),
+        faults: Arc::new(Faults::new(&config.faults)?),
+        mocks: Arc::new(Mocks::new(&config.mocks)?),
+        network: Arc::new(NetworkConditions::new(&config)),
+        player,
+        site_certificates,
+    };
 
     let app = Router::new()
-        .route("/health_check", get(health_check));
+        .route("/health_check", get(health_check))
+        .nest(api::API_PREFIX, api::router())
+        .fallback(proxy_handler)
+        .with_state(state.clone());
+    let app = match config.mode {
+        ProxyMode::Reverse => app,
+        ProxyMode::Forward => proxy::forward::router(app, state),
+    };
+
+    let acceptor = config.tls.as_ref().map(tls::acceptor).transpose()?;
 
     let handle = tokio::spawn(async move {
-        println!("Server running on http://{:?}", listener.local_addr().unwrap());
+        let result = match acceptor {
+            Some(acceptor) => {
+                println!("Server running on https://{:?}", listener.local_addr().unwrap());
+                server::serve_tls(listener, acceptor, app).await
+            }
+            None => {
+                println!("Server running on http://{:?}", listener.local_addr().unwrap());
+                server::serve(listener, app).await
+            }
+        };
 
-        if let Err(e) = axum::serve(listener, app).await {
+        if let Err(e) = result {
             println!("Failed to start server because of {}", e)
         }
     });
@@ -1,10 +1,8 @@
 use tokio::net::TcpListener;
 use endpoint_logger::{run, graceful_shutdown};
+use endpoint_logger::config::AppConfig;
+use endpoint_logger::utils::logger::init_tracing;
 use dotenvy::dotenv;
-mod config;
-mod utils;
-use crate::config::AppConfig;
-use crate::utils::logger::init_tracing;
 
 
 #[tokio::main]
@@ -28,7 +26,7 @@ async fn main() -> anyhow::Result<()> {
 
     // Bind to proxy server port
     let listener = TcpListener::bind(format!("0.0.0.0:{}", config.proxy_port)).await.expect("Failed to bind address");
-    let handle = run(listener).await?;
+    let handle = run(listener, config).await?;
     handle.await?;
     graceful_shutdown().await?;
     Ok(())
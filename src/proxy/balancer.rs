@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use axum::body::{Body, Bytes};
+use axum::http::{Request, Uri};
+use http_body::{Frame, SizeHint};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::config::{BalanceStrategy, HealthCheckConfig, POOL_PREFIX, PoolConfig};
+use crate::proxy::forwarder::{UpstreamClient, upstream_uri};
+use crate::proxy::unix::origin;
+use crate::utils::errors::AppError;
+
+/// Pools by name, as referenced by `pool:<name>` targets
+pub type Pools = BTreeMap<String, Arc<Pool>>;
+
+/// Build every configured pool
+pub fn pools(config: &BTreeMap<String, PoolConfig>) -> Result<Pools, AppError> {
+    config
+        .iter()
+        .map(|(name, pool)| Ok((name.clone(), Arc::new(Pool::new(name, pool)?))))
+        .collect()
+}
+
+/// Where a configured target points
+#[derive(Clone, Debug)]
+pub enum Upstream {
+    Url(Url),
+    Pool(Arc<Pool>),
+}
+
+impl Upstream {
+    /// Parse a target URL or `pool:<name>` reference
+    pub fn parse(target: &str, pools: &Pools) -> Result<Self, AppError> {
+        match target.strip_prefix(POOL_PREFIX) {
+            Some(name) => pools
+                .get(name)
+                .map(|pool| Upstream::Pool(pool.clone()))
+                .ok_or_else(|| AppError::ValidateURLConfig(format!("Unknown pool in target '{}'", target))),
+            None => Url::parse(target)
+                .map(Upstream::Url)
+                .map_err(|e| AppError::ValidateURLConfig(format!("Invalid target URL '{}': {}", target, e))),
+        }
+    }
+
+    /// Origin of the target URL, or the `pool:<name>` reference
+    pub fn label(&self) -> String {
+        match self {
+            Upstream::Url(url) => origin(url),
+            Upstream::Pool(pool) => format!("{}{}", POOL_PREFIX, pool.name),
+        }
+    }
+
+    /// Backend URL for the next request, with the pool member reservation if any
+    pub fn pick(&self) -> Result<(Url, Option<MemberGuard>), AppError> {
+        match self {
+            Upstream::Url(url) => Ok((url.clone(), None)),
+            Upstream::Pool(pool) => pool.pick().map(|(url, guard)| (url, Some(guard))),
+        }
+    }
+}
+
+/// A `[pools.<name>]` section with the live state of its members
+#[derive(Debug)]
+pub struct Pool {
+    name: String,
+    members: Vec<Member>,
+    strategy: BalanceStrategy,
+    health_check: Option<HealthCheckConfig>,
+    next: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct Member {
+    url: Url,
+    healthy: AtomicBool,
+    in_flight: AtomicUsize,
+}
+
+impl Pool {
+    pub fn new(name: &str, config: &PoolConfig) -> Result<Self, AppError> {
+        let members = config
+            .members
+            .iter()
+            .map(|member| {
+                let url = Url::parse(member).map_err(|e| {
+                    AppError::ValidateURLConfig(format!("Invalid member '{}' in pool '{}': {}", member, name, e))
+                })?;
+                Ok(Member { url, healthy: AtomicBool::new(true), in_flight: AtomicUsize::new(0) })
+            })
+            .collect::<Result<_, AppError>>()?;
+        Ok(Self {
+            name: name.to_string(),
+            members,
+            strategy: config.strategy,
+            health_check: config.health_check.clone(),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Pick a healthy member following the pool strategy
+    /// The member counts as busy until the returned guard is dropped
+    pub fn pick(self: &Arc<Self>) -> Result<(Url, MemberGuard), AppError> {
+        let healthy: Vec<usize> = (0..self.members.len())
+            .filter(|&i| self.members[i].healthy.load(Ordering::Relaxed))
+            .collect();
+        if healthy.is_empty() {
+            return Err(AppError::UpstreamUnreachable(format!("No healthy member in pool '{}'", self.name)));
+        }
+
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let index = match self.strategy {
+            BalanceStrategy::RoundRobin => healthy[start % healthy.len()],
+            // Ties go round-robin so idle members share the load
+            BalanceStrategy::LeastConnections => (0..healthy.len())
+                .map(|k| healthy[(start + k) % healthy.len()])
+                .min_by_key(|&i| self.members[i].in_flight.load(Ordering::Relaxed))
+                .unwrap_or(healthy[0]),
+            BalanceStrategy::Random => healthy[RandomState::new().hash_one(start) as usize % healthy.len()],
+        };
+
+        let member = &self.members[index];
+        member.in_flight.fetch_add(1, Ordering::Relaxed);
+        Ok((member.url.clone(), MemberGuard { pool: self.clone(), index }))
+    }
+
+    /// Probe members in the background, taking failing ones out of rotation until they recover
+    /// Does nothing without a `health_check` section, and stops once the pool is dropped
+    pub fn spawn_health_checks(self: &Arc<Self>, client: UpstreamClient) {
+        let Some(check) = self.health_check.clone() else {
+            return;
+        };
+        let pool = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(check.interval_ms));
+            loop {
+                interval.tick().await;
+                let Some(pool) = pool.upgrade() else {
+                    break;
+                };
+                // Probed side by side so a hung member doesn't delay the others
+                let mut probes = JoinSet::new();
+                for (index, member) in pool.members.iter().enumerate() {
+                    let (client, url, check) = (client.clone(), member.url.clone(), check.clone());
+                    probes.spawn(async move { (index, probe(&client, &url, &check).await) });
+                }
+                for (index, healthy) in probes.join_all().await {
+                    let member = &pool.members[index];
+                    if member.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+                        if healthy {
+                            info!("Pool '{}': {} is healthy again", pool.name, member.url);
+                        } else {
+                            warn!("Pool '{}': {} failed its health check, taking it out of rotation", pool.name, member.url);
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Request the health check path on a member, true on any 2xx or 3xx answer in time
+async fn probe(client: &UpstreamClient, member: &Url, check: &HealthCheckConfig) -> bool {
+    let Ok(path) = check.path.parse::<Uri>() else {
+        return false;
+    };
+    let Ok(uri) = upstream_uri(member, &path) else {
+        return false;
+    };
+    let Ok(req) = Request::get(uri).body(Body::empty()) else {
+        return false;
+    };
+    match tokio::time::timeout(Duration::from_millis(check.timeout_ms), client.request(req)).await {
+        Ok(Ok(response)) => response.status().is_success() || response.status().is_redirection(),
+        _ => false,
+    }
+}
+
+/// Reservation of a pool member for one request, released on drop
+#[derive(Debug)]
+pub struct MemberGuard {
+    pool: Arc<Pool>,
+    index: usize,
+}
+
+impl Drop for MemberGuard {
+    fn drop(&mut self) {
+        self.pool.members[self.index].in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Response body keeping its pool member reserved until the body is fully sent or dropped
+pub struct GuardedBody {
+    inner: Body,
+    _guard: MemberGuard,
+}
+
+impl GuardedBody {
+    pub fn new(inner: Body, guard: MemberGuard) -> Self {
+        Self { inner, _guard: guard }
+    }
+}
+
+impl http_body::Body for GuardedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(strategy: BalanceStrategy) -> Arc<Pool> {
+        let config = PoolConfig {
+            members: vec!["http://localhost:8081".to_string(), "http://localhost:8082".to_string()],
+            strategy,
+            health_check: None,
+        };
+        Arc::new(Pool::new("users", &config).unwrap())
+    }
+
+    fn port(url: &Url) -> u16 {
+        url.port().unwrap()
+    }
+
+    #[test]
+    fn test_round_robin_alternates_members() {
+        let pool = pool(BalanceStrategy::RoundRobin);
+
+        let ports: Vec<u16> = (0..4).map(|_| port(&pool.pick().unwrap().0)).collect();
+
+        assert_eq!(ports, vec![8081, 8082, 8081, 8082]);
+    }
+
+    #[test]
+    fn test_least_connections_prefers_idle_member() {
+        let pool = pool(BalanceStrategy::LeastConnections);
+
+        let (first, busy) = pool.pick().unwrap();
+        let picks: Vec<u16> = (0..3).map(|_| port(&pool.pick().unwrap().0)).collect();
+        drop(busy);
+
+        assert!(picks.iter().all(|&p| p != port(&first)));
+    }
+
+    #[test]
+    fn test_unhealthy_members_are_skipped() {
+        let pool = pool(BalanceStrategy::Random);
+        pool.members[0].healthy.store(false, Ordering::Relaxed);
+
+        assert!((0..10).all(|_| port(&pool.pick().unwrap().0) == 8082));
+        pool.members[1].healthy.store(false, Ordering::Relaxed);
+        assert!(pool.pick().is_err());
+    }
+}
@@ -0,0 +1,680 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use axum::body::{Body, Bytes, HttpBody, to_bytes};
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, header};
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use tracing::info;
+
+use crate::proxy::interceptor::Interceptor;
+use crate::proxy::routing::glob_matches;
+use crate::proxy::sse::is_event_stream;
+use crate::storage::models::{BreakpointHit, CapturedBody, HeldPhase, HoldOutcome, header_pairs, unix_millis};
+use crate::utils::errors::AppError;
+
+/// Time a message stays held when its breakpoint sets no timeout
+const DEFAULT_HOLD_TIMEOUT_MS: u64 = 60_000;
+
+/// Breakpoints set through the logger API, and the messages they currently hold
+#[derive(Debug, Default)]
+pub struct Breakpoints {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: u64,
+    next_held_id: u64,
+    breakpoints: Vec<Breakpoint>,
+    held: BTreeMap<u64, Held>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Breakpoint {
+    pub id: u64,
+    pub filter: String,
+    pub on: BreakOn,
+    /// Time after which a held message is let through unchanged
+    pub timeout_ms: u64,
+    #[serde(skip)]
+    expression: Filter,
+}
+
+/// Body of `POST /breakpoints`
+#[derive(Debug, Deserialize)]
+pub struct NewBreakpoint {
+    /// Conditions joined with `&&`, e.g. `method == POST && path ~ /api/**`, empty holds everything
+    #[serde(default)]
+    pub filter: String,
+    #[serde(default)]
+    pub on: BreakOn,
+    pub timeout_ms: Option<u64>,
+}
+
+/// Side of the exchange a breakpoint holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BreakOn {
+    #[default]
+    Request,
+    Response,
+    Both,
+}
+
+impl BreakOn {
+    fn includes(self, phase: HeldPhase) -> bool {
+        match self {
+            BreakOn::Request => phase == HeldPhase::Request,
+            BreakOn::Response => phase == HeldPhase::Response,
+            BreakOn::Both => true,
+        }
+    }
+}
+
+/// A request or response waiting for a decision, as shown for editing
+#[derive(Debug, Clone, Serialize)]
+pub struct HeldMessage {
+    pub id: u64,
+    pub exchange_id: u64,
+    pub breakpoint: u64,
+    pub phase: HeldPhase,
+    pub held_at_ms: u64,
+    /// Time at which the message is let through unchanged if nobody decided
+    pub expires_at_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    pub headers: Vec<(String, String)>,
+    /// Body as text, invalid UTF-8 sequences replaced, empty when not editable
+    pub body: String,
+    /// False when the body is larger than `max_body_size_kb` or streamed, only the headers are held then
+    pub body_editable: bool,
+}
+
+#[derive(Debug)]
+struct Held {
+    message: HeldMessage,
+    decide: oneshot::Sender<Verdict>,
+}
+
+/// Body of `POST /breakpoints/held/{id}`
+/// Every edit left out keeps the original value
+#[derive(Debug, Default, Deserialize)]
+pub struct Decision {
+    pub action: Action,
+    /// New method of a held request
+    #[serde(default)]
+    pub method: Option<String>,
+    /// New URL of a held request
+    #[serde(default)]
+    pub url: Option<String>,
+    /// New status of a held response, or of the answer written with `respond` (default 200)
+    #[serde(default)]
+    pub status: Option<u16>,
+    /// Replaces every header
+    #[serde(default)]
+    pub headers: Option<Vec<(String, String)>>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Let the message go on, with any edits
+    #[default]
+    Resume,
+    /// Answer the client with `status`, `headers` and `body` instead
+    Respond,
+    /// Close the client connection without an answer
+    Drop,
+}
+
+/// A decision whose edits have been parsed
+#[derive(Debug)]
+struct Verdict {
+    action: Action,
+    method: Option<Method>,
+    uri: Option<Uri>,
+    status: Option<StatusCode>,
+    headers: Option<HeaderMap>,
+    body: Option<Bytes>,
+}
+
+/// What becomes of a held request
+pub enum Release {
+    Continue(Request),
+    Respond(Response),
+    Drop,
+}
+
+impl Breakpoints {
+    pub fn add(&self, new: NewBreakpoint) -> Result<Breakpoint, AppError> {
+        let expression = Filter::parse(&new.filter)?;
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.next_id += 1;
+        let breakpoint = Breakpoint {
+            id: inner.next_id,
+            filter: new.filter,
+            on: new.on,
+            timeout_ms: new.timeout_ms.unwrap_or(DEFAULT_HOLD_TIMEOUT_MS),
+            expression,
+        };
+        inner.breakpoints.push(breakpoint.clone());
+        Ok(breakpoint)
+    }
+
+    /// Remove a breakpoint, messages it already holds stay held until decided or timed out
+    pub fn remove(&self, id: u64) -> Result<(), AppError> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let count = inner.breakpoints.len();
+        inner.breakpoints.retain(|b| b.id != id);
+        if inner.breakpoints.len() == count {
+            return Err(AppError::BreakpointNotFound(id));
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<Breakpoint> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).breakpoints.clone()
+    }
+
+    /// Messages currently held, oldest first
+    pub fn held(&self) -> Vec<HeldMessage> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.held.values().map(|held| held.message.clone()).collect()
+    }
+
+    /// Release a held message
+    pub fn decide(&self, id: u64, decision: Decision) -> Result<(), AppError> {
+        let verdict = Verdict::parse(decision)?;
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let held = inner.held.get(&id).ok_or(AppError::HeldMessageNotFound(id))?;
+        if verdict.action == Action::Resume && verdict.body.is_some() && !held.message.body_editable {
+            return Err(AppError::BreakpointError(format!("Body of held message {} is not editable", id)));
+        }
+        let held = inner.held.remove(&id).ok_or(AppError::HeldMessageNotFound(id))?;
+        drop(inner);
+        // The exchange may have been abandoned by its client in the meantime
+        held.decide.send(verdict).map_err(|_| AppError::HeldMessageNotFound(id))
+    }
+
+    /// Hold `req` if a breakpoint matches it, until someone decides or the breakpoint times out
+    /// The exchange is updated with any edit so it shows the request as it was sent on
+    pub async fn hold_request(
+        &self,
+        interceptor: &Interceptor,
+        exchange_id: u64,
+        req: Request,
+    ) -> Result<Release, AppError> {
+        let subject = Subject { method: req.method(), uri: req.uri(), status: None, headers: req.headers() };
+        let Some(breakpoint) = self.find(HeldPhase::Request, &subject) else {
+            return Ok(Release::Continue(req));
+        };
+        let (mut parts, body) = req.into_parts();
+        let body = HeldBody::read(body, interceptor.body_limit())
+            .await
+            .map_err(|e| AppError::UpstreamRequestError(format!("Failed to read request body: {}", e)))?;
+        let message = HeldMessage {
+            method: Some(parts.method.to_string()),
+            url: Some(parts.uri.to_string()),
+            ..HeldMessage::new(exchange_id, HeldPhase::Request, &parts.headers, &body)
+        };
+
+        let verdict = self.hold(&breakpoint, message).await;
+        let outcome = HoldOutcome::of(verdict.as_ref());
+        record_hit(interceptor, exchange_id, &breakpoint, HeldPhase::Request, outcome);
+        let Some(verdict) = verdict else {
+            return Ok(Release::Continue(Request::from_parts(parts, body.into_body())));
+        };
+        match verdict.action {
+            Action::Drop => Ok(Release::Drop),
+            Action::Respond => Ok(Release::Respond(verdict.response())),
+            Action::Resume => {
+                if let Some(method) = verdict.method {
+                    parts.method = method;
+                }
+                if let Some(uri) = verdict.uri {
+                    parts.uri = uri;
+                }
+                if let Some(headers) = verdict.headers {
+                    parts.headers = headers;
+                }
+                let body = match verdict.body {
+                    Some(edited) => {
+                        parts.headers.remove(header::CONTENT_LENGTH);
+                        let captured = CapturedBody::buffered(&edited, interceptor.body_limit());
+                        interceptor.store().update(exchange_id, |e| e.request_body = captured);
+                        Body::from(edited)
+                    }
+                    None => body.into_body(),
+                };
+                interceptor.store().update(exchange_id, |e| {
+                    e.method = parts.method.to_string();
+                    e.uri = parts.uri.to_string();
+                    e.request_headers = header_pairs(&parts.headers);
+                });
+                Ok(Release::Continue(Request::from_parts(parts, body)))
+            }
+        }
+    }
+
+    /// Hold the response to `method` `uri` if a breakpoint matches it
+    /// None when the connection is to be dropped, upgrades and event streams are never held
+    pub async fn hold_response(
+        &self,
+        interceptor: &Interceptor,
+        exchange_id: u64,
+        (method, uri): (&Method, &Uri),
+        response: Response,
+    ) -> Result<Option<Response>, AppError> {
+        if response.status() == StatusCode::SWITCHING_PROTOCOLS || is_event_stream(response.headers()) {
+            return Ok(Some(response));
+        }
+        let subject = Subject { method, uri, status: Some(response.status()), headers: response.headers() };
+        let Some(breakpoint) = self.find(HeldPhase::Response, &subject) else {
+            return Ok(Some(response));
+        };
+        let (mut parts, body) = response.into_parts();
+        let body = HeldBody::read(body, interceptor.body_limit())
+            .await
+            .map_err(|e| AppError::UpstreamUnreachable(format!("Failed to read upstream body: {}", e)))?;
+        let message = HeldMessage {
+            status: Some(parts.status.as_u16()),
+            ..HeldMessage::new(exchange_id, HeldPhase::Response, &parts.headers, &body)
+        };
+
+        let verdict = self.hold(&breakpoint, message).await;
+        let outcome = HoldOutcome::of(verdict.as_ref());
+        record_hit(interceptor, exchange_id, &breakpoint, HeldPhase::Response, outcome);
+        let Some(verdict) = verdict else {
+            return Ok(Some(Response::from_parts(parts, body.into_body())));
+        };
+        match verdict.action {
+            Action::Drop => Ok(None),
+            Action::Respond => Ok(Some(verdict.response())),
+            Action::Resume => {
+                if let Some(status) = verdict.status {
+                    parts.status = status;
+                }
+                if let Some(headers) = verdict.headers {
+                    parts.headers = headers;
+                }
+                let body = match verdict.body {
+                    Some(edited) => {
+                        parts.headers.remove(header::CONTENT_LENGTH);
+                        Body::from(edited)
+                    }
+                    None => body.into_body(),
+                };
+                Ok(Some(Response::from_parts(parts, body)))
+            }
+        }
+    }
+
+    /// First breakpoint holding `subject` in `phase`
+    fn find(&self, phase: HeldPhase, subject: &Subject) -> Option<Breakpoint> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner
+            .breakpoints
+            .iter()
+            .find(|b| b.on.includes(phase) && b.expression.matches(subject))
+            .cloned()
+    }
+
+    /// Wait for a decision on `message`, None once the breakpoint timed out
+    async fn hold(&self, breakpoint: &Breakpoint, mut message: HeldMessage) -> Option<Verdict> {
+        let (decide, decision) = oneshot::channel();
+        let id = {
+            let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+            inner.next_held_id += 1;
+            message.id = inner.next_held_id;
+            message.breakpoint = breakpoint.id;
+            message.expires_at_ms = message.held_at_ms.saturating_add(breakpoint.timeout_ms);
+            inner.held.insert(message.id, Held { message, decide });
+            inner.next_held_id
+        };
+        info!("Holding message {} at breakpoint {}", id, breakpoint.id);
+        // Also releases the message when the client gives up and this future is dropped
+        let _release = ReleaseOnDrop { breakpoints: self, id };
+        tokio::time::timeout(Duration::from_millis(breakpoint.timeout_ms), decision)
+            .await
+            .ok()
+            .and_then(Result::ok)
+    }
+}
+
+struct ReleaseOnDrop<'a> {
+    breakpoints: &'a Breakpoints,
+    id: u64,
+}
+
+impl Drop for ReleaseOnDrop<'_> {
+    fn drop(&mut self) {
+        let mut inner = self.breakpoints.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.held.remove(&self.id);
+    }
+}
+
+/// Body of a held message, only read when it is small enough to be edited
+enum HeldBody {
+    Buffered(Bytes),
+    /// Larger than the capture limit or of unknown length, passed on untouched
+    Streaming(Body),
+}
+
+impl HeldBody {
+    async fn read(body: Body, limit: usize) -> Result<Self, axum::Error> {
+        if body.size_hint().upper().is_none_or(|size| size > limit as u64) {
+            return Ok(HeldBody::Streaming(body));
+        }
+        Ok(HeldBody::Buffered(to_bytes(body, limit).await?))
+    }
+
+    fn into_body(self) -> Body {
+        match self {
+            HeldBody::Buffered(data) => Body::from(data),
+            HeldBody::Streaming(body) => body,
+        }
+    }
+}
+
+impl HeldMessage {
+    fn new(exchange_id: u64, phase: HeldPhase, headers: &HeaderMap, body: &HeldBody) -> Self {
+        Self {
+            id: 0,
+            exchange_id,
+            breakpoint: 0,
+            phase,
+            held_at_ms: unix_millis(),
+            expires_at_ms: 0,
+            method: None,
+            url: None,
+            status: None,
+            headers: header_pairs(headers),
+            body: match body {
+                HeldBody::Buffered(data) => String::from_utf8_lossy(data).into_owned(),
+                HeldBody::Streaming(_) => String::new(),
+            },
+            body_editable: matches!(body, HeldBody::Buffered(_)),
+        }
+    }
+}
+
+impl Verdict {
+    fn parse(decision: Decision) -> Result<Self, AppError> {
+        let invalid = |e: String| AppError::BreakpointError(e);
+        let method = decision
+            .method
+            .map(|m| Method::from_bytes(m.to_ascii_uppercase().as_bytes()))
+            .transpose()
+            .map_err(|e| invalid(format!("Invalid method: {}", e)))?;
+        let uri = decision
+            .url
+            .map(|url| url.parse::<Uri>())
+            .transpose()
+            .map_err(|e| invalid(format!("Invalid url: {}", e)))?;
+        let status = decision
+            .status
+            .map(StatusCode::from_u16)
+            .transpose()
+            .map_err(|e| invalid(format!("Invalid status: {}", e)))?;
+        let headers = decision
+            .headers
+            .map(|headers| {
+                headers
+                    .iter()
+                    .map(|(name, value)| {
+                        let name = HeaderName::from_bytes(name.as_bytes())
+                            .map_err(|e| invalid(format!("Invalid header '{}': {}", name, e)))?;
+                        let value = HeaderValue::from_str(value)
+                            .map_err(|e| invalid(format!("Invalid value of header '{}': {}", name, e)))?;
+                        Ok((name, value))
+                    })
+                    .collect::<Result<HeaderMap, AppError>>()
+            })
+            .transpose()?;
+        Ok(Self { action: decision.action, method, uri, status, headers, body: decision.body.map(Bytes::from) })
+    }
+
+    fn is_edit(&self) -> bool {
+        self.method.is_some()
+            || self.uri.is_some()
+            || self.status.is_some()
+            || self.headers.is_some()
+            || self.body.is_some()
+    }
+
+    /// Answer written at the breakpoint
+    fn response(self) -> Response {
+        let mut response = Response::new(Body::from(self.body.unwrap_or_default()));
+        *response.status_mut() = self.status.unwrap_or(StatusCode::OK);
+        if let Some(headers) = self.headers {
+            *response.headers_mut() = headers;
+            response.headers_mut().remove(header::CONTENT_LENGTH);
+        }
+        response
+    }
+}
+
+impl HoldOutcome {
+    fn of(verdict: Option<&Verdict>) -> Self {
+        match verdict {
+            None => HoldOutcome::TimedOut,
+            Some(verdict) => match verdict.action {
+                Action::Drop => HoldOutcome::Dropped,
+                Action::Respond => HoldOutcome::Answered,
+                Action::Resume if verdict.is_edit() => HoldOutcome::Edited,
+                Action::Resume => HoldOutcome::Resumed,
+            },
+        }
+    }
+}
+
+fn record_hit(
+    interceptor: &Interceptor,
+    exchange_id: u64,
+    breakpoint: &Breakpoint,
+    phase: HeldPhase,
+    outcome: HoldOutcome,
+) {
+    let hit = BreakpointHit { breakpoint: breakpoint.id, phase, outcome };
+    interceptor.store().update(exchange_id, |e| e.breakpoints.push(hit));
+}
+
+/// Fields of a message a filter can look at
+struct Subject<'a> {
+    method: &'a Method,
+    uri: &'a Uri,
+    /// Set for responses only
+    status: Option<StatusCode>,
+    /// Headers of the message itself
+    headers: &'a HeaderMap,
+}
+
+/// Conditions joined with `&&`, all of which must hold
+/// A condition is `<field> <op> <value>`, fields being `method`, `path`, `url`, `status` and `header.<name>`
+/// `~` is a glob for `path`, a prefix for `status` (`status ~ 5`) and a substring test otherwise,
+/// `<`, `<=`, `>` and `>=` compare statuses
+#[derive(Debug, Clone, Default)]
+struct Filter {
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    field: Field,
+    op: Op,
+    value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Method,
+    Path,
+    Url,
+    Status,
+    Header(HeaderName),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Matches,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Two-character operators first, so `>=` is not read as `>`
+const OPERATORS: [(&str, Op); 7] = [
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("~", Op::Matches),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+];
+
+impl Filter {
+    fn parse(expression: &str) -> Result<Self, AppError> {
+        let expression = expression.trim();
+        if expression.is_empty() {
+            return Ok(Self::default());
+        }
+        let conditions = expression.split("&&").map(Condition::parse).collect::<Result<_, _>>()?;
+        Ok(Self { conditions })
+    }
+
+    fn matches(&self, subject: &Subject) -> bool {
+        self.conditions.iter().all(|condition| condition.matches(subject))
+    }
+}
+
+impl Condition {
+    fn parse(text: &str) -> Result<Self, AppError> {
+        let invalid =
+            |reason: &str| AppError::BreakpointError(format!("Invalid condition '{}': {}", text.trim(), reason));
+        let (index, token, op) = OPERATORS
+            .iter()
+            .filter_map(|(token, op)| text.find(token).map(|index| (index, *token, *op)))
+            .min_by_key(|(index, token, _)| (*index, std::cmp::Reverse(token.len())))
+            .ok_or_else(|| invalid("expected one of ==, !=, ~, <, <=, >, >="))?;
+        let field = match text[..index].trim().to_ascii_lowercase().as_str() {
+            "method" => Field::Method,
+            "path" => Field::Path,
+            "url" => Field::Url,
+            "status" => Field::Status,
+            field => match field.strip_prefix("header.") {
+                Some(name) => {
+                    Field::Header(HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid("bad header name"))?)
+                }
+                None => return Err(invalid("fields are method, path, url, status and header.<name>")),
+            },
+        };
+        let value = text[index + token.len()..].trim().trim_matches('"').to_string();
+        if matches!(op, Op::Lt | Op::Le | Op::Gt | Op::Ge) && field != Field::Status {
+            return Err(invalid("only status can be compared with <, <=, > and >="));
+        }
+        if field == Field::Status && op != Op::Matches && value.parse::<u16>().is_err() {
+            return Err(invalid("status must be a number"));
+        }
+        Ok(Self { field, op, value })
+    }
+
+    fn matches(&self, subject: &Subject) -> bool {
+        let actual = match &self.field {
+            Field::Status => {
+                let Some(status) = subject.status else {
+                    return false;
+                };
+                let expected = self.value.parse::<u16>().unwrap_or_default();
+                return match self.op {
+                    Op::Eq => status.as_u16() == expected,
+                    Op::Ne => status.as_u16() != expected,
+                    Op::Matches => status.as_str().starts_with(&self.value),
+                    Op::Lt => status.as_u16() < expected,
+                    Op::Le => status.as_u16() <= expected,
+                    Op::Gt => status.as_u16() > expected,
+                    Op::Ge => status.as_u16() >= expected,
+                };
+            }
+            Field::Method => Some(subject.method.to_string()),
+            Field::Path => Some(subject.uri.path().to_string()),
+            Field::Url => Some(subject.uri.to_string()),
+            Field::Header(name) => subject.headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string),
+        };
+        let equals = |actual: &str| match self.field {
+            Field::Method => actual.eq_ignore_ascii_case(&self.value),
+            _ => actual == self.value,
+        };
+        match (self.op, actual) {
+            (Op::Ne, actual) => !actual.is_some_and(|actual| equals(&actual)),
+            (_, None) => false,
+            (Op::Eq, Some(actual)) => equals(&actual),
+            (Op::Matches, Some(actual)) if self.field == Field::Path => glob_matches(&self.value, &actual),
+            (Op::Matches, Some(actual)) => actual.contains(&self.value),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(filter: &str, method: Method, uri: &str, status: Option<u16>) -> bool {
+        let headers = HeaderMap::from_iter([(HeaderName::from_static("x-debug"), HeaderValue::from_static("on"))]);
+        let uri = uri.parse().unwrap();
+        let status = status.map(|s| StatusCode::from_u16(s).unwrap());
+        let subject = Subject { method: &method, uri: &uri, status, headers: &headers };
+        Filter::parse(filter).unwrap().matches(&subject)
+    }
+
+    #[test]
+    fn test_filter_conditions() {
+        assert!(matches("", Method::GET, "/", None));
+        assert!(matches("method == post && path ~ /api/**", Method::POST, "/api/users/1", None));
+        assert!(!matches("method == post && path ~ /api/**", Method::GET, "/api/users/1", None));
+        assert!(matches("url ~ page=2", Method::GET, "/users?page=2", None));
+        assert!(matches("header.x-debug == on && header.x-other != on", Method::GET, "/", None));
+        assert!(!matches("header.x-other == on", Method::GET, "/", None));
+        assert!(matches("status >= 500", Method::GET, "/", Some(503)));
+        assert!(matches("status ~ 4", Method::GET, "/", Some(404)));
+        assert!(!matches("status >= 500", Method::GET, "/", None));
+    }
+
+    #[tokio::test]
+    async fn test_huge_timeout_saturates_expiry() {
+        let breakpoints = Breakpoints::default();
+        let new = NewBreakpoint { filter: String::new(), on: BreakOn::Request, timeout_ms: Some(u64::MAX) };
+        let breakpoint = breakpoints.add(new).unwrap();
+        let message = HeldMessage::new(1, HeldPhase::Request, &HeaderMap::new(), &HeldBody::Buffered(Bytes::new()));
+
+        let holding = breakpoints.hold(&breakpoint, message);
+        tokio::pin!(holding);
+        tokio::select! {
+            _ = &mut holding => panic!("Message should stay held"),
+            _ = tokio::time::sleep(Duration::from_millis(20)) => {}
+        }
+
+        assert_eq!(breakpoints.held()[0].expires_at_ms, u64::MAX);
+    }
+
+    #[test]
+    fn test_filter_rejects_bad_conditions() {
+        assert!(Filter::parse("method").is_err());
+        assert!(Filter::parse("verb == GET").is_err());
+        assert!(Filter::parse("path > /api").is_err());
+        assert!(Filter::parse("status == teapot").is_err());
+    }
+}
@@ -0,0 +1,474 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use aws_lc_rs::digest::{SHA256, digest};
+use axum::body::{Body, Bytes};
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue, StatusCode, Uri, header};
+use axum::response::Response;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use http_body::{Body as _, Frame, SizeHint};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::config::{CassetteConfig, CassetteMatch, CassetteMiss};
+use crate::proxy::encoding::capture;
+use crate::storage::models::{CapturedBody, CapturedExchange, HoldOutcome};
+use crate::utils::errors::AppError;
+
+/// Interactions waiting to be written before further ones are dropped
+const MAX_PENDING_LINES: usize = 1024;
+
+/// A recorded request and the response it got, stored as one JSON line of a cassette file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+    #[serde(flatten)]
+    pub body: RecordedBody,
+    /// Hex SHA-256 of the body, compared when matching on `body`
+    pub body_sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    #[serde(flatten)]
+    pub body: RecordedBody,
+}
+
+/// Body kept as text when it is valid UTF-8, base64 encoded otherwise
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedBody {
+    Body(String),
+    BodyBase64(String),
+}
+
+impl RecordedBody {
+    fn new(data: &[u8]) -> Self {
+        match std::str::from_utf8(data) {
+            Ok(text) => Self::Body(text.to_string()),
+            Err(_) => Self::BodyBase64(STANDARD.encode(data)),
+        }
+    }
+
+    fn bytes(&self) -> Result<Bytes, base64::DecodeError> {
+        match self {
+            Self::Body(text) => Ok(Bytes::from(text.clone())),
+            Self::BodyBase64(encoded) => STANDARD.decode(encoded).map(Bytes::from),
+        }
+    }
+}
+
+impl Interaction {
+    /// Interaction for a finished exchange, None when its bodies were not captured in full
+    pub fn from_exchange(exchange: &CapturedExchange) -> Option<Self> {
+        let status = exchange.status?;
+        let whole = |body: &CapturedBody| body.complete && !body.truncated;
+        if !whole(&exchange.request_body) || !whole(&exchange.response_body) {
+            return None;
+        }
+        // The captured body is stored decoded, so it is played back without its coding
+        let mut response_headers = exchange.response_headers.clone();
+        if exchange.response_body.encoding.is_some() {
+            response_headers.retain(|(name, _)| !name.eq_ignore_ascii_case("content-encoding"));
+        }
+        Some(Self {
+            request: RecordedRequest {
+                method: exchange.method.clone(),
+                uri: exchange.uri.clone(),
+                headers: exchange.request_headers.clone(),
+                body: RecordedBody::new(&exchange.request_body.data),
+                body_sha256: sha256(&exchange.request_body.data),
+            },
+            response: RecordedResponse {
+                status,
+                headers: response_headers,
+                body: RecordedBody::new(&exchange.response_body.data),
+            },
+        })
+    }
+}
+
+/// Appends every forwarded exchange to the cassette given to `--record`
+/// Lines are written by a background task, so recording never blocks the exchange that finished
+/// Interactions are dropped with a warning while the task is `MAX_PENDING_LINES` behind
+#[derive(Debug)]
+pub struct Recorder {
+    lines: mpsc::Sender<String>,
+    dropped: AtomicU64,
+}
+
+impl Recorder {
+    /// Start a new cassette at `path`, replacing any previous recording
+    pub fn create(path: &str) -> Result<Self, AppError> {
+        let file = File::create(path)
+            .map_err(|e| AppError::CassetteError(format!("Failed to create cassette '{}': {}", path, e)))?;
+        info!("Recording exchanges to cassette {}", path);
+        let (lines, mut received) = mpsc::channel::<String>(MAX_PENDING_LINES);
+        let mut file = tokio::fs::File::from_std(file);
+        let path = path.to_string();
+        tokio::spawn(async move {
+            while let Some(line) = received.recv().await {
+                // Flushed line by line so the cassette is usable while still recording
+                if let Err(e) = async { file.write_all(line.as_bytes()).await?; file.flush().await }.await {
+                    warn!("Failed to write to cassette {}: {}", path, e);
+                }
+            }
+        });
+        Ok(Self { lines, dropped: AtomicU64::new(0) })
+    }
+
+    pub fn record(&self, exchange: &CapturedExchange) {
+        // The tunnel of an upgraded connection can't be played back
+        if exchange.status == Some(StatusCode::SWITCHING_PROTOCOLS.as_u16()) {
+            return;
+        }
+        // A response written at a breakpoint is not what the target sent
+        if exchange.breakpoints.iter().any(|hit| hit.outcome == HoldOutcome::Answered) {
+            return;
+        }
+        let Some(interaction) = Interaction::from_exchange(exchange) else {
+            warn!(
+                "Not recording {} {} to cassette, its bodies were not captured in full (see max_body_size_kb)",
+                exchange.method, exchange.uri
+            );
+            return;
+        };
+        let mut line = serde_json::to_string(&interaction).expect("Interactions always serialize");
+        line.push('\n');
+        // A closed channel only happens once the runtime shuts down
+        if let Err(mpsc::error::TrySendError::Full(_)) = self.lines.try_send(line) {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(
+                "Cassette writer is behind, {} {} not recorded ({} interactions dropped so far)",
+                exchange.method, exchange.uri, dropped
+            );
+        }
+    }
+}
+
+/// Answers requests from the cassette given to `--replay`
+#[derive(Debug)]
+pub struct Player {
+    interactions: Vec<Recorded>,
+    match_on: Vec<CassetteMatch>,
+    on_miss: CassetteMiss,
+    /// Largest request body read to be compared, matching the capture limit bodies were recorded with
+    body_limit: usize,
+}
+
+#[derive(Debug)]
+struct Recorded {
+    key: RequestKey,
+    response: RecordedResponse,
+    /// Already served, later matches prefer interactions not played yet
+    played: AtomicBool,
+}
+
+/// Parts of a request compared when replaying
+#[derive(Debug, PartialEq, Eq)]
+struct RequestKey {
+    method: String,
+    path: String,
+    /// Sorted, so parameter order does not matter
+    query: Vec<(String, String)>,
+    /// None for a body too large to have been recorded, which matches nothing
+    body_sha256: Option<String>,
+}
+
+/// Outcome of looking a request up in the cassette
+pub enum Replay {
+    /// Recorded response to send back
+    Hit(Response),
+    /// No interaction matches and misses pass through, the request is handed back to be forwarded
+    Miss(Request),
+}
+
+impl Player {
+    /// Load every interaction of the cassette at `path`
+    /// Request bodies larger than `body_limit` bytes are never compared and so never match
+    pub fn load(path: &str, config: &CassetteConfig, body_limit: usize) -> Result<Self, AppError> {
+        let invalid = |e: String| AppError::CassetteError(format!("Failed to read cassette '{}': {}", path, e));
+        let file = File::open(path).map_err(|e| invalid(e.to_string()))?;
+        let mut interactions = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| invalid(e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let interaction: Interaction =
+                serde_json::from_str(&line).map_err(|e| invalid(format!("line {}: {}", index + 1, e)))?;
+            let uri = interaction
+                .request
+                .uri
+                .parse::<Uri>()
+                .map_err(|e| invalid(format!("line {}: {}", index + 1, e)))?;
+            interactions.push(Recorded {
+                key: RequestKey::new(&interaction.request.method, &uri, Some(interaction.request.body_sha256)),
+                response: interaction.response,
+                played: AtomicBool::new(false),
+            });
+        }
+        info!("Replaying {} interactions from cassette {}", interactions.len(), path);
+        Ok(Self { interactions, match_on: config.match_on.clone(), on_miss: config.on_miss, body_limit })
+    }
+
+    /// Recorded response for `req`
+    /// Interactions are played in recording order, the last match is served again once all were played
+    pub async fn replay(&self, req: Request) -> Result<Replay, AppError> {
+        let (parts, body) = req.into_parts();
+        let (body, body_sha256) = if self.match_on.contains(&CassetteMatch::Body) {
+            let read = read_within(body, self.body_limit)
+                .await
+                .map_err(|e| AppError::UpstreamRequestError(format!("Failed to read request body: {}", e)))?;
+            match read {
+                ReadBody::Whole(body) => {
+                    // Recorded hashes are of decoded bodies
+                    let decoded = capture(&body, &parts.headers, self.body_limit);
+                    let hash = (!decoded.truncated).then(|| sha256(&decoded.data));
+                    (Body::from(body), hash)
+                }
+                ReadBody::TooLarge(body) => (body, None),
+            }
+        } else {
+            (body, None)
+        };
+        let key = RequestKey::new(parts.method.as_str(), &parts.uri, body_sha256);
+
+        let mut matching = self.interactions.iter().filter(|recorded| self.matches(&recorded.key, &key)).peekable();
+        let mut chosen = None;
+        while let Some(recorded) = matching.next() {
+            if !recorded.played.swap(true, Ordering::Relaxed) || matching.peek().is_none() {
+                chosen = Some(recorded);
+                break;
+            }
+        }
+        match (chosen, self.on_miss) {
+            (Some(recorded), _) => Ok(Replay::Hit(response(&recorded.response)?)),
+            (None, CassetteMiss::Passthrough) => Ok(Replay::Miss(Request::from_parts(parts, body))),
+            (None, CassetteMiss::Strict) => {
+                Err(AppError::NoRecordedInteraction(format!("{} {}", parts.method, parts.uri)))
+            }
+        }
+    }
+
+    fn matches(&self, recorded: &RequestKey, key: &RequestKey) -> bool {
+        self.match_on.iter().all(|part| match part {
+            CassetteMatch::Method => recorded.method == key.method,
+            CassetteMatch::Path => recorded.path == key.path,
+            CassetteMatch::Query => recorded.query == key.query,
+            CassetteMatch::Body => key.body_sha256.is_some() && recorded.body_sha256 == key.body_sha256,
+        })
+    }
+}
+
+impl RequestKey {
+    fn new(method: &str, uri: &Uri, body_sha256: Option<String>) -> Self {
+        let mut query: Vec<_> = url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+            .into_owned()
+            .collect();
+        query.sort();
+        Self {
+            method: method.to_ascii_uppercase(),
+            path: uri.path().to_string(),
+            query,
+            body_sha256,
+        }
+    }
+}
+
+/// Rebuild a recorded response, letting the server frame the body again
+fn response(recorded: &RecordedResponse) -> Result<Response, AppError> {
+    let body = recorded
+        .body
+        .bytes()
+        .map_err(|e| AppError::CassetteError(format!("Invalid base64 response body: {}", e)))?;
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = StatusCode::from_u16(recorded.status)
+        .map_err(|e| AppError::CassetteError(format!("Invalid recorded status: {}", e)))?;
+    for (name, value) in &recorded.headers {
+        let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) else {
+            continue;
+        };
+        if name != header::CONTENT_LENGTH && name != header::TRANSFER_ENCODING {
+            response.headers_mut().append(name, value);
+        }
+    }
+    Ok(response)
+}
+
+/// Request body read to be hashed
+enum ReadBody {
+    Whole(Bytes),
+    /// Longer than the limit, handed back with the bytes already read put in front
+    TooLarge(Body),
+}
+
+/// Read `body` as long as it stays within `limit` bytes
+async fn read_within(mut body: Body, limit: usize) -> Result<ReadBody, axum::Error> {
+    if body.size_hint().lower() > limit as u64 {
+        return Ok(ReadBody::TooLarge(body));
+    }
+    let mut data = Vec::new();
+    while let Some(frame) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+        if let Ok(chunk) = frame?.into_data() {
+            data.extend_from_slice(&chunk);
+            if data.len() > limit {
+                return Ok(ReadBody::TooLarge(Body::new(Resumed { read: Some(Bytes::from(data)), rest: body })));
+            }
+        }
+    }
+    Ok(ReadBody::Whole(Bytes::from(data)))
+}
+
+/// Body whose start was already read
+struct Resumed {
+    read: Option<Bytes>,
+    rest: Body,
+}
+
+impl http_body::Body for Resumed {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        if let Some(read) = self.read.take() {
+            return Poll::Ready(Some(Ok(Frame::data(read))));
+        }
+        Pin::new(&mut self.rest).poll_frame(cx)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let read = self.read.as_ref().map_or(0, |read| read.len() as u64);
+        let rest = self.rest.size_hint();
+        let mut hint = SizeHint::new();
+        hint.set_lower(rest.lower() + read);
+        if let Some(upper) = rest.upper() {
+            hint.set_upper(upper + read);
+        }
+        hint
+    }
+}
+
+/// Lowercase hex SHA-256 of `data`
+fn sha256(data: &[u8]) -> String {
+    digest(&SHA256, data).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use axum::http::Method;
+
+    fn player(match_on: Vec<CassetteMatch>, interactions: &[(&str, &str, &str, &str)]) -> Player {
+        let interactions = interactions
+            .iter()
+            .map(|(method, uri, body, response)| Recorded {
+                key: RequestKey::new(method, &uri.parse().unwrap(), Some(sha256(body.as_bytes()))),
+                response: RecordedResponse {
+                    status: 200,
+                    headers: vec![("content-length".to_string(), "99".to_string())],
+                    body: RecordedBody::Body(response.to_string()),
+                },
+                played: AtomicBool::new(false),
+            })
+            .collect();
+        Player { interactions, match_on, on_miss: CassetteMiss::Strict, body_limit: 16 }
+    }
+
+    async fn replay(player: &Player, method: Method, uri: &str, body: &str) -> Option<String> {
+        let req = Request::builder().method(method).uri(uri).body(Body::from(body.to_string())).unwrap();
+        match player.replay(req).await {
+            Ok(Replay::Hit(response)) => {
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                Some(String::from_utf8(body.to_vec()).unwrap())
+            }
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_in_recording_order_then_repeat_last() {
+        let player = player(
+            vec![CassetteMatch::Method, CassetteMatch::Path, CassetteMatch::Query],
+            &[("GET", "/users?page=1&size=10", "", "first"), ("GET", "/users?size=10&page=1", "", "second")],
+        );
+
+        assert_eq!(replay(&player, Method::GET, "/users?size=10&page=1", "").await.as_deref(), Some("first"));
+        assert_eq!(replay(&player, Method::GET, "/users?page=1&size=10", "").await.as_deref(), Some("second"));
+        assert_eq!(replay(&player, Method::GET, "/users?page=1&size=10", "").await.as_deref(), Some("second"));
+        assert!(replay(&player, Method::GET, "/users?page=2&size=10", "").await.is_none());
+        assert!(replay(&player, Method::DELETE, "/users?page=1&size=10", "").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_replay_matches_only_selected_parts() {
+        let player = player(
+            vec![CassetteMatch::Path, CassetteMatch::Body],
+            &[("POST", "/search?q=a", "{\"q\":1}", "one"), ("POST", "/search", "{\"q\":2}", "two")],
+        );
+
+        assert_eq!(replay(&player, Method::PUT, "/search", "{\"q\":2}").await.as_deref(), Some("two"));
+        assert_eq!(replay(&player, Method::POST, "/search", "{\"q\":1}").await.as_deref(), Some("one"));
+        assert!(replay(&player, Method::POST, "/search", "{\"q\":3}").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_body_over_limit_is_a_miss() {
+        let long = "{\"q\":\"longer than the limit\"}";
+        let player = player(vec![CassetteMatch::Path, CassetteMatch::Body], &[("POST", "/search", long, "long")]);
+
+        assert!(replay(&player, Method::POST, "/search", long).await.is_none());
+    }
+
+    #[test]
+    fn test_interaction_round_trips_binary_bodies() {
+        let mut exchange = CapturedExchange::new("POST", "/upload", &Default::default());
+        exchange.status = Some(201);
+        exchange.request_body = CapturedBody::buffered(&[0xff, 0x00], 1024);
+        exchange.response_body = CapturedBody::buffered(b"ok", 1024);
+
+        let line = serde_json::to_string(&Interaction::from_exchange(&exchange).unwrap()).unwrap();
+        let interaction: Interaction = serde_json::from_str(&line).unwrap();
+
+        assert!(line.contains(r#""body_base64":"/wA=""#), "{}", line);
+        assert_eq!(interaction.request.body.bytes().unwrap().as_ref(), [0xff, 0x00]);
+        assert_eq!(interaction.response.body, RecordedBody::Body("ok".to_string()));
+
+        exchange.response_body.truncated = true;
+        assert!(Interaction::from_exchange(&exchange).is_none());
+    }
+
+    #[test]
+    fn test_recorder_drops_lines_while_the_writer_is_behind() {
+        let (lines, mut received) = mpsc::channel(1);
+        let recorder = Recorder { lines, dropped: AtomicU64::new(0) };
+        let mut exchange = CapturedExchange::new("GET", "/users", &Default::default());
+        exchange.status = Some(200);
+        exchange.request_body = CapturedBody::buffered(b"", 1024);
+        exchange.response_body = CapturedBody::buffered(b"[]", 1024);
+
+        recorder.record(&exchange);
+        recorder.record(&exchange);
+
+        assert_eq!(recorder.dropped.load(Ordering::Relaxed), 1);
+        assert!(received.try_recv().unwrap().contains(r#""uri":"/users""#));
+        assert!(received.try_recv().is_err());
+    }
+}
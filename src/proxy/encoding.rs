@@ -0,0 +1,231 @@
+use std::io::{self, Write};
+use axum::http::{HeaderMap, header};
+use flate2::write::{GzDecoder, ZlibDecoder};
+
+use crate::storage::models::CapturedBody;
+
+/// Buffer size of the brotli decoder
+const BROTLI_BUFFER: usize = 4096;
+
+/// Handed every chunk of a body as it streams through
+pub type Observer = Box<dyn FnMut(&[u8]) + Send + Sync>;
+
+/// Decodes the `Content-Encoding` of a body for its captured copy
+/// The bytes relayed by the proxy are never touched, only what gets stored
+pub struct Decoder {
+    /// Coding as named in the header, e.g. "gzip"
+    encoding: String,
+    inner: Inner,
+    error: Option<String>,
+}
+
+enum Inner {
+    Gzip(GzDecoder<Sink>),
+    /// HTTP "deflate" is a zlib stream
+    Deflate(ZlibDecoder<Sink>),
+    Brotli(Box<brotli::DecompressorWriter<Sink>>),
+    Zstd(zstd::stream::write::Decoder<'static, Sink>),
+}
+
+/// Keeps the first `limit` decoded bytes and counts the rest
+struct Sink {
+    data: Vec<u8>,
+    limit: usize,
+    size: u64,
+    /// Sees every decoded byte, past the limit too
+    observer: Option<Observer>,
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(observer) = self.observer.as_mut() {
+            observer(buf);
+        }
+        let room = self.limit.saturating_sub(self.data.len());
+        self.data.extend_from_slice(&buf[..buf.len().min(room)]);
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Decoder {
+    /// Decoder for a body sent with `headers`, None unless it carries a single supported coding
+    pub fn new(headers: &HeaderMap, limit: usize) -> Option<Self> {
+        let encoding = headers.get(header::CONTENT_ENCODING)?.to_str().ok()?.trim().to_ascii_lowercase();
+        let sink = Sink { data: Vec::new(), limit, size: 0, observer: None };
+        let inner = match encoding.as_str() {
+            "gzip" | "x-gzip" => Inner::Gzip(GzDecoder::new(sink)),
+            "deflate" => Inner::Deflate(ZlibDecoder::new(sink)),
+            "br" => Inner::Brotli(Box::new(brotli::DecompressorWriter::new(sink, BROTLI_BUFFER))),
+            "zstd" => Inner::Zstd(zstd::stream::write::Decoder::new(sink).ok()?),
+            _ => return None,
+        };
+        Some(Self { encoding, inner, error: None })
+    }
+
+    /// Hand the decoded body to `observer` as it is decoded
+    pub fn observe(&mut self, observer: Observer) {
+        let sink = match &mut self.inner {
+            Inner::Gzip(decoder) => decoder.get_mut(),
+            Inner::Deflate(decoder) => decoder.get_mut(),
+            Inner::Brotli(decoder) => decoder.get_mut(),
+            Inner::Zstd(decoder) => decoder.get_mut(),
+        };
+        sink.observer = Some(observer);
+    }
+
+    /// Decode the next chunk of the encoded body, nothing more is decoded after a failure
+    pub fn write(&mut self, data: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = match &mut self.inner {
+            Inner::Gzip(decoder) => decoder.write_all(data),
+            Inner::Deflate(decoder) => decoder.write_all(data),
+            Inner::Brotli(decoder) => decoder.write_all(data),
+            Inner::Zstd(decoder) => decoder.write_all(data),
+        };
+        if let Err(e) = result {
+            self.error = Some(e.to_string());
+        }
+    }
+
+    /// Replace the raw bytes of `captured` with the decoded ones and record both sizes
+    /// If decoding failed the raw bytes stay, along with the reason
+    /// `complete` is false when the stream was cut short, what was decoded so far is kept then
+    pub fn finish(mut self, complete: bool, captured: &mut CapturedBody) {
+        let ended = |result: io::Result<()>| match result {
+            Ok(()) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        let (sink, error) = match self.inner {
+            Inner::Gzip(mut decoder) => {
+                let error = if complete { ended(decoder.try_finish()) } else { None };
+                (std::mem::replace(decoder.get_mut(), Sink::empty()), error)
+            }
+            Inner::Deflate(mut decoder) => {
+                let error = if complete { ended(decoder.try_finish()) } else { None };
+                (std::mem::replace(decoder.get_mut(), Sink::empty()), error)
+            }
+            Inner::Brotli(decoder) => match decoder.into_inner() {
+                Ok(sink) => (sink, None),
+                Err(sink) if !complete => (sink, None),
+                Err(sink) => (sink, Some("unexpected end of brotli stream".to_string())),
+            },
+            Inner::Zstd(mut decoder) => {
+                let error = ended(decoder.flush());
+                (decoder.into_inner(), error)
+            }
+        };
+        if let Some(error) = self.error.take().or(error) {
+            captured.decode_error = Some(format!("Failed to decode {} body: {}", self.encoding, error));
+            return;
+        }
+        captured.truncated = sink.size > sink.limit as u64;
+        captured.data = sink.data;
+        captured.decoded_size = Some(sink.size);
+        captured.encoding = Some(self.encoding);
+    }
+}
+
+impl Sink {
+    fn empty() -> Self {
+        Self { data: Vec::new(), limit: 0, size: 0, observer: None }
+    }
+}
+
+/// Capture of a body that was read in full, decoded as `headers` describe it
+pub fn capture(data: &[u8], headers: &HeaderMap, limit: usize) -> CapturedBody {
+    let mut captured = CapturedBody::buffered(data, limit);
+    if let Some(mut decoder) = Decoder::new(headers, limit) {
+        decoder.write(data);
+        decoder.finish(true, &mut captured);
+    }
+    captured
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use flate2::Compression;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+
+    const JSON: &[u8] = br#"{"users": [{"name": "Ada"}, {"name": "Grace"}]}"#;
+
+    fn encoded(encoding: &str) -> Vec<u8> {
+        match encoding {
+            "gzip" => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(JSON).unwrap();
+                encoder.finish().unwrap()
+            }
+            "deflate" => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(JSON).unwrap();
+                encoder.finish().unwrap()
+            }
+            "br" => {
+                let mut encoded = Vec::new();
+                let mut encoder = brotli::CompressorWriter::new(&mut encoded, 4096, 5, 22);
+                encoder.write_all(JSON).unwrap();
+                drop(encoder);
+                encoded
+            }
+            "zstd" => zstd::encode_all(JSON, 3).unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn headers(encoding: &str) -> HeaderMap {
+        HeaderMap::from_iter([(header::CONTENT_ENCODING, HeaderValue::from_str(encoding).unwrap())])
+    }
+
+    #[test]
+    fn test_every_coding_is_decoded_in_chunks() {
+        for encoding in ["gzip", "deflate", "br", "zstd"] {
+            let body = encoded(encoding);
+            let mut captured = CapturedBody::buffered(&body, 1024);
+            let mut decoder = Decoder::new(&headers(encoding), 1024).unwrap();
+            for chunk in body.chunks(7) {
+                decoder.write(chunk);
+            }
+            decoder.finish(true, &mut captured);
+
+            assert_eq!(captured.data, JSON, "{}", encoding);
+            assert_eq!(captured.size, body.len() as u64, "{}", encoding);
+            assert_eq!(captured.decoded_size, Some(JSON.len() as u64), "{}", encoding);
+            assert_eq!(captured.encoding.as_deref(), Some(encoding));
+            assert!(captured.decode_error.is_none(), "{}", encoding);
+        }
+    }
+
+    #[test]
+    fn test_decoded_copy_is_truncated_at_limit() {
+        let captured = capture(&encoded("gzip"), &headers("gzip"), 10);
+
+        assert_eq!(captured.data, &JSON[..10]);
+        assert!(captured.truncated);
+        assert_eq!(captured.decoded_size, Some(JSON.len() as u64));
+    }
+
+    #[test]
+    fn test_undecodable_body_keeps_raw_bytes() {
+        let captured = capture(b"not gzip at all", &headers("gzip"), 1024);
+
+        assert_eq!(captured.data, b"not gzip at all");
+        assert!(captured.encoding.is_none());
+        assert!(captured.decode_error.unwrap().starts_with("Failed to decode gzip body"));
+    }
+
+    #[test]
+    fn test_unknown_codings_are_left_alone() {
+        assert!(Decoder::new(&headers("compress"), 1024).is_none());
+        assert!(Decoder::new(&headers("gzip, br"), 1024).is_none());
+        assert!(Decoder::new(&HeaderMap::new(), 1024).is_none());
+    }
+}
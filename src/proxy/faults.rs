@@ -0,0 +1,161 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll, ready};
+use axum::body::{Body, Bytes};
+use axum::http::Method;
+use http_body::Frame;
+
+use crate::config::FaultConfig;
+use crate::proxy::routing::glob_matches;
+use crate::storage::models::InjectedFault;
+use crate::utils::errors::AppError;
+
+/// `[[faults]]` rules, the first one matching a request decides its fate
+#[derive(Debug, Default)]
+pub struct Faults {
+    rules: Vec<FaultRule>,
+    rolls: AtomicU64,
+}
+
+#[derive(Debug)]
+struct FaultRule {
+    method: Option<Method>,
+    path: String,
+    probability: f64,
+    fault: InjectedFault,
+}
+
+impl Faults {
+    pub fn new(configs: &[FaultConfig]) -> Result<Self, AppError> {
+        let rules = configs
+            .iter()
+            .map(|config| {
+                let method = config
+                    .method
+                    .as_deref()
+                    .map(|m| Method::from_bytes(m.to_ascii_uppercase().as_bytes()))
+                    .transpose()
+                    .map_err(|e| AppError::ValidateConfigError(format!("Invalid fault method: {}", e)))?;
+                Ok(FaultRule {
+                    method,
+                    path: config.path.clone(),
+                    probability: config.probability,
+                    fault: InjectedFault {
+                        rule: config.name.clone().unwrap_or_else(|| config.path.clone()),
+                        latency_ms: config.latency_ms,
+                        status: config.status,
+                        drop_connection: config.drop_connection,
+                        truncate_body: config.truncate_body,
+                    },
+                })
+            })
+            .collect::<Result<_, AppError>>()?;
+        Ok(Self { rules, rolls: AtomicU64::new(0) })
+    }
+
+    /// Fault to inject into a request, if its rule matches and the dice say so
+    pub fn pick(&self, method: &Method, path: &str) -> Option<InjectedFault> {
+        let rule = self.rules.iter().find(|rule| {
+            rule.method.as_ref().is_none_or(|m| m == method) && glob_matches(&rule.path, path)
+        })?;
+        self.roll(rule.probability).then(|| rule.fault.clone())
+    }
+
+    fn roll(&self, probability: f64) -> bool {
+        if probability >= 1.0 {
+            return true;
+        }
+        let roll = RandomState::new().hash_one(self.rolls.fetch_add(1, Ordering::Relaxed));
+        (roll as f64 / u64::MAX as f64) < probability
+    }
+}
+
+/// Response body failing after a number of bytes, which makes the server close the connection
+pub struct FaultyBody {
+    inner: Body,
+    remaining: u64,
+    cut: bool,
+    /// Whether the server got a chance to flush the bytes passed before the cut
+    flushed: bool,
+}
+
+impl FaultyBody {
+    /// Pass the first `limit` bytes of `inner`, then fail
+    pub fn truncated(inner: Body, limit: u64) -> Self {
+        Self { inner, remaining: limit, cut: false, flushed: false }
+    }
+}
+
+impl http_body::Body for FaultyBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        if self.cut {
+            // The server only flushes its buffer once the body stops being ready,
+            // failing straight away would discard the bytes sent so far
+            if !self.flushed {
+                self.flushed = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            return Poll::Ready(Some(Err(axum::Error::new("Connection closed by injected fault"))));
+        }
+        let frame = match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            other => return Poll::Ready(other),
+        };
+        match frame.into_data() {
+            Ok(mut data) => {
+                if data.len() as u64 > self.remaining {
+                    data.truncate(self.remaining as usize);
+                    self.cut = true;
+                }
+                self.remaining -= data.len() as u64;
+                Poll::Ready(Some(Ok(Frame::data(data))))
+            }
+            Err(frame) => Poll::Ready(Some(Ok(frame))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        !self.cut && self.inner.is_end_stream()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let failing = FaultConfig {
+            method: Some("post".to_string()),
+            path: "/api/**".to_string(),
+            status: Some(500),
+            ..FaultConfig::default()
+        };
+        let slow = FaultConfig {
+            name: Some("slow".to_string()),
+            path: "/api/**".to_string(),
+            latency_ms: Some(10),
+            ..FaultConfig::default()
+        };
+        let configs = [failing, slow];
+        let faults = Faults::new(&configs).unwrap();
+
+        assert_eq!(faults.pick(&Method::POST, "/api/orders").unwrap().status, Some(500));
+        assert_eq!(faults.pick(&Method::GET, "/api/orders").unwrap().rule, "slow");
+        assert!(faults.pick(&Method::GET, "/health").is_none());
+    }
+
+    #[test]
+    fn test_probability_zero_never_fires() {
+        let config = FaultConfig { probability: 0.0, status: Some(503), ..FaultConfig::default() };
+        let faults = Faults::new(&[config]).unwrap();
+
+        assert!((0..100).all(|_| faults.pick(&Method::GET, "/").is_none()));
+    }
+}
@@ -0,0 +1,175 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use axum::Router;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::uri::Authority;
+use axum::http::{Method, StatusCode, Version};
+use axum::response::{IntoResponse, Response};
+use hyper::upgrade::OnUpgrade;
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+use tower::util::MapRequestLayer;
+use tracing::{info, warn};
+
+use crate::AppState;
+use crate::proxy::forwarder::proxy_handler;
+use crate::proxy::websocket::pipe;
+use crate::server::{Scheme, serve_connection};
+use crate::storage::LogStore;
+use crate::storage::models::Tunnel;
+use crate::utils::errors::AppError;
+
+/// Router used in forward-proxy mode
+/// CONNECT tunnels and absolute-form requests (`GET http://host/path`) go to the destination they name,
+/// origin-form requests are addressed to the proxy itself and served by `local` (e.g. the logger API)
+pub fn router(local: Router, state: AppState) -> Router {
+    Router::new()
+        .fallback(move |state: State<AppState>, client: ConnectInfo<SocketAddr>, req: Request| {
+            dispatch(local.clone(), state, client, req)
+        })
+        .with_state(state)
+}
+
+async fn dispatch(
+    local: Router,
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    req: Request,
+) -> Response {
+    if req.method() == Method::CONNECT {
+        return connect(state, client, req).await;
+    }
+    // HTTP/2 requests always carry a scheme, clients only speak forward-proxy HTTP/1.x
+    if req.version() < Version::HTTP_2 && req.uri().scheme().is_some() {
+        return proxy_handler(State(state), ConnectInfo(client), req).await;
+    }
+    local.oneshot(req).await.into_response()
+}
+
+/// Open a CONNECT tunnel to the requested `host:port`
+/// With TLS interception the tunnel is terminated here and every request inside it is captured,
+/// otherwise bytes are relayed untouched and only the tunnel itself is recorded
+async fn connect(state: AppState, client: SocketAddr, mut req: Request) -> Response {
+    let Some(authority) = req.uri().authority().cloned() else {
+        return (StatusCode::BAD_REQUEST, "CONNECT requires a host:port target").into_response();
+    };
+    let on_upgrade = hyper::upgrade::on(&mut req);
+    let (in_flight, _) = state.interceptor.begin(req).await;
+    let store = state.interceptor.store().clone();
+    let id = in_flight.id;
+
+    match &state.site_certificates {
+        Some(certificates) => {
+            let acceptor = match certificates.acceptor(host_name(&authority)).await {
+                Ok(acceptor) => acceptor,
+                Err(e) => {
+                    state.interceptor.fail(in_flight, &e.to_string());
+                    return e.into_response();
+                }
+            };
+            store.update(id, |e| e.tunnel = Some(Tunnel { intercepted: true, ..Tunnel::default() }));
+            let app = intercepted(state.clone(), authority);
+            tokio::spawn(terminate(on_upgrade, acceptor, client, app, store, id));
+        }
+        None => {
+            let upstream = match open(&authority, state.forwarder.connect_timeout()).await {
+                Ok(upstream) => upstream,
+                Err(e) => {
+                    state.interceptor.fail(in_flight, &e.to_string());
+                    return e.into_response();
+                }
+            };
+            store.update(id, |e| e.tunnel = Some(Tunnel::default()));
+            tokio::spawn(relay(on_upgrade, upstream, store, id));
+        }
+    }
+
+    state.interceptor.complete(in_flight, StatusCode::OK.into_response())
+}
+
+/// Connect to the tunnel destination, giving up after `limit`
+async fn open(authority: &Authority, limit: Option<Duration>) -> Result<TcpStream, AppError> {
+    let port = authority.port_u16().unwrap_or(443);
+    let connecting = TcpStream::connect((host_name(authority), port));
+    let connected = match limit {
+        Some(limit) => tokio::time::timeout(limit, connecting).await.map_err(|_| {
+            warn!("CONNECT to {} not established after {:?}", authority, limit);
+            AppError::UpstreamConnectTimeout(limit.as_millis() as u64)
+        })?,
+        None => connecting.await,
+    };
+    connected.map_err(|e| AppError::UpstreamUnreachable(format!("{}: {}", authority, e)))
+}
+
+/// Relay the raw tunnel bytes between the client and the destination, counting both ways
+async fn relay(on_upgrade: OnUpgrade, upstream: TcpStream, store: LogStore, id: u64) {
+    let (sent, received) = match on_upgrade.await {
+        Ok(upgraded) => {
+            let (client_read, client_write) = tokio::io::split(TokioIo::new(upgraded));
+            let (upstream_read, upstream_write) = upstream.into_split();
+            tokio::join!(
+                pipe(client_read, upstream_write, None),
+                pipe(upstream_read, client_write, None)
+            )
+        }
+        Err(e) => {
+            warn!("CONNECT upgrade failed: {}", e);
+            (0, 0)
+        }
+    };
+    info!("Tunnel closed after {} bytes sent and {} bytes received", sent, received);
+    close_tunnel(&store, id, |tunnel| {
+        tunnel.bytes_sent = sent;
+        tunnel.bytes_received = received;
+    });
+}
+
+/// Terminate TLS on the tunnel and serve the decrypted requests through the proxy
+async fn terminate(
+    on_upgrade: OnUpgrade,
+    acceptor: TlsAcceptor,
+    client: SocketAddr,
+    app: Router,
+    store: LogStore,
+    id: u64,
+) {
+    match on_upgrade.await {
+        Ok(upgraded) => match acceptor.accept(TokioIo::new(upgraded)).await {
+            Ok(tls) => serve_connection(tls, client, Scheme::Https, app).await,
+            Err(e) => warn!("TLS handshake inside CONNECT tunnel failed, is the local CA trusted? {}", e),
+        },
+        Err(e) => warn!("CONNECT upgrade failed: {}", e),
+    }
+    close_tunnel(&store, id, |_| {});
+}
+
+fn close_tunnel(store: &LogStore, id: u64, update: impl FnOnce(&mut Tunnel)) {
+    store.update(id, |e| {
+        if let Some(tunnel) = e.tunnel.as_mut() {
+            update(tunnel);
+            tunnel.closed = true;
+        }
+    });
+}
+
+/// Router for requests decrypted from a tunnel to `authority`
+/// Their origin-form URIs are made absolute so the forwarder sends them to that destination
+fn intercepted(state: AppState, authority: Authority) -> Router {
+    Router::new()
+        .fallback(proxy_handler)
+        .with_state(state)
+        .layer(MapRequestLayer::new(move |mut req: Request| {
+            let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+            if let Ok(uri) = format!("https://{}{}", authority, path).parse() {
+                *req.uri_mut() = uri;
+            }
+            req
+        }))
+}
+
+/// Host of a CONNECT target without the brackets around IPv6 addresses
+fn host_name(authority: &Authority) -> &str {
+    authority.host().trim_start_matches('[').trim_end_matches(']')
+}
@@ -0,0 +1,314 @@
+use axum::http::{HeaderMap, Method, Uri, header};
+use serde_json::Value;
+
+use crate::storage::models::{GraphqlError, GraphqlOperation, OperationType};
+
+/// How a GraphQL POST carries its operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestFormat {
+    /// `application/json` object with `query`, `operationName`, `variables` and `extensions`
+    Json,
+    /// `application/graphql`, the body is the query document
+    Document,
+}
+
+/// Format of a POST body that may hold a GraphQL operation, None for other requests
+pub fn request_format(method: &Method, headers: &HeaderMap) -> Option<RequestFormat> {
+    if method != Method::POST {
+        return None;
+    }
+    let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?.split(';').next()?.trim();
+    match content_type.to_ascii_lowercase().as_str() {
+        "application/json" => Some(RequestFormat::Json),
+        "application/graphql" => Some(RequestFormat::Document),
+        _ => None,
+    }
+}
+
+/// Operations of a GraphQL POST body, empty if the body is not one
+/// A batch (a JSON array of requests) has one operation per request, in order
+pub fn from_body(format: RequestFormat, data: &[u8]) -> Vec<GraphqlOperation> {
+    let operations = match format {
+        RequestFormat::Document => operation(std::str::from_utf8(data).ok(), None, None, None).map(|o| vec![o]),
+        RequestFormat::Json => match serde_json::from_slice::<Value>(data) {
+            // Responses are matched to operations by position, so a batch counts only if all of it is GraphQL
+            Ok(Value::Array(batch)) => batch.iter().map(json_operation).collect(),
+            Ok(request) => json_operation(&request).map(|o| vec![o]),
+            Err(_) => None,
+        },
+    };
+    operations.unwrap_or_default()
+}
+
+/// Operation of a JSON request with `query`, `operationName`, `variables` and `extensions`
+fn json_operation(request: &Value) -> Option<GraphqlOperation> {
+    operation(
+        request.get("query").and_then(Value::as_str),
+        request.get("operationName").and_then(Value::as_str),
+        request.get("variables").filter(|v| !v.is_null()).cloned(),
+        persisted_query(request.get("extensions"), request.get("documentId").and_then(Value::as_str)),
+    )
+}
+
+/// Operation of a GraphQL GET, sent in the query string with JSON-encoded `variables` and `extensions`
+/// Persisted queries are GETs that send only the hash of a document the server already knows
+pub fn from_query(method: &Method, uri: &Uri) -> Option<GraphqlOperation> {
+    if method != Method::GET {
+        return None;
+    }
+    let mut params = Vec::new();
+    for (name, value) in url::form_urlencoded::parse(uri.query()?.as_bytes()) {
+        params.push((name.into_owned(), value.into_owned()));
+    }
+    let param = |name: &str| params.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+    let json = |name: &str| param(name).and_then(|v| serde_json::from_str::<Value>(v).ok());
+    let persisted = persisted_query(json("extensions").as_ref(), param("documentId"));
+    let mut operation = operation(param("query"), param("operationName"), json("variables"), persisted)?;
+    // GET can only run queries
+    operation.operation_type.get_or_insert(OperationType::Query);
+    Some(operation)
+}
+
+/// Top-level `errors` of each GraphQL response, None if the body is not JSON
+/// A batch is answered with an array of responses, in the order of its operations
+pub fn errors(data: &[u8]) -> Option<Vec<Vec<GraphqlError>>> {
+    let responses = match serde_json::from_slice::<Value>(data).ok()? {
+        Value::Array(responses) => responses,
+        response => vec![response],
+    };
+    let errors = responses
+        .iter()
+        .map(|response| {
+            response
+                .get("errors")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .map(|error| GraphqlError {
+                    message: error.get("message").and_then(Value::as_str).unwrap_or_default().to_string(),
+                    path: error.get("path").filter(|p| !p.is_null()).cloned(),
+                    code: error.pointer("/extensions/code").and_then(Value::as_str).map(str::to_string),
+                })
+                .collect()
+        })
+        .collect();
+    Some(errors)
+}
+
+/// `extensions.persistedQuery.sha256Hash` (automatic persisted queries) or a `documentId`
+fn persisted_query(extensions: Option<&Value>, document_id: Option<&str>) -> Option<String> {
+    extensions
+        .and_then(|e| e.pointer("/persistedQuery/sha256Hash"))
+        .and_then(Value::as_str)
+        .or(document_id)
+        .map(str::to_string)
+}
+
+/// Build the operation picked by `operation_name` in `document`
+/// A request is only taken for GraphQL if its document defines an operation or it names a persisted query
+fn operation(
+    document: Option<&str>,
+    operation_name: Option<&str>,
+    variables: Option<Value>,
+    persisted_query: Option<String>,
+) -> Option<GraphqlOperation> {
+    let operations = document.map(operations).unwrap_or_default();
+    if operations.is_empty() && persisted_query.is_none() {
+        return None;
+    }
+    let picked = match operation_name {
+        Some(name) => operations.iter().find(|(_, n)| n.as_deref() == Some(name)),
+        None if operations.len() == 1 => operations.first(),
+        None => None,
+    };
+    Some(GraphqlOperation {
+        operation_name: operation_name.map(str::to_string).or_else(|| picked.and_then(|(_, n)| n.clone())),
+        operation_type: picked.map(|(kind, _)| *kind),
+        variables,
+        persisted_query,
+        errors: Vec::new(),
+    })
+}
+
+/// Type and name of every operation defined in a query document, fragments are skipped
+fn operations(document: &str) -> Vec<(OperationType, Option<String>)> {
+    // Keyword and name seen so far of the definition being read, a None type is a fragment
+    let mut pending: Option<(Option<OperationType>, Option<String>)> = None;
+    let mut operations = Vec::new();
+    for token in top_level_tokens(document) {
+        match (token, &mut pending) {
+            (Token::Name(keyword), None) => {
+                pending = match keyword {
+                    "query" => Some((Some(OperationType::Query), None)),
+                    "mutation" => Some((Some(OperationType::Mutation), None)),
+                    "subscription" => Some((Some(OperationType::Subscription), None)),
+                    "fragment" => Some((None, None)),
+                    _ => return Vec::new(),
+                }
+            }
+            (Token::Name(name), Some((Some(_), operation_name @ None))) => *operation_name = Some(name.to_string()),
+            (Token::Name(_), Some(_)) => {}
+            // A selection set on its own is an anonymous query
+            (Token::SelectionSet, None) => operations.push((OperationType::Query, None)),
+            (Token::SelectionSet, Some(_)) => {
+                if let Some((Some(kind), name)) = pending.take() {
+                    operations.push((kind, name));
+                }
+            }
+        }
+    }
+    operations
+}
+
+enum Token<'a> {
+    Name(&'a str),
+    /// Opening brace of a selection set, its content is skipped
+    SelectionSet,
+}
+
+/// Names and selection sets outside of any braces or parentheses
+/// Strings, block strings and comments are skipped so braces inside them don't count
+fn top_level_tokens(document: &str) -> Vec<Token<'_>> {
+    let bytes = document.as_bytes();
+    let mut tokens = Vec::new();
+    let (mut braces, mut parens) = (0usize, 0usize);
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'#' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'"' if bytes[i..].starts_with(b"\"\"\"") => {
+                i += 3;
+                while i < bytes.len() && !bytes[i..].starts_with(b"\"\"\"") {
+                    i += if bytes[i..].starts_with(b"\\\"\"\"") { 4 } else { 1 };
+                }
+                i += 2;
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'{' => {
+                if braces == 0 && parens == 0 {
+                    tokens.push(Token::SelectionSet);
+                }
+                braces += 1;
+            }
+            b'}' => braces = braces.saturating_sub(1),
+            b'(' => parens += 1,
+            b')' => parens = parens.saturating_sub(1),
+            b if b == b'_' || b.is_ascii_alphabetic() => {
+                let start = i;
+                while i + 1 < bytes.len() && (bytes[i + 1] == b'_' || bytes[i + 1].is_ascii_alphanumeric()) {
+                    i += 1;
+                }
+                if braces == 0 && parens == 0 && (start == 0 || !matches!(bytes[start - 1], b'$' | b'@')) {
+                    tokens.push(Token::Name(&document[start..=i]));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_operation_is_picked_from_document() {
+        let document = r#"
+            # "{" in a comment
+            fragment UserFields on User @client { id name }
+            query GetUser($id: ID = "}") @cached { user(id: $id) { ...UserFields } }
+            mutation RenameUser { rename(name: """a "{" block""") { id } }
+        "#;
+
+        let named = |name| operation(Some(document), Some(name), None, None).unwrap();
+
+        assert_eq!(named("GetUser").operation_type, Some(OperationType::Query));
+        assert_eq!(named("RenameUser").operation_type, Some(OperationType::Mutation));
+        assert_eq!(operation(Some(document), None, None, None).unwrap().operation_type, None);
+        let anonymous = operation(Some("{ viewer { id } }"), None, None, None).unwrap();
+        assert_eq!(anonymous.operation_type, Some(OperationType::Query));
+        assert!(anonymous.operation_name.is_none());
+        let subscription = operation(Some("subscription OnMessage { message }"), None, None, None).unwrap();
+        assert_eq!(subscription.operation_name.as_deref(), Some("OnMessage"));
+        assert_eq!(subscription.operation_type, Some(OperationType::Subscription));
+    }
+
+    #[test]
+    fn test_non_graphql_requests_are_ignored() {
+        let post = |body: Value| from_body(RequestFormat::Json, body.to_string().as_bytes());
+
+        assert!(post(json!({"query": "shoes"})).is_empty());
+        assert!(post(json!({"name": "Ada"})).is_empty());
+        assert!(from_query(&Method::GET, &"/search?query=red+shoes".parse().unwrap()).is_none());
+        assert!(from_query(&Method::GET, &"/users".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_persisted_query_get() {
+        let extensions = r#"{"persistedQuery":{"version":1,"sha256Hash":"ecf4edb4"}}"#;
+        let uri = format!(
+            "/graphql?operationName=GetUser&variables=%7B%22id%22%3A1%7D&extensions={}",
+            url::form_urlencoded::byte_serialize(extensions.as_bytes()).collect::<String>()
+        );
+
+        let operation = from_query(&Method::GET, &uri.parse().unwrap()).unwrap();
+
+        assert_eq!(operation.operation_name.as_deref(), Some("GetUser"));
+        assert_eq!(operation.operation_type, Some(OperationType::Query));
+        assert_eq!(operation.variables, Some(json!({"id": 1})));
+        assert_eq!(operation.persisted_query.as_deref(), Some("ecf4edb4"));
+    }
+
+    #[test]
+    fn test_batched_request_keeps_every_operation() {
+        let batch = json!([
+            {"query": "query GetUser { user { id } }"},
+            {"query": "mutation Logout { logout }"},
+        ]);
+        let responses = json!([
+            {"data": {"user": null}},
+            {"data": null, "errors": [{"message": "Not logged in"}]},
+        ]);
+
+        let operations = from_body(RequestFormat::Json, batch.to_string().as_bytes());
+        let errors = errors(responses.to_string().as_bytes()).unwrap();
+
+        let names: Vec<_> = operations.iter().map(|o| o.operation_name.as_deref()).collect();
+        assert_eq!(names, vec![Some("GetUser"), Some("Logout")]);
+        assert_eq!(operations[1].operation_type, Some(OperationType::Mutation));
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].is_empty());
+        assert_eq!(errors[1][0].message, "Not logged in");
+        assert!(from_body(RequestFormat::Json, b"[]").is_empty());
+        assert!(from_body(RequestFormat::Json, br#"[{"query": "{ me }"}, {"name": "Ada"}]"#).is_empty());
+    }
+
+    #[test]
+    fn test_response_errors() {
+        let response = json!({
+            "data": null,
+            "errors": [{"message": "Not allowed", "path": ["user", "email"], "extensions": {"code": "FORBIDDEN"}}]
+        });
+
+        let errors = errors(response.to_string().as_bytes()).unwrap().remove(0);
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Not allowed");
+        assert_eq!(errors[0].path, Some(json!(["user", "email"])));
+        assert_eq!(errors[0].code.as_deref(), Some("FORBIDDEN"));
+        assert_eq!(super::errors(br#"{"data": {}}"#), Some(vec![Vec::new()]));
+        assert_eq!(super::errors(b"<html>"), None);
+    }
+}
@@ -0,0 +1,376 @@
+use std::io::Read;
+use axum::http::{HeaderMap, header};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+use serde_json::Value;
+use tracing::info;
+
+use crate::storage::LogStore;
+use crate::storage::models::{Direction, GrpcMessage, unix_millis};
+use crate::utils::errors::AppError;
+
+/// Messages stored per exchange before further ones are only counted
+pub const MAX_MESSAGES_PER_EXCHANGE: usize = 10_000;
+
+/// Length prefix of every message: a compressed flag and a big-endian length
+const PREFIX_LEN: usize = 5;
+
+/// True if the body is a gRPC message stream (`application/grpc` or `application/grpc+proto`)
+/// gRPC-Web is not included, it carries its trailers inside the body
+pub fn is_grpc(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .is_some_and(|v| v == "application/grpc" || v.starts_with("application/grpc+"))
+}
+
+/// Service and method named by a gRPC request path, "/helloworld.Greeter/SayHello"
+pub fn method_name(path: &str) -> Option<(&str, &str)> {
+    let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+    (!service.is_empty() && !method.is_empty() && !method.contains('/')).then_some((service, method))
+}
+
+/// Message types loaded from the `[grpc]` descriptor set, empty when none is configured
+#[derive(Debug, Default)]
+pub struct Descriptors {
+    pool: DescriptorPool,
+}
+
+impl Descriptors {
+    /// Read a binary `FileDescriptorSet`, it must include the imports of the files it describes
+    pub fn load(path: &str) -> Result<Self, AppError> {
+        let invalid = |e: String| AppError::DescriptorSetError(format!("Failed to read '{}': {}", path, e));
+        let bytes = std::fs::read(path).map_err(|e| invalid(e.to_string()))?;
+        let pool = DescriptorPool::decode(bytes.as_slice()).map_err(|e| invalid(e.to_string()))?;
+        info!("Decoding gRPC messages of {} services from {}", pool.services().len(), path);
+        Ok(Self { pool })
+    }
+
+    /// Input and output types of a method, None if the descriptor set doesn't know it
+    pub fn method(&self, service: &str, method: &str) -> Option<(MessageDescriptor, MessageDescriptor)> {
+        let method = self.pool.get_service_by_name(service)?.methods().find(|m| m.name() == method)?;
+        Some((method.input(), method.output()))
+    }
+}
+
+/// Status and message of a gRPC call, from its trailers or the headers of a trailers-only response
+pub fn status(fields: &[(String, String)]) -> (Option<u32>, Option<String>) {
+    let field = |name: &str| fields.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+    let status = field("grpc-status").and_then(|v| v.trim().parse().ok());
+    (status, field("grpc-message").map(percent_decode))
+}
+
+/// `grpc-message` percent-encodes everything outside printable ASCII
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // Only two hex digits make an escape, `from_str_radix` alone would also take a sign like `%+1`
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Incremental parser of a gRPC body, storing each message as soon as it is complete
+pub struct GrpcParser {
+    store: LogStore,
+    exchange_id: u64,
+    direction: Direction,
+    /// Type of the messages, when known from the descriptor set
+    message_type: Option<MessageDescriptor>,
+    /// `grpc-encoding` of compressed messages
+    compression: Option<String>,
+    data_limit: usize,
+    prefix: Vec<u8>,
+    /// Bytes of the current message still to come
+    remaining: usize,
+    size: usize,
+    data: Vec<u8>,
+}
+
+impl GrpcParser {
+    /// `data_limit` is the maximum number of bytes kept per message
+    pub fn new(
+        store: LogStore,
+        exchange_id: u64,
+        direction: Direction,
+        message_type: Option<MessageDescriptor>,
+        headers: &HeaderMap,
+        data_limit: usize,
+    ) -> Self {
+        let compression = headers.get("grpc-encoding").and_then(|v| v.to_str().ok()).map(str::to_string);
+        Self {
+            store,
+            exchange_id,
+            direction,
+            message_type,
+            compression,
+            data_limit,
+            prefix: Vec::with_capacity(PREFIX_LEN),
+            remaining: 0,
+            size: 0,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn feed(&mut self, mut chunk: &[u8]) {
+        while !chunk.is_empty() {
+            if self.prefix.len() < PREFIX_LEN {
+                let taken = chunk.len().min(PREFIX_LEN - self.prefix.len());
+                self.prefix.extend_from_slice(&chunk[..taken]);
+                chunk = &chunk[taken..];
+                if self.prefix.len() == PREFIX_LEN {
+                    self.size = u32::from_be_bytes([self.prefix[1], self.prefix[2], self.prefix[3], self.prefix[4]])
+                        as usize;
+                    self.remaining = self.size;
+                    if self.remaining == 0 {
+                        self.dispatch();
+                    }
+                }
+                continue;
+            }
+            let taken = chunk.len().min(self.remaining);
+            let room = self.data_limit.saturating_sub(self.data.len());
+            self.data.extend_from_slice(&chunk[..taken.min(room)]);
+            self.remaining -= taken;
+            chunk = &chunk[taken..];
+            if self.remaining == 0 {
+                self.dispatch();
+            }
+        }
+    }
+
+    fn dispatch(&mut self) {
+        let compressed = self.prefix[0] & 1 == 1;
+        let data = std::mem::take(&mut self.data);
+        self.prefix.clear();
+        let mut message = GrpcMessage {
+            timestamp_ms: unix_millis(),
+            compressed,
+            size: self.size as u64,
+            truncated: data.len() < self.size,
+            json: None,
+            data: None,
+            decode_error: None,
+        };
+        let mut json = None;
+        if let Some(message_type) = &self.message_type
+            && !message.truncated
+        {
+            match self.decode(message_type, &data, compressed) {
+                Ok(Some(decoded)) => json = Some(decoded),
+                Ok(None) => message.truncated = true,
+                Err(error) => message.decode_error = Some(error),
+            }
+        }
+        match json {
+            Some(json) => message.json = Some(json),
+            None => message.data = Some(STANDARD.encode(&data)),
+        }
+
+        let direction = self.direction;
+        self.store.update(self.exchange_id, |e| {
+            let Some(grpc) = e.grpc.as_mut() else { return };
+            if grpc.request_messages.len() + grpc.response_messages.len() >= MAX_MESSAGES_PER_EXCHANGE {
+                grpc.messages_dropped += 1;
+                return;
+            }
+            match direction {
+                Direction::ClientToServer => grpc.request_messages.push(message),
+                Direction::ServerToClient => grpc.response_messages.push(message),
+            }
+        });
+    }
+
+    /// None when the message decompresses to more than `data_limit` bytes
+    fn decode(&self, message_type: &MessageDescriptor, data: &[u8], compressed: bool) -> Result<Option<Value>, String> {
+        let decompressed;
+        let data = match self.compression.as_deref() {
+            _ if !compressed => data,
+            Some(coding @ ("gzip" | "deflate")) => {
+                let mut buffer = Vec::new();
+                // One byte past the limit tells a message that fits from one that does not
+                let limit = self.data_limit as u64 + 1;
+                let result = match coding {
+                    "gzip" => GzDecoder::new(data).take(limit).read_to_end(&mut buffer),
+                    _ => ZlibDecoder::new(data).take(limit).read_to_end(&mut buffer),
+                };
+                result.map_err(|e| format!("Failed to decompress {} message: {}", coding, e))?;
+                if buffer.len() > self.data_limit {
+                    return Ok(None);
+                }
+                decompressed = buffer;
+                &decompressed
+            }
+            Some(coding) => return Err(format!("Unsupported message compression '{}'", coding)),
+            None => return Err("Compressed message without grpc-encoding".to_string()),
+        };
+        let message = DynamicMessage::decode(message_type.clone(), data)
+            .map_err(|e| format!("Failed to decode {}: {}", message_type.full_name(), e))?;
+        serde_json::to_value(&message)
+            .map(Some)
+            .map_err(|e| format!("Failed to convert {} to JSON: {}", message_type.full_name(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use axum::http::{HeaderName, HeaderValue};
+    use prost_reflect::prost::Message;
+    use prost_reflect::prost_types::field_descriptor_proto::{Label, Type};
+    use prost_reflect::prost_types::{
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet, MethodDescriptorProto,
+        ServiceDescriptorProto,
+    };
+    use crate::storage::models::{CapturedExchange, GrpcCall};
+
+    fn descriptors() -> Descriptors {
+        let field = |name: &str, number: i32, kind: Type| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(kind as i32),
+            json_name: None,
+            ..FieldDescriptorProto::default()
+        };
+        let file = FileDescriptorProto {
+            name: Some("greeter.proto".to_string()),
+            package: Some("helloworld".to_string()),
+            syntax: Some("proto3".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("HelloRequest".to_string()),
+                field: vec![field("name", 1, Type::String), field("times", 2, Type::Int32)],
+                ..DescriptorProto::default()
+            }],
+            service: vec![ServiceDescriptorProto {
+                name: Some("Greeter".to_string()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("SayHello".to_string()),
+                    input_type: Some(".helloworld.HelloRequest".to_string()),
+                    output_type: Some(".helloworld.HelloRequest".to_string()),
+                    ..MethodDescriptorProto::default()
+                }],
+                ..ServiceDescriptorProto::default()
+            }],
+            ..FileDescriptorProto::default()
+        };
+        let set = FileDescriptorSet { file: vec![file] };
+        Descriptors { pool: DescriptorPool::decode(set.encode_to_vec().as_slice()).unwrap() }
+    }
+
+    fn framed(message: &[u8]) -> Vec<u8> {
+        let mut framed = vec![0];
+        framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        framed.extend_from_slice(message);
+        framed
+    }
+
+    fn parse(message_type: Option<MessageDescriptor>, body: &[u8], limit: usize) -> GrpcCall {
+        let store = LogStore::default();
+        let mut exchange = CapturedExchange::new("POST", "/s/m", &HeaderMap::new());
+        exchange.grpc = Some(GrpcCall::new("s", "m"));
+        let id = store.insert(exchange);
+        let headers = HeaderMap::new();
+        let mut parser = GrpcParser::new(store.clone(), id, Direction::ClientToServer, message_type, &headers, limit);
+        for chunk in body.chunks(3) {
+            parser.feed(chunk);
+        }
+        store.get(id).unwrap().grpc.unwrap()
+    }
+
+    #[test]
+    fn test_messages_are_split_and_decoded() {
+        let (input, _) = descriptors().method("helloworld.Greeter", "SayHello").unwrap();
+        // name = "Ada", times = 2
+        let mut body = framed(b"\x0a\x03Ada\x10\x02");
+        body.extend(framed(b""));
+
+        let call = parse(Some(input), &body, 1024);
+
+        assert_eq!(call.request_messages.len(), 2);
+        assert_eq!(call.request_messages[0].size, 7);
+        assert_eq!(call.request_messages[0].json, Some(serde_json::json!({"name": "Ada", "times": 2})));
+        assert_eq!(call.request_messages[1].json, Some(serde_json::json!({})));
+    }
+
+    #[test]
+    fn test_unknown_or_truncated_messages_keep_raw_bytes() {
+        let (input, _) = descriptors().method("helloworld.Greeter", "SayHello").unwrap();
+        let body = framed(b"\x0a\x03Ada");
+
+        let unknown = parse(None, &body, 1024);
+        let truncated = parse(Some(input.clone()), &body, 2);
+        let invalid = parse(Some(input), &framed(b"\x0a\x09Ada"), 1024);
+
+        assert_eq!(unknown.request_messages[0].data.as_deref(), Some("CgNBZGE="));
+        assert!(unknown.request_messages[0].json.is_none());
+        assert!(truncated.request_messages[0].truncated);
+        assert_eq!(truncated.request_messages[0].data.as_deref(), Some("CgM="));
+        assert!(invalid.request_messages[0].decode_error.as_ref().unwrap().contains("helloworld.HelloRequest"));
+    }
+
+    #[test]
+    fn test_message_decompressing_past_limit_is_truncated() {
+        let (input, _) = descriptors().method("helloworld.Greeter", "SayHello").unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        // name = 300 times "a"
+        encoder.write_all(b"\x0a\xac\x02").unwrap();
+        encoder.write_all(&[b'a'; 300]).unwrap();
+        let mut body = framed(&encoder.finish().unwrap());
+        body[0] = 1;
+        let store = LogStore::default();
+        let mut exchange = CapturedExchange::new("POST", "/s/m", &HeaderMap::new());
+        exchange.grpc = Some(GrpcCall::new("s", "m"));
+        let id = store.insert(exchange);
+        let gzip = (HeaderName::from_static("grpc-encoding"), HeaderValue::from_static("gzip"));
+        let headers = HeaderMap::from_iter([gzip]);
+
+        for limit in [100, 1024] {
+            GrpcParser::new(store.clone(), id, Direction::ClientToServer, Some(input.clone()), &headers, limit)
+                .feed(&body);
+        }
+
+        let messages = store.get(id).unwrap().grpc.unwrap().request_messages;
+        assert!(messages[0].truncated);
+        assert!(messages[0].json.is_none() && messages[0].decode_error.is_none());
+        assert!(!messages[1].truncated);
+        assert_eq!(messages[1].json.as_ref().unwrap()["name"].as_str().unwrap().len(), 300);
+    }
+
+    #[test]
+    fn test_status_and_method_names() {
+        let trailers = vec![
+            ("grpc-status".to_string(), "5".to_string()),
+            ("grpc-message".to_string(), "user%20%E2%9C%93 not found".to_string()),
+        ];
+        let content_type = |value| HeaderMap::from_iter([(header::CONTENT_TYPE, HeaderValue::from_static(value))]);
+
+        assert_eq!(status(&trailers), (Some(5), Some("user ✓ not found".to_string())));
+        let literal = vec![("grpc-message".to_string(), "100%+1 sure %zz%4".to_string())];
+        assert_eq!(status(&literal), (None, Some("100%+1 sure %zz%4".to_string())));
+        assert_eq!(method_name("/helloworld.Greeter/SayHello"), Some(("helloworld.Greeter", "SayHello")));
+        assert_eq!(method_name("/health"), None);
+        assert!(is_grpc(&content_type("application/grpc+proto")));
+        assert!(!is_grpc(&content_type("application/grpc-web")));
+    }
+}
@@ -0,0 +1,193 @@
+use std::net::SocketAddr;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, header};
+
+/// Headers that only apply to a single connection and must not be forwarded (RFC 9110 7.6.1)
+/// `Trailer` is not among them, it declares end-to-end trailer fields (RFC 9110 6.6.2)
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// True if the headers ask to switch protocols (e.g. `Upgrade: websocket`)
+pub fn is_upgrade(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::UPGRADE) && connection_tokens(headers).any(|t| t == "upgrade")
+}
+
+/// Remove hop-by-hop headers, including any listed in `Connection`
+/// Upgrade negotiation headers are kept when the message is an upgrade,
+/// and `TE: trailers` is kept since it is allowed end-to-end (gRPC relies on it)
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let upgrading = is_upgrade(headers);
+    let keep_te_trailers = headers
+        .get_all(header::TE)
+        .iter()
+        .any(|v| v.to_str().is_ok_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case("trailers"))));
+
+    let listed: Vec<HeaderName> = connection_tokens(headers)
+        .filter(|t| !(upgrading && t == "upgrade"))
+        .filter_map(|t| HeaderName::from_bytes(t.as_bytes()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+
+    for name in HOP_BY_HOP {
+        if upgrading && (name == "connection" || name == "upgrade") {
+            continue;
+        }
+        headers.remove(name);
+    }
+
+    if upgrading {
+        headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    }
+    if keep_te_trailers {
+        headers.insert(header::TE, HeaderValue::from_static("trailers"));
+    }
+}
+
+/// Add `Forwarded` and `X-Forwarded-*` headers describing the original client request
+/// `For` values are appended to any existing chain, `X-Forwarded-Proto`/`-Host` always describe this hop
+/// so clients can't choose the scheme and host the target builds absolute URLs from
+pub fn add_forwarded(headers: &mut HeaderMap, client: SocketAddr, proto: &str, host: Option<&str>) {
+    let ip = client.ip();
+    let for_node = if ip.is_ipv6() {
+        format!("\"[{}]\"", ip)
+    } else {
+        ip.to_string()
+    };
+    let mut forwarded = format!("for={};proto={}", for_node, proto);
+    if let Some(host) = host {
+        forwarded.push_str(&format!(";host=\"{}\"", host));
+    }
+    append_list(headers, HeaderName::from_static("forwarded"), &forwarded);
+    append_list(headers, HeaderName::from_static("x-forwarded-for"), &ip.to_string());
+
+    headers.remove("x-forwarded-proto");
+    headers.remove("x-forwarded-host");
+    if let Ok(value) = HeaderValue::from_str(proto) {
+        headers.insert("x-forwarded-proto", value);
+    }
+    if let Some(value) = host.and_then(|host| HeaderValue::from_str(host).ok()) {
+        headers.insert("x-forwarded-host", value);
+    }
+}
+
+/// Append `value` to a comma separated list header, merging repeated headers into one
+fn append_list(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    let existing: Vec<&str> = headers
+        .get_all(&name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+    let combined = if existing.is_empty() {
+        value.to_string()
+    } else {
+        format!("{}, {}", existing.join(", "), value)
+    };
+    if let Ok(combined) = HeaderValue::from_str(&combined) {
+        headers.insert(name, combined);
+    }
+}
+
+fn connection_tokens(headers: &HeaderMap) -> impl Iterator<Item = String> + '_ {
+    headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|t| t.trim().to_ascii_lowercase())
+        .filter(|t| !t.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    #[test]
+    fn test_strip_hop_by_hop() {
+        let mut map = headers(&[
+            ("connection", "keep-alive, x-session"),
+            ("keep-alive", "timeout=5"),
+            ("proxy-authorization", "Basic abc"),
+            ("te", "gzip"),
+            ("upgrade", "h2c"),
+            ("x-session", "1"),
+            ("content-type", "text/plain"),
+        ]);
+
+        strip_hop_by_hop(&mut map);
+
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["content-type"], "text/plain");
+    }
+
+    #[test]
+    fn test_strip_keeps_trailer_declaration() {
+        let mut map = headers(&[("trailer", "grpc-status, grpc-message"), ("transfer-encoding", "chunked")]);
+
+        strip_hop_by_hop(&mut map);
+
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["trailer"], "grpc-status, grpc-message");
+    }
+
+    #[test]
+    fn test_strip_keeps_upgrade_and_te_trailers() {
+        let mut map = headers(&[
+            ("connection", "Upgrade"),
+            ("upgrade", "websocket"),
+            ("te", "trailers"),
+        ]);
+
+        strip_hop_by_hop(&mut map);
+
+        assert_eq!(map["connection"], "upgrade");
+        assert_eq!(map["upgrade"], "websocket");
+        assert_eq!(map["te"], "trailers");
+    }
+
+    #[test]
+    fn test_add_forwarded_headers() {
+        let mut map = HeaderMap::new();
+        let client: SocketAddr = "192.168.1.10:51000".parse().unwrap();
+
+        add_forwarded(&mut map, client, "http", Some("localhost:3000"));
+
+        assert_eq!(map["forwarded"], "for=192.168.1.10;proto=http;host=\"localhost:3000\"");
+        assert_eq!(map["x-forwarded-for"], "192.168.1.10");
+        assert_eq!(map["x-forwarded-proto"], "http");
+        assert_eq!(map["x-forwarded-host"], "localhost:3000");
+    }
+
+    #[test]
+    fn test_add_forwarded_appends_to_existing_chain() {
+        let mut map = headers(&[
+            ("x-forwarded-for", "10.0.0.1"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "evil.example"),
+        ]);
+        let client: SocketAddr = "[::1]:51000".parse().unwrap();
+
+        add_forwarded(&mut map, client, "http", None);
+
+        assert_eq!(map["forwarded"], "for=\"[::1]\";proto=http");
+        assert_eq!(map["x-forwarded-for"], "10.0.0.1, ::1");
+        assert_eq!(map["x-forwarded-proto"], "http");
+        assert!(!map.contains_key("x-forwarded-host"));
+    }
+}
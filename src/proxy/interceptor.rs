@@ -0,0 +1,969 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use std::time::Instant;
+use axum::body::{Body, Bytes};
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, header};
+use axum::response::Response;
+use http_body::{Frame, SizeHint};
+use prost_reflect::MessageDescriptor;
+use regex::Regex;
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tracing::debug;
+
+use crate::config::{JsonPatchKind, JsonPatchOp, RewriteConfig, RewritePhase};
+use crate::proxy::cassette::Recorder;
+use crate::proxy::encoding::{Decoder, Observer};
+use crate::proxy::graphql;
+use crate::proxy::grpc::{Descriptors, GrpcParser, is_grpc, method_name, status};
+use crate::proxy::routing::glob_matches;
+use crate::proxy::sse::{SseParser, is_event_stream};
+use crate::server::StreamId;
+use crate::storage::LogStore;
+use crate::storage::models::{CapturedBody, CapturedExchange, Direction, GrpcCall, header_pairs};
+use crate::utils::errors::AppError;
+
+/// Capture pipeline sitting around the forwarder
+/// Records request/response metadata in the store and tees bodies as they stream through
+#[derive(Clone, Debug)]
+pub struct Interceptor {
+    store: LogStore,
+    body_limit: usize,
+    absolute_uris: bool,
+    recorder: Option<Arc<Recorder>>,
+    rewrites: Arc<Rewrites>,
+    descriptors: Arc<Descriptors>,
+}
+
+/// Handle for an exchange that is currently in flight
+#[derive(Debug)]
+pub struct InFlight {
+    pub id: u64,
+    started: Instant,
+    /// Response rules matched by the request, by position in `Rewrites`
+    response_rewrites: Vec<usize>,
+    /// Notified once the response body is captured, dropped if the exchange fails
+    settled: Option<oneshot::Sender<()>>,
+    /// Set for gRPC calls
+    grpc: bool,
+    /// Type of the gRPC response messages, when the descriptor set knows the method
+    grpc_response: Option<MessageDescriptor>,
+    /// Set once the response came from the target, only those exchanges are recorded to a cassette
+    forwarded: bool,
+}
+
+impl InFlight {
+    /// Resolves once the response body is captured in full or the exchange failed
+    pub fn settled(&mut self) -> oneshot::Receiver<()> {
+        let (settled, receiver) = oneshot::channel();
+        self.settled = Some(settled);
+        receiver
+    }
+
+    /// Mark the response as the one the target sent, rather than a mock, fault, breakpoint or replay answer
+    pub fn forwarded(&mut self) {
+        self.forwarded = true;
+    }
+}
+
+impl Interceptor {
+    /// `body_limit` is the number of bytes kept per body, anything beyond is marked truncated
+    pub fn new(store: LogStore, body_limit: usize) -> Self {
+        Self {
+            store,
+            body_limit,
+            absolute_uris: false,
+            recorder: None,
+            rewrites: Arc::default(),
+            descriptors: Arc::default(),
+        }
+    }
+
+    /// Record the full request target instead of only the path and query
+    /// Used in forward-proxy mode, where each request names its own destination
+    pub fn with_absolute_uris(mut self) -> Self {
+        self.absolute_uris = true;
+        self
+    }
+
+    /// Write every forwarded exchange to a cassette once its response body has been fully relayed
+    pub fn with_recorder(mut self, recorder: Arc<Recorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Apply `[[rewrites]]` rules to the exchanges going through
+    pub fn with_rewrites(mut self, rewrites: Arc<Rewrites>) -> Self {
+        self.rewrites = rewrites;
+        self
+    }
+
+    /// Decode the messages of gRPC calls with the types of a descriptor set
+    pub fn with_descriptors(mut self, descriptors: Arc<Descriptors>) -> Self {
+        self.descriptors = descriptors;
+        self
+    }
+
+    pub fn store(&self) -> &LogStore {
+        &self.store
+    }
+
+    /// Number of bytes kept per captured body
+    pub fn body_limit(&self) -> usize {
+        self.body_limit
+    }
+
+    /// Rewrite the incoming request, record it and wrap its body so it is captured while being forwarded
+    /// The request is recorded as the target will see it
+    /// A body changed by JSON patches is read and patched here, so it goes on with its exact length
+    /// and can still be retried or mirrored
+    pub async fn begin(&self, req: Request) -> (InFlight, Request) {
+        let (request_rewrites, response_rewrites): (Vec<_>, Vec<_>) = self
+            .rewrites
+            .matching(req.method(), req.uri().path())
+            .partition(|&index| self.rewrites.rules[index].phase == RewritePhase::Request);
+        let (req, request_patches) = self.rewrites.rewrite_request(req, &request_rewrites);
+
+        let uri = match req.uri().path_and_query() {
+            _ if self.absolute_uris => req.uri().to_string(),
+            Some(path_and_query) => path_and_query.to_string(),
+            None => "/".to_string(),
+        };
+        let mut exchange = CapturedExchange::new(req.method().as_str(), &uri, req.headers());
+        exchange.http_version = format!("{:?}", req.version());
+        exchange.stream_id = req.extensions().get::<StreamId>().map(|s| s.0);
+        exchange.rewrites = self.rewrites.names(&request_rewrites);
+        let grpc = match method_name(req.uri().path()) {
+            Some((service, method)) if is_grpc(req.headers()) => {
+                exchange.grpc = Some(GrpcCall::new(service, method));
+                Some(self.descriptors.method(service, method).unzip())
+            }
+            _ => None,
+        };
+        exchange.graphql = graphql::from_query(req.method(), req.uri()).into_iter().collect();
+        let graphql_format = graphql::request_format(req.method(), req.headers());
+        let id = self.store.insert(exchange);
+        let (parts, body) = req.into_parts();
+        let body = match self.patch_body(id, "request", body, request_patches) {
+            Ok(mut patched) => {
+                patched.fill().await;
+                patched.into_body()
+            }
+            Err(body) => body,
+        };
+        let req = Request::from_parts(parts, body);
+
+        let store = self.store.clone();
+        let decoder = Decoder::new(req.headers(), self.body_limit);
+        let (grpc_request, grpc_response) = grpc.clone().unwrap_or_default();
+        let mut grpc_parser = grpc.is_some().then(|| {
+            let store = self.store.clone();
+            GrpcParser::new(store, id, Direction::ClientToServer, grpc_request, req.headers(), self.body_limit)
+        });
+        let req = req.map(|body| {
+            let mut body = CaptureBody::new(body, self.body_limit, move |captured| {
+                store.update(id, |e| {
+                    if let Some(format) = graphql_format
+                        && captured.complete
+                        && !captured.truncated
+                    {
+                        e.graphql = graphql::from_body(format, &captured.data);
+                    }
+                    e.request_body = captured;
+                });
+            });
+            if let Some(mut parser) = grpc_parser.take() {
+                body = body.with_observer(move |data| parser.feed(data));
+            }
+            Body::new(body.with_decoder(decoder))
+        });
+
+        let in_flight = InFlight {
+            id,
+            started: Instant::now(),
+            response_rewrites,
+            settled: None,
+            grpc: grpc.is_some(),
+            grpc_response,
+            forwarded: false,
+        };
+        (in_flight, req)
+    }
+
+    /// Record the upstream response head and wrap its body so it is captured while streaming back
+    /// The response is recorded as the target sent it, response rewrites only change what the client gets
+    pub fn complete(&self, in_flight: InFlight, response: Response) -> Response {
+        let id = in_flight.id;
+        let elapsed = in_flight.started.elapsed().as_millis() as u64;
+        self.store.update(id, |e| {
+            e.status = Some(response.status().as_u16());
+            e.upstream_http_version = Some(format!("{:?}", response.version()));
+            e.response_headers = header_pairs(response.headers());
+            e.duration_ms = Some(elapsed);
+            e.rewrites.extend(self.rewrites.names(&in_flight.response_rewrites));
+            if let Some(grpc) = e.grpc.as_mut() {
+                // A trailers-only response carries the status in its headers
+                (grpc.status, grpc.message) = status(&e.response_headers);
+            }
+        });
+
+        let sse = is_event_stream(response.headers())
+            .then(|| SseParser::new(self.store.clone(), id, self.body_limit));
+        let grpc = (in_flight.grpc && is_grpc(response.headers())).then(|| {
+            let store = self.store.clone();
+            let message_type = in_flight.grpc_response;
+            GrpcParser::new(store, id, Direction::ServerToClient, message_type, response.headers(), self.body_limit)
+        });
+
+        let store = self.store.clone();
+        let recorder = self.recorder.clone().filter(|_| in_flight.forwarded);
+        let settled = in_flight.settled;
+        let decoder = Decoder::new(response.headers(), self.body_limit);
+        let response = response.map(|body| {
+            let mut body = CaptureBody::new(body, self.body_limit, move |captured| {
+                let complete = captured.complete;
+                store.update(id, |e| {
+                    if let Some(grpc) = e.grpc.as_mut()
+                        && let (Some(status), message) = status(&captured.trailers)
+                    {
+                        (grpc.status, grpc.message) = (Some(status), message);
+                    }
+                    if !e.graphql.is_empty()
+                        && !captured.truncated
+                        && let Some(errors) = graphql::errors(&captured.data)
+                    {
+                        for (operation, errors) in e.graphql.iter_mut().zip(errors) {
+                            operation.errors = errors;
+                        }
+                    }
+                    e.response_body = captured;
+                });
+                if let Some(recorder) = recorder
+                    && complete
+                    && let Some(exchange) = store.get(id)
+                {
+                    recorder.record(&exchange);
+                }
+                if let Some(settled) = settled {
+                    let _ = settled.send(());
+                }
+            });
+            if let Some(mut sse) = sse {
+                body = body.with_observer(move |data| sse.feed(data));
+            }
+            if let Some(mut grpc) = grpc {
+                body = body.with_observer(move |data| grpc.feed(data));
+            }
+            Body::new(body.with_decoder(decoder))
+        });
+        let (response, patches) = self.rewrites.rewrite_response(response, &in_flight.response_rewrites);
+        response.map(|body| match self.patch_body(id, "response", body, patches) {
+            Ok(patched) => Body::new(patched),
+            Err(body) => body,
+        })
+    }
+
+    /// Apply the JSON patches of the rewrites to a body
+    /// The body is given back as it is when there are none or they can't apply, the reason is then kept on the exchange
+    fn patch_body(
+        &self,
+        id: u64,
+        side: &'static str,
+        body: Body,
+        patches: Result<Vec<JsonPatchOp>, String>,
+    ) -> Result<JsonPatchBody, Body> {
+        match patches {
+            Ok(patches) if patches.is_empty() => Err(body),
+            Ok(patches) => {
+                let store = self.store.clone();
+                let limit = self.body_limit;
+                Ok(JsonPatchBody::new(body, patches, limit, move || {
+                    let reason = format!("{} body over {} bytes", side, limit);
+                    store.update(id, |e| e.skipped_patches.push(reason));
+                }))
+            }
+            Err(reason) => {
+                self.store.update(id, |e| e.skipped_patches.push(format!("{} {}", side, reason)));
+                Err(body)
+            }
+        }
+    }
+
+    /// Record that the exchange failed before a response came back
+    pub fn fail(&self, in_flight: InFlight, error: &str) {
+        let elapsed = in_flight.started.elapsed().as_millis() as u64;
+        self.store.update(in_flight.id, |e| {
+            e.error = Some(error.to_string());
+            e.duration_ms = Some(elapsed);
+        });
+    }
+}
+
+/// `[[rewrites]]` rules, every rule matching a request applies in the order they are declared
+#[derive(Debug, Default)]
+pub struct Rewrites {
+    rules: Vec<RewriteRule>,
+}
+
+#[derive(Debug)]
+struct RewriteRule {
+    name: String,
+    phase: RewritePhase,
+    method: Option<Method>,
+    path: String,
+    set_headers: Vec<(HeaderName, HeaderValue)>,
+    add_headers: Vec<(HeaderName, HeaderValue)>,
+    remove_headers: Vec<HeaderName>,
+    rewrite_path: Option<(Regex, String)>,
+    status: Option<StatusCode>,
+    json_patch: Vec<JsonPatchOp>,
+}
+
+impl Rewrites {
+    pub fn new(configs: &[RewriteConfig]) -> Result<Self, AppError> {
+        let rules = configs.iter().map(RewriteRule::new).collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Position of every rule matching a request
+    fn matching<'a>(&'a self, method: &'a Method, path: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.rules.iter().enumerate().filter_map(move |(index, rule)| {
+            let matches = rule.method.as_ref().is_none_or(|m| m == method) && glob_matches(&rule.path, path);
+            matches.then_some(index)
+        })
+    }
+
+    fn names(&self, rules: &[usize]) -> Vec<String> {
+        rules.iter().map(|&index| self.rules[index].name.clone()).collect()
+    }
+
+    /// Rewrite the head of a request, with the JSON patches left to apply to its body
+    fn rewrite_request(&self, mut req: Request, rules: &[usize]) -> (Request, Result<Vec<JsonPatchOp>, String>) {
+        for rule in rules.iter().map(|&index| &self.rules[index]) {
+            rule.rewrite_headers(req.headers_mut());
+            if let Some((pattern, replacement)) = &rule.rewrite_path {
+                *req.uri_mut() = rewrite_path(req.uri(), pattern, replacement);
+            }
+        }
+        let patches = self.json_patches(req.headers_mut(), rules);
+        (req, patches)
+    }
+
+    /// Rewrite the head of a response, with the JSON patches left to apply to its body
+    fn rewrite_response(
+        &self,
+        mut response: Response,
+        rules: &[usize],
+    ) -> (Response, Result<Vec<JsonPatchOp>, String>) {
+        for rule in rules.iter().map(|&index| &self.rules[index]) {
+            rule.rewrite_headers(response.headers_mut());
+            if let Some(status) = rule.status {
+                *response.status_mut() = status;
+            }
+        }
+        let patches = self.json_patches(response.headers_mut(), rules);
+        (response, patches)
+    }
+
+    /// JSON patches of the rules for a body sent with `headers`, empty unless it is JSON
+    /// An encoded body can't be patched, the error says why
+    fn json_patches(&self, headers: &mut HeaderMap, rules: &[usize]) -> Result<Vec<JsonPatchOp>, String> {
+        let patches: Vec<_> = rules.iter().flat_map(|&index| self.rules[index].json_patch.iter().cloned()).collect();
+        if patches.is_empty() || !is_json(headers) {
+            return Ok(Vec::new());
+        }
+        if let Some(encoding) = headers
+            .get(header::CONTENT_ENCODING)
+            .map(|v| v.to_str().unwrap_or_default().trim())
+            .filter(|encoding| !encoding.eq_ignore_ascii_case("identity"))
+        {
+            return Err(format!("body with content-encoding '{}'", encoding));
+        }
+        headers.remove(header::CONTENT_LENGTH);
+        Ok(patches)
+    }
+}
+
+impl RewriteRule {
+    fn new(config: &RewriteConfig) -> Result<Self, AppError> {
+        let name = config.name.clone().unwrap_or_else(|| config.path.clone());
+        let invalid = |e: String| AppError::ValidateConfigError(format!("Invalid rewrite '{}': {}", name, e));
+        let method = config
+            .method
+            .as_deref()
+            .map(|m| Method::from_bytes(m.to_ascii_uppercase().as_bytes()))
+            .transpose()
+            .map_err(|e| invalid(e.to_string()))?;
+        let headers = |headers: &std::collections::BTreeMap<String, String>| {
+            headers
+                .iter()
+                .map(|(name, value)| {
+                    let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(e.to_string()))?;
+                    let value = HeaderValue::from_str(value).map_err(|e| invalid(e.to_string()))?;
+                    Ok((name, value))
+                })
+                .collect::<Result<Vec<_>, AppError>>()
+        };
+        let remove_headers = config
+            .remove_headers
+            .iter()
+            .map(|name| HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(e.to_string())))
+            .collect::<Result<_, _>>()?;
+        let rewrite_path = config
+            .rewrite_path
+            .as_ref()
+            .map(|r| Ok((Regex::new(&r.pattern).map_err(|e| invalid(e.to_string()))?, r.replacement.clone())))
+            .transpose()?;
+        let status = config
+            .status
+            .map(StatusCode::from_u16)
+            .transpose()
+            .map_err(|e| invalid(e.to_string()))?;
+        Ok(Self {
+            set_headers: headers(&config.set_headers)?,
+            add_headers: headers(&config.add_headers)?,
+            remove_headers,
+            rewrite_path,
+            status,
+            json_patch: config.json_patch.clone(),
+            phase: config.phase,
+            method,
+            path: config.path.clone(),
+            name,
+        })
+    }
+
+    /// Removals first, then replacements, then additions
+    fn rewrite_headers(&self, headers: &mut HeaderMap) {
+        for name in &self.remove_headers {
+            headers.remove(name);
+        }
+        for (name, value) in &self.set_headers {
+            headers.insert(name.clone(), value.clone());
+        }
+        for (name, value) in &self.add_headers {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+}
+
+/// Apply a regex replacement to the path of `uri`, keeping its query and any scheme and authority
+fn rewrite_path(uri: &Uri, pattern: &Regex, replacement: &str) -> Uri {
+    let path = pattern.replace(uri.path(), replacement);
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.into_owned(),
+    };
+    let mut parts = uri.clone().into_parts();
+    match path_and_query.parse() {
+        Ok(path_and_query) => parts.path_and_query = Some(path_and_query),
+        Err(e) => {
+            debug!("Ignoring rewritten path '{}': {}", path_and_query, e);
+            return uri.clone();
+        }
+    }
+    Uri::from_parts(parts).unwrap_or_else(|_| uri.clone())
+}
+
+/// `application/json` and the `+json` types
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| {
+            let mime = mime.trim().to_ascii_lowercase();
+            mime.ends_with("/json") || mime.ends_with("+json")
+        })
+}
+
+/// Apply `patches` to a JSON document, bodies that don't parse are returned untouched
+fn patch_json(data: Vec<u8>, patches: &[JsonPatchOp]) -> Bytes {
+    let Ok(mut document) = serde_json::from_slice::<Value>(&data) else {
+        return Bytes::from(data);
+    };
+    for patch in patches {
+        if !apply_patch(&mut document, patch) {
+            debug!("Skipping json_patch {:?} {}, the target does not exist", patch.op, patch.path);
+        }
+    }
+    serde_json::to_vec(&document).map(Bytes::from).unwrap_or_else(|_| Bytes::from(data))
+}
+
+/// Apply one operation, false when its path does not exist in the document
+fn apply_patch(document: &mut Value, patch: &JsonPatchOp) -> bool {
+    let value = || patch.value.clone().unwrap_or(Value::Null);
+    if patch.op == JsonPatchKind::Replace || (patch.op == JsonPatchKind::Add && patch.path.is_empty()) {
+        return match document.pointer_mut(&patch.path) {
+            Some(target) => {
+                *target = value();
+                true
+            }
+            None => false,
+        };
+    }
+    let Some((parent, token)) = patch.path.rsplit_once('/') else {
+        return false;
+    };
+    let token = token.replace("~1", "/").replace("~0", "~");
+    let index = |len: usize| token.parse::<usize>().ok().filter(|&i| i < len);
+    match (patch.op, document.pointer_mut(parent)) {
+        (JsonPatchKind::Add, Some(Value::Object(map))) => {
+            map.insert(token, value());
+            true
+        }
+        (JsonPatchKind::Add, Some(Value::Array(items))) => match token.as_str() {
+            "-" => {
+                items.push(value());
+                true
+            }
+            _ => match index(items.len() + 1) {
+                Some(index) => {
+                    items.insert(index, value());
+                    true
+                }
+                None => false,
+            },
+        },
+        (JsonPatchKind::Remove, Some(Value::Object(map))) => map.remove(&token).is_some(),
+        (JsonPatchKind::Remove, Some(Value::Array(items))) => match index(items.len()) {
+            Some(index) => {
+                items.remove(index);
+                true
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// Body read in full, patched, then sent as a single chunk
+/// Past `limit` bytes it is passed through unpatched, and `on_skip` runs
+struct JsonPatchBody {
+    inner: Body,
+    buffer: Vec<u8>,
+    trailers: Option<HeaderMap>,
+    patches: Vec<JsonPatchOp>,
+    limit: usize,
+    on_skip: Option<Box<dyn FnOnce() + Send + Sync>>,
+    /// Patched body, or what was read before passing through, waiting to be sent
+    ready: Option<Result<Bytes, axum::Error>>,
+    passthrough: bool,
+    done: bool,
+}
+
+impl JsonPatchBody {
+    fn new<F>(inner: Body, patches: Vec<JsonPatchOp>, limit: usize, on_skip: F) -> Self
+    where
+        F: FnOnce() + Send + Sync + 'static,
+    {
+        Self {
+            inner,
+            buffer: Vec::new(),
+            trailers: None,
+            patches,
+            limit,
+            on_skip: Some(Box::new(on_skip)),
+            ready: None,
+            passthrough: false,
+            done: false,
+        }
+    }
+
+    /// Read the body until it is patched or found over the limit, without sending anything yet
+    async fn fill(&mut self) {
+        std::future::poll_fn(|cx| self.poll_fill(cx)).await
+    }
+
+    /// The patched bytes on their own once the whole body was read, so its length is known
+    fn into_body(mut self) -> Body {
+        if self.done
+            && self.trailers.is_none()
+            && matches!(self.ready, Some(Ok(_)))
+            && let Some(Ok(data)) = self.ready.take()
+        {
+            return Body::from(data);
+        }
+        Body::new(self)
+    }
+
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.ready.is_some() || self.passthrough || self.done {
+            return Poll::Ready(());
+        }
+        loop {
+            match ready!(http_body::Body::poll_frame(Pin::new(&mut self.inner), cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        self.buffer.extend_from_slice(&data);
+                        if self.buffer.len() > self.limit {
+                            self.passthrough = true;
+                            if let Some(on_skip) = self.on_skip.take() {
+                                on_skip();
+                            }
+                            self.ready = Some(Ok(Bytes::from(std::mem::take(&mut self.buffer))));
+                            return Poll::Ready(());
+                        }
+                    }
+                    Err(frame) => self.trailers = frame.into_trailers().ok(),
+                },
+                Some(Err(e)) => {
+                    self.ready = Some(Err(e));
+                    return Poll::Ready(());
+                }
+                None => {
+                    self.done = true;
+                    let data = std::mem::take(&mut self.buffer);
+                    self.ready = Some(Ok(patch_json(data, &self.patches)));
+                    return Poll::Ready(());
+                }
+            }
+        }
+    }
+}
+
+impl http_body::Body for JsonPatchBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        ready!(self.poll_fill(cx));
+        if let Some(ready) = self.ready.take() {
+            return Poll::Ready(Some(ready.map(Frame::data)));
+        }
+        if self.passthrough {
+            return Pin::new(&mut self.inner).poll_frame(cx);
+        }
+        Poll::Ready(self.trailers.take().map(|trailers| Ok(Frame::trailers(trailers))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self.passthrough {
+            _ if self.ready.is_some() => false,
+            true => self.inner.is_end_stream(),
+            false => self.done && self.trailers.is_none(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let pending = match &self.ready {
+            Some(Ok(data)) => data.len() as u64,
+            _ => 0,
+        };
+        if self.passthrough {
+            let inner = self.inner.size_hint();
+            let mut hint = SizeHint::new();
+            hint.set_lower(inner.lower() + pending);
+            if let Some(upper) = inner.upper() {
+                hint.set_upper(upper + pending);
+            }
+            return hint;
+        }
+        match self.done {
+            true => SizeHint::with_exact(pending),
+            // Patches can grow or shrink the document, nothing is known before they applied
+            false => SizeHint::default(),
+        }
+    }
+}
+
+type OnComplete = Box<dyn FnOnce(CapturedBody) + Send + Sync>;
+
+/// Body wrapper that passes every frame through untouched
+/// while copying data into a bounded buffer
+pub struct CaptureBody<B: http_body::Body> {
+    inner: B,
+    captured: CapturedBody,
+    limit: usize,
+    on_complete: Option<OnComplete>,
+    observer: Option<Observer>,
+    decoder: Option<Decoder>,
+}
+
+impl<B: http_body::Body> CaptureBody<B> {
+    /// `on_complete` runs once, when the stream ends, fails or is dropped
+    pub fn new<F>(inner: B, limit: usize, on_complete: F) -> Self
+    where
+        F: FnOnce(CapturedBody) + Send + Sync + 'static,
+    {
+        Self {
+            inner,
+            captured: CapturedBody::default(),
+            limit,
+            on_complete: Some(Box::new(on_complete)),
+            observer: None,
+            decoder: None,
+        }
+    }
+
+    /// Keep the captured copy decoded, the relayed bytes stay as they are
+    pub fn with_decoder(mut self, decoder: Option<Decoder>) -> Self {
+        self.decoder = decoder;
+        self
+    }
+
+    /// Also hand every data chunk to `observer` as it passes, regardless of the capture limit
+    /// With a decoder the observer gets the decoded chunks
+    pub fn with_observer<F>(mut self, observer: F) -> Self
+    where
+        F: FnMut(&[u8]) + Send + Sync + 'static,
+    {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    fn record(&mut self, data: &Bytes) {
+        match (self.decoder.as_mut(), self.observer.as_mut()) {
+            (Some(decoder), Some(_)) => decoder.observe(self.observer.take().expect("Observer is set")),
+            (None, Some(observer)) => observer(data),
+            _ => {}
+        }
+        self.captured.size += data.len() as u64;
+        let room = self.limit.saturating_sub(self.captured.data.len());
+        if data.len() > room {
+            self.captured.truncated = true;
+        }
+        self.captured.data.extend_from_slice(&data[..data.len().min(room)]);
+        if let Some(decoder) = self.decoder.as_mut() {
+            decoder.write(data);
+        }
+    }
+
+    fn finish(&mut self, complete: bool) {
+        if let Some(on_complete) = self.on_complete.take() {
+            if let Some(decoder) = self.decoder.take() {
+                decoder.finish(complete, &mut self.captured);
+            }
+            self.captured.complete = complete;
+            on_complete(std::mem::take(&mut self.captured));
+        }
+    }
+}
+
+impl<B> http_body::Body for CaptureBody<B>
+where
+    B: http_body::Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    this.record(data);
+                } else if let Some(trailers) = frame.trailers_ref() {
+                    this.captured.trailers = header_pairs(trailers);
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            Poll::Ready(Some(Err(e))) => {
+                this.finish(false);
+                Poll::Ready(Some(Err(e)))
+            }
+            Poll::Ready(None) => {
+                this.finish(true);
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B: http_body::Body> Drop for CaptureBody<B> {
+    fn drop(&mut self) {
+        // Bodies that are never polled (e.g. empty ones) or aborted midway end up here
+        let complete = self.inner.is_end_stream();
+        self.finish(complete);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use axum::body::to_bytes;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use http_body::Body as _;
+
+    async fn capture(chunks: Vec<&'static str>, limit: usize) -> (Bytes, CapturedBody) {
+        let captured = Arc::new(Mutex::new(None));
+        let sink = captured.clone();
+        let stream = chunked_body(chunks);
+        let body = Body::new(CaptureBody::new(stream, limit, move |c| {
+            *sink.lock().unwrap() = Some(c);
+        }));
+
+        let forwarded = to_bytes(body, usize::MAX).await.unwrap();
+        let captured = captured.lock().unwrap().take().expect("capture should complete");
+        (forwarded, captured)
+    }
+
+    fn chunked_body(chunks: Vec<&'static str>) -> Body {
+        let (tx, rx) = tokio::sync::mpsc::channel::<Result<Frame<Bytes>, axum::Error>>(chunks.len().max(1));
+        for chunk in chunks {
+            tx.try_send(Ok(Frame::data(Bytes::from_static(chunk.as_bytes())))).unwrap();
+        }
+        drop(tx);
+        Body::new(ChannelBody(rx))
+    }
+
+    struct ChannelBody(tokio::sync::mpsc::Receiver<Result<Frame<Bytes>, axum::Error>>);
+
+    impl http_body::Body for ChannelBody {
+        type Data = Bytes;
+        type Error = axum::Error;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+            self.0.poll_recv(cx)
+        }
+    }
+
+    fn patch(op: JsonPatchKind, path: &str, value: Option<Value>) -> JsonPatchOp {
+        JsonPatchOp { op, path: path.to_string(), value }
+    }
+
+    #[test]
+    fn test_json_patch_operations() {
+        let document = br#"{"user": {"name": "Ada", "token": "secret", "tags": ["a", "c"]}, "a/b": 1}"#.to_vec();
+        let patches = [
+            patch(JsonPatchKind::Replace, "/user/name", Some(Value::from("Grace"))),
+            patch(JsonPatchKind::Remove, "/user/token", None),
+            patch(JsonPatchKind::Add, "/user/tags/1", Some(Value::from("b"))),
+            patch(JsonPatchKind::Add, "/user/tags/-", Some(Value::from("d"))),
+            patch(JsonPatchKind::Add, "/user/admin", Some(Value::from(true))),
+            patch(JsonPatchKind::Replace, "/a~1b", Some(Value::from(2))),
+            // Missing targets are skipped
+            patch(JsonPatchKind::Replace, "/user/missing", Some(Value::from(0))),
+            patch(JsonPatchKind::Remove, "/user/tags/9", None),
+        ];
+
+        let patched: Value = serde_json::from_slice(&patch_json(document, &patches)).unwrap();
+
+        let expected = serde_json::json!({
+            "user": {"name": "Grace", "tags": ["a", "b", "c", "d"], "admin": true},
+            "a/b": 2
+        });
+        assert_eq!(patched, expected);
+    }
+
+    #[test]
+    fn test_json_patch_leaves_other_bodies_alone() {
+        let patches = [patch(JsonPatchKind::Remove, "/token", None)];
+
+        assert_eq!(patch_json(b"not json".to_vec(), &patches), "not json");
+    }
+
+    #[tokio::test]
+    async fn test_json_patch_body_passes_large_bodies_through() {
+        let skipped = Arc::new(Mutex::new(false));
+        let flag = skipped.clone();
+        let patches = vec![patch(JsonPatchKind::Remove, "/token", None)];
+        let stream = chunked_body(vec![r#"{"token": "secret", "#, r#""name": "Ada"}"#]);
+        let body = Body::new(JsonPatchBody::new(stream, patches, 8, move || *flag.lock().unwrap() = true));
+
+        let forwarded = to_bytes(body, usize::MAX).await.unwrap();
+
+        assert_eq!(forwarded, r#"{"token": "secret", "name": "Ada"}"#);
+        assert!(*skipped.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_filled_json_patch_body_has_exact_size() {
+        let patches = vec![patch(JsonPatchKind::Remove, "/token", None)];
+        let stream = chunked_body(vec![r#"{"token": "secret", "#, r#""name": "Ada"}"#]);
+        let mut patched = JsonPatchBody::new(stream, patches, 1024, || {});
+
+        patched.fill().await;
+        let body = patched.into_body();
+
+        assert_eq!(body.size_hint().exact(), Some(14));
+        assert_eq!(to_bytes(body, usize::MAX).await.unwrap(), r#"{"name":"Ada"}"#);
+    }
+
+    #[test]
+    fn test_rewrite_path_keeps_query_and_authority() {
+        let pattern = Regex::new("^/v1/(.*)$").unwrap();
+
+        let relative = rewrite_path(&"/v1/users?page=2".parse().unwrap(), &pattern, "/v2/$1");
+        let absolute = rewrite_path(&"http://example.com/v1/users".parse().unwrap(), &pattern, "/v2/$1");
+        let untouched = rewrite_path(&"/health".parse().unwrap(), &pattern, "/v2/$1");
+
+        assert_eq!(relative, "/v2/users?page=2");
+        assert_eq!(absolute, "http://example.com/v2/users");
+        assert_eq!(untouched, "/health");
+    }
+
+    #[tokio::test]
+    async fn test_capture_within_limit() {
+        let (forwarded, captured) = capture(vec!["hello ", "world"], 1024).await;
+
+        assert_eq!(forwarded, "hello world");
+        assert_eq!(captured.data, b"hello world");
+        assert_eq!(captured.size, 11);
+        assert!(!captured.truncated);
+        assert!(captured.complete);
+    }
+
+    #[tokio::test]
+    async fn test_capture_truncates_but_forwards_everything() {
+        let (forwarded, captured) = capture(vec!["hello ", "world"], 8).await;
+
+        assert_eq!(forwarded, "hello world");
+        assert_eq!(captured.data, b"hello wo");
+        assert_eq!(captured.size, 11);
+        assert!(captured.truncated);
+        assert!(captured.complete);
+    }
+
+    #[tokio::test]
+    async fn test_capture_reports_dropped_body_as_incomplete() {
+        let captured = Arc::new(Mutex::new(None));
+        let sink = captured.clone();
+        let mut body = CaptureBody::new(chunked_body(vec!["partial", "rest"]), 1024, move |c| {
+            *sink.lock().unwrap() = Some(c);
+        });
+
+        std::future::poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await;
+        drop(body);
+
+        let captured = captured.lock().unwrap().take().unwrap();
+        assert_eq!(captured.data, b"partial");
+        assert!(!captured.complete);
+    }
+
+    #[tokio::test]
+    async fn test_observer_sees_decoded_body() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"data: hello\n\n").unwrap();
+        let headers = HeaderMap::from_iter([(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"))]);
+        let observed = Arc::new(Mutex::new(Vec::new()));
+        let seen = observed.clone();
+        let body = CaptureBody::new(Body::from(encoder.finish().unwrap()), 1024, |_| {})
+            .with_observer(move |data| seen.lock().unwrap().extend_from_slice(data))
+            .with_decoder(Decoder::new(&headers, 1024));
+
+        to_bytes(Body::new(body), usize::MAX).await.unwrap();
+
+        assert_eq!(*observed.lock().unwrap(), b"data: hello\n\n");
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use axum::body::{Body, Bytes, to_bytes};
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue, Method, StatusCode};
+use axum::response::Response;
+use serde_json::Value;
+
+use crate::config::MockConfig;
+use crate::proxy::routing::glob_matches;
+use crate::utils::errors::AppError;
+
+/// Largest request body read to render a mock template
+const MAX_MOCK_BODY: usize = 1024 * 1024;
+
+/// `[[mocks]]` rules, the first one matching a request answers it
+#[derive(Debug, Default)]
+pub struct Mocks {
+    mocks: Vec<Mock>,
+}
+
+/// A canned response, headers and body are templates rendered against each request
+#[derive(Debug)]
+pub struct Mock {
+    pub name: String,
+    method: Option<Method>,
+    path: String,
+    status: StatusCode,
+    headers: Vec<(HeaderName, String)>,
+    body: String,
+}
+
+impl Mocks {
+    /// Build the rules, reading `body_file` templates from disk
+    pub fn new(configs: &[MockConfig]) -> Result<Self, AppError> {
+        let mocks = configs.iter().map(Mock::new).collect::<Result<_, _>>()?;
+        Ok(Self { mocks })
+    }
+
+    pub fn find(&self, method: &Method, path: &str) -> Option<&Mock> {
+        self.mocks.iter().find(|mock| {
+            mock.method.as_ref().is_none_or(|m| m == method) && glob_matches(&mock.path, path)
+        })
+    }
+}
+
+impl Mock {
+    fn new(config: &MockConfig) -> Result<Self, AppError> {
+        let name = config.name.clone().unwrap_or_else(|| config.path.clone());
+        let invalid = |e: String| AppError::ValidateConfigError(format!("Invalid mock '{}': {}", name, e));
+        let method = config
+            .method
+            .as_deref()
+            .map(|m| Method::from_bytes(m.to_ascii_uppercase().as_bytes()))
+            .transpose()
+            .map_err(|e| invalid(e.to_string()))?;
+        let status = StatusCode::from_u16(config.status).map_err(|e| invalid(e.to_string()))?;
+        let headers = config
+            .headers
+            .iter()
+            .map(|(name, value)| Ok((HeaderName::from_bytes(name.as_bytes())?, value.clone())))
+            .collect::<Result<_, axum::http::header::InvalidHeaderName>>()
+            .map_err(|e| invalid(e.to_string()))?;
+        let body = match (&config.body, &config.body_file) {
+            (_, Some(file)) => std::fs::read_to_string(file)
+                .map_err(|e| invalid(format!("can't read body_file '{}': {}", file, e)))?,
+            (Some(body), None) => body.clone(),
+            (None, None) => String::new(),
+        };
+        Ok(Self { name, method, path: config.path.clone(), status, headers, body })
+    }
+
+    /// Render the canned response for `req`
+    pub async fn respond(&self, req: Request) -> Result<Response, AppError> {
+        let (parts, body) = req.into_parts();
+        let body = to_bytes(body, MAX_MOCK_BODY)
+            .await
+            .map_err(|e| AppError::UpstreamRequestError(format!("Failed to read request body: {}", e)))?;
+        let context = TemplateContext::new(&parts, body);
+
+        let mut response = Response::new(Body::from(render(&self.body, &context)));
+        *response.status_mut() = self.status;
+        for (name, template) in &self.headers {
+            let value = HeaderValue::from_str(&render(template, &context))
+                .map_err(|e| AppError::UpstreamRequestError(format!("Invalid mock header '{}': {}", name, e)))?;
+            response.headers_mut().append(name.clone(), value);
+        }
+        Ok(response)
+    }
+}
+
+/// Request fields available to templates
+struct TemplateContext<'a> {
+    parts: &'a axum::http::request::Parts,
+    query: HashMap<String, String>,
+    body: Bytes,
+    json: Option<Value>,
+}
+
+impl<'a> TemplateContext<'a> {
+    fn new(parts: &'a axum::http::request::Parts, body: Bytes) -> Self {
+        let query = url::form_urlencoded::parse(parts.uri.query().unwrap_or_default().as_bytes())
+            .into_owned()
+            .collect();
+        let json = serde_json::from_slice(&body).ok();
+        Self { parts, query, body, json }
+    }
+
+    /// Value of a `{{...}}` expression, empty when the request has no such field
+    fn lookup(&self, expression: &str) -> String {
+        let (root, key) = match expression.split_once('.') {
+            Some((root, key)) => (root, Some(key)),
+            None => (expression, None),
+        };
+        let value = match (root, key) {
+            ("method", None) => Some(self.parts.method.to_string()),
+            ("path", None) => Some(self.parts.uri.path().to_string()),
+            ("uri", None) => Some(self.parts.uri.to_string()),
+            ("query", Some(name)) => self.query.get(name).cloned(),
+            ("headers", Some(name)) => {
+                self.parts.headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string)
+            }
+            ("segments", Some(index)) => index
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| self.parts.uri.path().split('/').filter(|s| !s.is_empty()).nth(i))
+                .map(str::to_string),
+            ("body", None) => Some(String::from_utf8_lossy(&self.body).into_owned()),
+            ("body", Some(path)) => self
+                .json
+                .as_ref()
+                .and_then(|json| json.pointer(&format!("/{}", path.replace('.', "/"))))
+                .map(|value| match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                }),
+            _ => None,
+        };
+        value.unwrap_or_default()
+    }
+}
+
+/// Replace every `{{expression}}` in `template` with its value for the request
+fn render(template: &str, context: &TemplateContext) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let Some(end) = rest[start + 2..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        rendered.push_str(&context.lookup(rest[start + 2..start + 2 + end].trim()));
+        rest = &rest[start + 2 + end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_for(template: &str, uri: &str, body: &str) -> String {
+        let (parts, _) = Request::post(uri)
+            .header("x-request-id", "abc")
+            .body(())
+            .unwrap()
+            .into_parts();
+        render(template, &TemplateContext::new(&parts, Bytes::from(body.to_string())))
+    }
+
+    #[test]
+    fn test_render_request_fields() {
+        let template = "{{method}} {{ path }} {{query.page}} {{headers.X-Request-Id}} {{segments.2}}";
+
+        let rendered = render_for(template, "/api/users/42?page=2", "");
+
+        assert_eq!(rendered, "POST /api/users/42 2 abc 42");
+    }
+
+    #[test]
+    fn test_render_json_body_fields() {
+        let body = r#"{"user": {"name": "Ada", "tags": ["admin"], "age": 36}}"#;
+        let template = "{{body.user.name}} {{body.user.tags.0}} {{body.user.age}} {{body.missing}}!";
+
+        let rendered = render_for(template, "/", body);
+
+        assert_eq!(rendered, "Ada admin 36 !");
+    }
+
+    #[test]
+    fn test_render_keeps_unclosed_braces() {
+        assert_eq!(render_for("{{method}} {{oops", "/", ""), "POST {{oops");
+    }
+
+    #[test]
+    fn test_first_matching_mock_wins() {
+        let users = MockConfig {
+            method: Some("get".to_string()),
+            path: "/api/users/*".to_string(),
+            ..MockConfig::default()
+        };
+        let catch_all = MockConfig { name: Some("catch-all".to_string()), ..MockConfig::default() };
+        let configs = [users, catch_all];
+        let mocks = Mocks::new(&configs).unwrap();
+
+        assert_eq!(mocks.find(&Method::GET, "/api/users/42").unwrap().name, "/api/users/*");
+        assert_eq!(mocks.find(&Method::POST, "/api/users/42").unwrap().name, "catch-all");
+    }
+}
@@ -0,0 +1,18 @@
+pub mod balancer;
+pub mod breakpoints;
+pub mod cassette;
+pub mod encoding;
+pub mod faults;
+pub mod forward;
+pub mod forwarder;
+pub mod graphql;
+pub mod grpc;
+pub mod headers;
+pub mod interceptor;
+pub mod mocks;
+pub mod network;
+pub mod routing;
+pub mod shadow;
+pub mod sse;
+pub mod unix;
+pub mod websocket;
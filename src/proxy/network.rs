@@ -0,0 +1,207 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::pin::Pin;
+use std::sync::RwLock;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use axum::body::{Body, Bytes};
+use http_body::Frame;
+use serde::Serialize;
+use tokio::time::{Instant, Sleep};
+
+use crate::config::{AppConfig, NetworkProfile};
+use crate::utils::errors::AppError;
+
+/// Simulated network conditions, switchable at runtime through the logger API
+#[derive(Debug)]
+pub struct NetworkConditions {
+    profiles: BTreeMap<String, NetworkProfile>,
+    /// `[[routes]]` and `[hosts]` names a profile can be selected for
+    routes: BTreeSet<String>,
+    selection: RwLock<NetworkSelection>,
+}
+
+/// Profiles currently in use
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NetworkSelection {
+    /// Profile for requests whose route has none of its own, None when unthrottled
+    pub profile: Option<String>,
+    /// Profile by route or host name
+    pub routes: BTreeMap<String, String>,
+}
+
+impl NetworkConditions {
+    /// Profiles and initial selection from `[network]` and the `network_profile` of each route
+    pub fn new(config: &AppConfig) -> Self {
+        let routes = config
+            .routes
+            .iter()
+            .map(|r| r.label().to_string())
+            .chain(config.hosts.keys().map(|h| h.to_ascii_lowercase()))
+            .collect();
+        let selection = NetworkSelection {
+            profile: config.network.profile.clone(),
+            routes: config
+                .routes
+                .iter()
+                .filter_map(|r| Some((r.label().to_string(), r.network_profile.clone()?)))
+                .collect(),
+        };
+        Self {
+            profiles: config.network.all_profiles(),
+            routes,
+            selection: RwLock::new(selection),
+        }
+    }
+
+    pub fn profiles(&self) -> &BTreeMap<String, NetworkProfile> {
+        &self.profiles
+    }
+
+    pub fn selection(&self) -> NetworkSelection {
+        self.selection.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Switch the profile of a route, or the global one without `route`
+    /// None turns the simulation off, or lets the route fall back to the global profile
+    pub fn select(&self, route: Option<&str>, profile: Option<String>) -> Result<NetworkSelection, AppError> {
+        if let Some(profile) = &profile
+            && !self.profiles.contains_key(profile)
+        {
+            return Err(AppError::NetworkProfileError(format!("Unknown profile '{}'", profile)));
+        }
+        let mut selection = self.selection.write().unwrap_or_else(|e| e.into_inner());
+        match (route, profile) {
+            (None, profile) => selection.profile = profile,
+            (Some(route), _) if !self.routes.contains(route) => {
+                return Err(AppError::NetworkProfileError(format!("Unknown route '{}'", route)));
+            }
+            (Some(route), Some(profile)) => {
+                selection.routes.insert(route.to_string(), profile);
+            }
+            (Some(route), None) => {
+                selection.routes.remove(route);
+            }
+        }
+        Ok(selection.clone())
+    }
+
+    /// Profile applied to a request picked by `route`, with its name
+    pub fn profile_for(&self, route: Option<&str>) -> Option<(String, NetworkProfile)> {
+        let selection = self.selection.read().unwrap_or_else(|e| e.into_inner());
+        let name = route
+            .and_then(|route| selection.routes.get(route))
+            .or(selection.profile.as_ref())?;
+        self.profiles.get(name).map(|profile| (name.clone(), *profile))
+    }
+}
+
+/// Response body released no faster than a given throughput
+pub struct ThrottledBody {
+    inner: Body,
+    bytes_per_sec: u64,
+    started: Instant,
+    sent: u64,
+    /// Data received from `inner` and not yet released
+    pending: Bytes,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl ThrottledBody {
+    pub fn new(inner: Body, bandwidth_kbps: u64) -> Self {
+        Self {
+            inner,
+            bytes_per_sec: (bandwidth_kbps * 1000 / 8).max(1),
+            started: Instant::now(),
+            sent: 0,
+            pending: Bytes::new(),
+            delay: None,
+        }
+    }
+}
+
+impl http_body::Body for ThrottledBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        loop {
+            if let Some(delay) = self.delay.as_mut() {
+                ready!(delay.as_mut().poll(cx));
+                self.delay = None;
+            }
+            if self.pending.is_empty() {
+                match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                    Some(Ok(frame)) => match frame.into_data() {
+                        Ok(data) => self.pending = data,
+                        Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                    },
+                    other => return Poll::Ready(other),
+                }
+                if self.pending.is_empty() {
+                    continue;
+                }
+            }
+
+            // Each chunk may only leave once the previous ones had time to go through
+            let due = self.started + Duration::from_secs_f64(self.sent as f64 / self.bytes_per_sec as f64);
+            if due > Instant::now() {
+                self.delay = Some(Box::pin(tokio::time::sleep_until(due)));
+                continue;
+            }
+            // Chunks of a tenth of a second keep the flow steady
+            let size = self.pending.len().min((self.bytes_per_sec / 10).max(1) as usize);
+            let chunk = self.pending.split_to(size);
+            self.sent += chunk.len() as u64;
+            return Poll::Ready(Some(Ok(Frame::data(chunk))));
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_empty() && self.inner.is_end_stream()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RouteConfig;
+
+    fn conditions() -> NetworkConditions {
+        let route = RouteConfig {
+            name: Some("users-api".to_string()),
+            prefix: "/api".to_string(),
+            target: "http://localhost:8081".to_string(),
+            strip_prefix: false,
+            network_profile: Some("satellite".to_string()),
+            shadow_target: None,
+        };
+        let config = AppConfig {
+            routes: vec![route],
+            hosts: BTreeMap::from([("web.localtest.me".to_string(), "http://localhost:5173".to_string())]),
+            ..AppConfig::default()
+        };
+        NetworkConditions::new(&config)
+    }
+
+    #[test]
+    fn test_route_profile_wins_over_global() {
+        let network = conditions();
+        network.select(None, Some("3g".to_string())).unwrap();
+
+        assert_eq!(network.profile_for(Some("users-api")).unwrap().0, "satellite");
+        assert_eq!(network.profile_for(Some("web.localtest.me")).unwrap().0, "3g");
+        assert_eq!(network.profile_for(None).unwrap().0, "3g");
+
+        network.select(Some("users-api"), None).unwrap();
+        assert_eq!(network.profile_for(Some("users-api")).unwrap().0, "3g");
+    }
+
+    #[test]
+    fn test_select_rejects_unknown_names() {
+        let network = conditions();
+
+        assert!(network.select(None, Some("dial-up".to_string())).is_err());
+        assert!(network.select(Some("orders-api"), Some("3g".to_string())).is_err());
+        assert!(network.profile_for(None).is_none());
+    }
+}
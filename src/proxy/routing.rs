@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, HashMap};
+use axum::http::Uri;
+use axum::http::uri::Authority;
+
+use crate::config::RouteConfig;
+use crate::proxy::balancer::{Pools, Upstream};
+use crate::utils::errors::AppError;
+
+/// A `[[routes]]` entry ready for matching
+#[derive(Clone, Debug)]
+pub struct Route {
+    /// Label recorded on captured exchanges
+    pub name: String,
+    prefix: String,
+    pub target: Upstream,
+    /// Receives a copy of every request sent to `target`
+    pub shadow: Option<Upstream>,
+    strip_prefix: bool,
+}
+
+impl Route {
+    pub fn new(config: &RouteConfig, pools: &Pools) -> Result<Self, AppError> {
+        Ok(Self {
+            name: config.label().to_string(),
+            prefix: config.prefix.trim_end_matches('/').to_string(),
+            target: Upstream::parse(&config.target, pools)?,
+            shadow: config.shadow_target.as_deref().map(|shadow| Upstream::parse(shadow, pools)).transpose()?,
+            strip_prefix: config.strip_prefix,
+        })
+    }
+
+    /// True if `path` is the prefix itself or lies below it
+    pub fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(&self.prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// Request URI as the route's target should see it, without the prefix if `strip_prefix` is set
+    pub fn rewrite(&self, uri: &Uri) -> Result<Uri, AppError> {
+        let Some(rest) = uri.path().strip_prefix(&self.prefix).filter(|_| self.strip_prefix) else {
+            return Ok(uri.clone());
+        };
+        let path = if rest.is_empty() { "/" } else { rest };
+        let rewritten = match uri.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.to_string(),
+        };
+        rewritten
+            .parse()
+            .map_err(|e| AppError::UpstreamRequestError(format!("Invalid rewritten URI '{}': {}", rewritten, e)))
+    }
+}
+
+/// Routes checked against every request path, the longest matching prefix wins
+#[derive(Clone, Debug, Default)]
+pub struct Routes {
+    routes: Vec<Route>,
+}
+
+impl Routes {
+    pub fn new(configs: &[RouteConfig], pools: &Pools) -> Result<Self, AppError> {
+        let mut routes = configs
+            .iter()
+            .map(|config| Route::new(config, pools))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Stable sort keeps the configured order between routes with the same prefix
+        routes.sort_by_key(|r| std::cmp::Reverse(r.prefix.len()));
+        Ok(Self { routes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Route for `path`, if any
+    pub fn find(&self, path: &str) -> Option<&Route> {
+        self.routes.iter().find(|r| r.matches(path))
+    }
+}
+
+/// `[hosts]` entries, matched against the Host of each request
+#[derive(Clone, Debug, Default)]
+pub struct VirtualHosts {
+    hosts: HashMap<String, Upstream>,
+}
+
+impl VirtualHosts {
+    pub fn new(config: &BTreeMap<String, String>, pools: &Pools) -> Result<Self, AppError> {
+        let hosts = config
+            .iter()
+            .map(|(host, target)| Ok((host.to_ascii_lowercase(), Upstream::parse(target, pools)?)))
+            .collect::<Result<_, AppError>>()?;
+        Ok(Self { hosts })
+    }
+
+    /// Configured host name and target for a `Host` header value, the port is ignored
+    pub fn find(&self, host: &str) -> Option<(&str, &Upstream)> {
+        let authority = host.parse::<Authority>().ok()?;
+        self.hosts
+            .get_key_value(&authority.host().to_ascii_lowercase())
+            .map(|(host, url)| (host.as_str(), url))
+    }
+}
+
+/// Match a path against a glob where `*` stays within a segment, `**` spans segments and `?` is one character
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    fn matches(pattern: &[u8], path: &[u8]) -> bool {
+        match pattern {
+            [] => path.is_empty(),
+            [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| matches(rest, &path[i..])),
+            [b'*', rest @ ..] => (0..=path.len())
+                .take_while(|&i| i == 0 || path[i - 1] != b'/')
+                .any(|i| matches(rest, &path[i..])),
+            [b'?', rest @ ..] => path.first().is_some_and(|&c| c != b'/') && matches(rest, &path[1..]),
+            [c, rest @ ..] => path.first() == Some(c) && matches(rest, &path[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), path.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(prefix: &str, strip_prefix: bool) -> RouteConfig {
+        RouteConfig {
+            name: None,
+            prefix: prefix.to_string(),
+            target: "http://localhost:8081".to_string(),
+            strip_prefix,
+            network_profile: None,
+            shadow_target: None,
+        }
+    }
+
+    #[test]
+    fn test_longest_prefix_wins_on_segment_boundaries() {
+        let configs = [route("/", false), route("/api", false), route("/api/users/", false)];
+        let routes = Routes::new(&configs, &Pools::new()).unwrap();
+
+        assert_eq!(routes.find("/api/users/42").unwrap().name, "/api/users/");
+        assert_eq!(routes.find("/api").unwrap().name, "/api");
+        assert_eq!(routes.find("/apis").unwrap().name, "/");
+        assert_eq!(routes.find("/").unwrap().name, "/");
+    }
+
+    #[test]
+    fn test_no_route_without_catch_all() {
+        let routes = Routes::new(&[route("/api", false)], &Pools::new()).unwrap();
+
+        assert!(routes.find("/app").is_none());
+    }
+
+    #[test]
+    fn test_virtual_hosts_ignore_port_and_case() {
+        let config = BTreeMap::from([("api.localtest.me".to_string(), "http://localhost:8081".to_string())]);
+        let hosts = VirtualHosts::new(&config, &Pools::new()).unwrap();
+
+        let (name, target) = hosts.find("API.localtest.me:3000").unwrap();
+        assert_eq!(name, "api.localtest.me");
+        assert!(matches!(target, Upstream::Url(url) if url.as_str() == "http://localhost:8081/"));
+        assert!(hosts.find("web.localtest.me").is_none());
+        assert!(hosts.find("localtest.me").is_none());
+    }
+
+    #[test]
+    fn test_rewrite_strips_prefix_and_keeps_query() {
+        let strip = Route::new(&route("/api/", true), &Pools::new()).unwrap();
+        let keep = Route::new(&route("/api", false), &Pools::new()).unwrap();
+
+        assert_eq!(strip.rewrite(&"/api/users?page=2".parse().unwrap()).unwrap(), "/users?page=2");
+        assert_eq!(strip.rewrite(&"/api".parse().unwrap()).unwrap(), "/");
+        assert_eq!(keep.rewrite(&"/api/users".parse().unwrap()).unwrap(), "/api/users");
+    }
+
+    #[test]
+    fn test_glob_segments() {
+        assert!(glob_matches("/api/users/*", "/api/users/42"));
+        assert!(!glob_matches("/api/users/*", "/api/users/42/orders"));
+        assert!(glob_matches("/api/**", "/api/users/42/orders"));
+        assert!(glob_matches("/api/**", "/api/"));
+        assert!(glob_matches("/files/*.json", "/files/data.json"));
+        assert!(!glob_matches("/files/*.json", "/files/data.xml"));
+        assert!(glob_matches("/v?/users", "/v2/users"));
+        assert!(!glob_matches("/api", "/api/users"));
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::time::Instant;
+use axum::response::Response;
+use http_body::Body as _;
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tracing::info;
+
+use crate::proxy::encoding::Decoder;
+use crate::proxy::interceptor::{CaptureBody, InFlight, Interceptor};
+use crate::storage::LogStore;
+use crate::storage::models::{CapturedBody, CapturedExchange, Difference, DifferenceKind, ShadowExchange, header_pairs};
+use crate::utils::errors::AppError;
+
+/// Headers expected to differ between any two responses, left out of the diff
+const IGNORED_HEADERS: [&str; 1] = ["date"];
+
+/// Compares the answer of a shadow target with the primary response of the same exchange
+pub struct Comparison {
+    store: LogStore,
+    exchange_id: u64,
+    body_limit: usize,
+    started: Instant,
+    primary: oneshot::Receiver<()>,
+}
+
+impl Comparison {
+    pub fn new(interceptor: &Interceptor, in_flight: &mut InFlight) -> Self {
+        Self {
+            store: interceptor.store().clone(),
+            exchange_id: in_flight.id,
+            body_limit: interceptor.body_limit(),
+            started: Instant::now(),
+            primary: in_flight.settled(),
+        }
+    }
+
+    /// Read the shadow response, wait for the primary one to complete, then store both side by side
+    /// A request that wasn't mirrored at all is stored right away, with nothing to compare
+    pub async fn finish(self, backend: String, answer: Result<Response, AppError>) {
+        let mut shadow = ShadowExchange {
+            backend,
+            status: None,
+            response_headers: Vec::new(),
+            response_body: CapturedBody::default(),
+            duration_ms: None,
+            error: None,
+            diff: Vec::new(),
+            truncated: false,
+        };
+        match answer {
+            Ok(response) => {
+                shadow.duration_ms = Some(self.started.elapsed().as_millis() as u64);
+                shadow.status = Some(response.status().as_u16());
+                shadow.response_headers = header_pairs(response.headers());
+                let (parts, body) = response.into_parts();
+                let (sender, captured) = oneshot::channel();
+                let mut body = CaptureBody::new(body, self.body_limit, move |captured| {
+                    let _ = sender.send(captured);
+                })
+                .with_decoder(Decoder::new(&parts.headers, self.body_limit));
+                // Only the captured copy is kept, the rest of the body is read and dropped
+                while let Some(frame) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+                    if let Err(e) = frame {
+                        shadow.error = Some(format!("Failed to read shadow body: {}", e));
+                        break;
+                    }
+                }
+                drop(body);
+                shadow.response_body = captured.await.unwrap_or_default();
+            }
+            Err(e @ AppError::NotMirrored(_)) => {
+                shadow.error = Some(e.to_string());
+                self.store.update(self.exchange_id, |e| e.shadow = Some(shadow));
+                return;
+            }
+            Err(e) => shadow.error = Some(e.to_string()),
+        }
+
+        // Resolves with an error when the primary exchange failed, there is still something to compare
+        let _ = self.primary.await;
+        let Some(primary) = self.store.get(self.exchange_id) else {
+            return;
+        };
+        shadow.diff = diff(&primary, &shadow);
+        shadow.truncated = primary.response_body.truncated || shadow.response_body.truncated;
+        info!("Shadow response of exchange {} has {} differences", self.exchange_id, shadow.diff.len());
+        self.store.update(self.exchange_id, |e| e.shadow = Some(shadow));
+    }
+}
+
+/// Structural differences between the primary and the shadow response
+/// JSON bodies are compared value by value, any other body as a whole
+pub fn diff(primary: &CapturedExchange, shadow: &ShadowExchange) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    compare(&mut differences, "/status".to_string(), Some(&primary.status.into()), Some(&shadow.status.into()));
+
+    let primary_headers = headers(&primary.response_headers);
+    let shadow_headers = headers(&shadow.response_headers);
+    let primary_headers = Value::Object(primary_headers.into_iter().collect());
+    let shadow_headers = Value::Object(shadow_headers.into_iter().collect());
+    compare(&mut differences, "/headers".to_string(), Some(&primary_headers), Some(&shadow_headers));
+
+    let primary_body = body(&primary.response_body);
+    let shadow_body = body(&shadow.response_body);
+    compare(&mut differences, "/body".to_string(), Some(&primary_body), Some(&shadow_body));
+    differences
+}
+
+fn compare(differences: &mut Vec<Difference>, path: String, primary: Option<&Value>, shadow: Option<&Value>) {
+    match (primary, shadow) {
+        (Some(Value::Object(primary)), Some(Value::Object(shadow))) => {
+            let mut keys: Vec<&String> = primary.keys().chain(shadow.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                compare(differences, path, primary.get(key), shadow.get(key));
+            }
+        }
+        (Some(Value::Array(primary)), Some(Value::Array(shadow))) => {
+            for index in 0..primary.len().max(shadow.len()) {
+                compare(differences, format!("{}/{}", path, index), primary.get(index), shadow.get(index));
+            }
+        }
+        (primary, shadow) if primary == shadow => {}
+        (primary, shadow) => {
+            let kind = match (primary, shadow) {
+                (None, _) => DifferenceKind::Added,
+                (_, None) => DifferenceKind::Removed,
+                _ => DifferenceKind::Changed,
+            };
+            differences.push(Difference { path, kind, primary: primary.cloned(), shadow: shadow.cloned() });
+        }
+    }
+}
+
+/// Headers by lowercase name, repeated ones joined with ", "
+fn headers(pairs: &[(String, String)]) -> BTreeMap<String, Value> {
+    let mut headers: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for (name, value) in pairs {
+        let name = name.to_ascii_lowercase();
+        if !IGNORED_HEADERS.contains(&name.as_str()) {
+            headers.entry(name).or_default().push(value);
+        }
+    }
+    headers.into_iter().map(|(name, values)| (name, Value::String(values.join(", ")))).collect()
+}
+
+/// Parsed JSON body, or the body as text when it is not JSON or was truncated
+fn body(body: &CapturedBody) -> Value {
+    match serde_json::from_slice(&body.data) {
+        Ok(value) if !body.truncated && !body.data.is_empty() => value,
+        _ => Value::String(String::from_utf8_lossy(&body.data).into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderMap;
+    use serde_json::json;
+
+    fn response(status: u16, headers: &[(&str, &str)], body: &str) -> ShadowExchange {
+        ShadowExchange {
+            backend: "http://localhost:9090".to_string(),
+            status: Some(status),
+            response_headers: headers.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+            response_body: CapturedBody::buffered(body.as_bytes(), 1024),
+            duration_ms: None,
+            error: None,
+            diff: Vec::new(),
+            truncated: false,
+        }
+    }
+
+    fn differences(primary: ShadowExchange, shadow: ShadowExchange) -> Vec<Difference> {
+        let mut exchange = CapturedExchange::new("GET", "/", &HeaderMap::new());
+        exchange.status = primary.status;
+        exchange.response_headers = primary.response_headers;
+        exchange.response_body = primary.response_body;
+        diff(&exchange, &shadow)
+    }
+
+    #[test]
+    fn test_identical_responses_have_no_diff() {
+        let primary = response(200, &[("content-type", "application/json"), ("date", "Mon")], r#"{"a": [1, 2]}"#);
+        let shadow = response(200, &[("Content-Type", "application/json"), ("date", "Tue")], r#"{"a":[1,2]}"#);
+
+        assert_eq!(differences(primary, shadow), vec![]);
+    }
+
+    #[test]
+    fn test_json_bodies_are_compared_structurally() {
+        let primary = r#"{"user": {"name": "Ada", "a/b": 1}, "tags": ["x", "y"]}"#;
+        let shadow = r#"{"user": {"name": "Grace", "a/b": 1}, "tags": ["x"], "new": true}"#;
+        let primary = response(200, &[("x-version", "1")], primary);
+        let shadow = response(201, &[("x-build", "2")], shadow);
+
+        let differences = differences(primary, shadow);
+
+        let paths: Vec<(&str, DifferenceKind)> = differences.iter().map(|d| (d.path.as_str(), d.kind)).collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                ("/status", DifferenceKind::Changed),
+                ("/headers/x-build", DifferenceKind::Added),
+                ("/headers/x-version", DifferenceKind::Removed),
+                ("/body/new", DifferenceKind::Added),
+                ("/body/tags/1", DifferenceKind::Removed),
+                ("/body/user/name", DifferenceKind::Changed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_text_bodies_are_compared_whole() {
+        let primary = response(200, &[], "hello");
+        let shadow = response(200, &[], "hello world");
+
+        let differences = differences(primary, shadow);
+
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].primary, Some(json!("hello")));
+        assert_eq!(differences[0].shadow, Some(json!("hello world")));
+    }
+}
@@ -0,0 +1,195 @@
+use axum::http::{HeaderMap, header};
+
+use crate::storage::LogStore;
+use crate::storage::models::{SseEvent, unix_millis};
+
+/// Events stored per exchange before further ones are only counted
+pub const MAX_EVENTS_PER_EXCHANGE: usize = 10_000;
+
+/// True if the response is a Server-Sent Events stream
+pub fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("text/event-stream"))
+}
+
+/// Incremental `text/event-stream` parser
+/// Follows the WHATWG event stream interpretation: an event is dispatched on each blank line
+pub struct SseParser {
+    store: LogStore,
+    exchange_id: u64,
+    data_limit: usize,
+    /// Line being read, kept up to `line_limit` bytes, the rest of a longer line is dropped
+    line: Vec<u8>,
+    last_was_cr: bool,
+    event: Option<String>,
+    id: Option<String>,
+    data: String,
+}
+
+impl SseParser {
+    /// `data_limit` is the maximum number of data bytes kept per event
+    pub fn new(store: LogStore, exchange_id: u64, data_limit: usize) -> Self {
+        Self {
+            store,
+            exchange_id,
+            data_limit,
+            line: Vec::new(),
+            last_was_cr: false,
+            event: None,
+            id: None,
+            data: String::new(),
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) {
+        for &byte in chunk {
+            match byte {
+                b'\n' if self.last_was_cr => self.last_was_cr = false,
+                b'\r' | b'\n' => {
+                    self.last_was_cr = byte == b'\r';
+                    let line = std::mem::take(&mut self.line);
+                    self.process_line(&String::from_utf8_lossy(&line));
+                }
+                _ => {
+                    self.last_was_cr = false;
+                    if self.line.len() < self.line_limit() {
+                        self.line.push(byte);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Longest line worth keeping, a `data: ` field holding `data_limit` bytes
+    fn line_limit(&self) -> usize {
+        self.data_limit + "data: ".len()
+    }
+
+    fn process_line(&mut self, line: &str) {
+        if line.is_empty() {
+            self.dispatch();
+            return;
+        }
+        if line.starts_with(':') {
+            return;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.id = Some(value.to_string()),
+            "data" if self.data.len() <= self.data_limit => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self) {
+        let event = self.event.take();
+        let mut data = std::mem::take(&mut self.data);
+        if data.is_empty() {
+            return;
+        }
+        data.pop();
+        if data.len() > self.data_limit {
+            let mut end = self.data_limit;
+            while !data.is_char_boundary(end) {
+                end -= 1;
+            }
+            data.truncate(end);
+        }
+
+        let event = SseEvent {
+            timestamp_ms: unix_millis(),
+            event,
+            id: self.id.clone(),
+            data,
+        };
+        self.store.update(self.exchange_id, |e| {
+            if e.sse_events.len() < MAX_EVENTS_PER_EXCHANGE {
+                e.sse_events.push(event);
+            } else {
+                e.sse_events_dropped += 1;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::models::CapturedExchange;
+
+    fn parse(chunks: &[&str]) -> Vec<SseEvent> {
+        let store = LogStore::default();
+        let id = store.insert(CapturedExchange::new("GET", "/events", &HeaderMap::new()));
+        let mut parser = SseParser::new(store.clone(), id, 1024);
+        for chunk in chunks {
+            parser.feed(chunk.as_bytes());
+        }
+        store.get(id).unwrap().sse_events
+    }
+
+    #[test]
+    fn test_parse_events_with_fields() {
+        let events = parse(&["event: token\nid: 1\ndata: hel", "lo\n\n", ": keep-alive\n\ndata: a\ndata: b\n\n"]);
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event.as_deref(), Some("token"));
+        assert_eq!(events[0].id.as_deref(), Some("1"));
+        assert_eq!(events[0].data, "hello");
+        assert_eq!(events[1].event, None);
+        assert_eq!(events[1].id.as_deref(), Some("1"));
+        assert_eq!(events[1].data, "a\nb");
+    }
+
+    #[test]
+    fn test_parse_crlf_split_across_chunks() {
+        let events = parse(&["data: first\r", "\n\r", "\ndata: second\r\r"]);
+
+        let data: Vec<&str> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_event_without_data_is_not_dispatched() {
+        let events = parse(&["event: ping\n\n", "data\n\n"]);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, None);
+        assert_eq!(events[0].data, "");
+    }
+
+    #[test]
+    fn test_long_line_is_cut_at_data_limit() {
+        let store = LogStore::default();
+        let id = store.insert(CapturedExchange::new("GET", "/events", &HeaderMap::new()));
+        let mut parser = SseParser::new(store.clone(), id, 8);
+
+        parser.feed(b"data: 0123456789");
+        parser.feed("abcdef".repeat(1000).as_bytes());
+        parser.feed(b"\n\ndata: next\n\n");
+
+        assert!(parser.line.is_empty());
+        let events = store.get(id).unwrap().sse_events;
+        let data: Vec<&str> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["01234567", "next"]);
+    }
+
+    #[test]
+    fn test_is_event_stream() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "text/event-stream; charset=utf-8".parse().unwrap());
+
+        assert!(is_event_stream(&headers));
+        headers.insert(header::CONTENT_TYPE, "text/plain".parse().unwrap());
+        assert!(!is_event_stream(&headers));
+    }
+}
@@ -0,0 +1,204 @@
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use axum::http::Uri;
+use hyper::rt::{Read, ReadBufCursor, Write};
+use hyper_rustls::{HttpsConnector, MaybeHttpsStream};
+use hyper_util::client::legacy::connect::{Connected, Connection, HttpConnector};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tower::Service;
+use url::{Position, Url};
+
+/// Scheme of targets reached over a Unix domain socket, e.g. `unix:///run/app.sock`
+pub const UNIX_SCHEME: &str = "unix";
+
+/// Socket path and HTTP base path of a `unix://` target
+/// `unix:///run/app.sock:/api` is the socket `/run/app.sock` with requests sent under `/api`
+pub fn unix_target(target: &Url) -> Option<(&str, &str)> {
+    if target.scheme() != UNIX_SCHEME {
+        return None;
+    }
+    Some(target.path().split_once(':').unwrap_or((target.path(), "")))
+}
+
+/// Origin recorded for a target, the socket URL for `unix://` targets
+pub fn origin(target: &Url) -> String {
+    match unix_target(target) {
+        Some((socket, _)) => format!("{}://{}", UNIX_SCHEME, socket),
+        None => target[..Position::AfterPort].to_string(),
+    }
+}
+
+/// Authority of the request URIs sent to a socket
+/// The client pools connections by authority, so the socket path goes there, hex encoded
+pub fn socket_authority(socket: &str) -> String {
+    socket.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn socket_path(uri: &Uri) -> Option<String> {
+    let hex = uri.host()?.as_bytes();
+    let bytes = hex
+        .chunks(2)
+        .map(|pair| std::str::from_utf8(pair).ok().and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Connector of the upstream client
+/// `unix://` URIs are connected to their socket, anything else goes through TCP and TLS as usual
+#[derive(Clone, Debug)]
+pub struct UpstreamConnector {
+    https: HttpsConnector<HttpConnector>,
+    /// Bound of socket connects, TCP connects are bounded by the wrapped `HttpConnector`
+    connect_timeout: Option<Duration>,
+}
+
+impl UpstreamConnector {
+    pub fn new(https: HttpsConnector<HttpConnector>, connect_timeout: Option<Duration>) -> Self {
+        Self { https, connect_timeout }
+    }
+}
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+impl Service<Uri> for UpstreamConnector {
+    type Response = UpstreamStream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<UpstreamStream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.https.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        if uri.scheme_str() != Some(UNIX_SCHEME) {
+            let connecting = self.https.call(uri);
+            return Box::pin(async move { connecting.await.map(|stream| UpstreamStream::Tcp(Box::new(stream))) });
+        }
+        let limit = self.connect_timeout;
+        Box::pin(async move {
+            let path = socket_path(&uri)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("No socket path in '{}'", uri)))?;
+            connect_socket(&path, limit).await
+        })
+    }
+}
+
+/// Connect to the socket at `path`, failing with `TimedOut` like TCP connects once `limit` is over
+#[cfg(unix)]
+async fn connect_socket(path: &str, limit: Option<Duration>) -> Result<UpstreamStream, BoxError> {
+    let stream = match limit {
+        Some(limit) => tokio::time::timeout(limit, UnixStream::connect(path)).await.map_err(|_| {
+            io::Error::new(io::ErrorKind::TimedOut, format!("Connecting to '{}' timed out after {:?}", path, limit))
+        })??,
+        None => UnixStream::connect(path).await?,
+    };
+    Ok(UpstreamStream::Unix(TokioIo::new(stream)))
+}
+
+#[cfg(not(unix))]
+async fn connect_socket(path: &str, _limit: Option<Duration>) -> Result<UpstreamStream, BoxError> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, format!("Unix domain sockets are not supported here: '{}'", path)).into())
+}
+
+/// Connection to the target, over TCP (with or without TLS) or a Unix domain socket
+pub enum UpstreamStream {
+    Tcp(Box<MaybeHttpsStream<TokioIo<TcpStream>>>),
+    #[cfg(unix)]
+    Unix(TokioIo<UnixStream>),
+}
+
+impl Connection for UpstreamStream {
+    fn connected(&self) -> Connected {
+        match self {
+            UpstreamStream::Tcp(stream) => stream.connected(),
+            #[cfg(unix)]
+            UpstreamStream::Unix(stream) => stream.connected(),
+        }
+    }
+}
+
+impl Read for UpstreamStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: ReadBufCursor<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            UpstreamStream::Tcp(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+            #[cfg(unix)]
+            UpstreamStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl Write for UpstreamStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            UpstreamStream::Tcp(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+            #[cfg(unix)]
+            UpstreamStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            UpstreamStream::Tcp(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+            #[cfg(unix)]
+            UpstreamStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            UpstreamStream::Tcp(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+            #[cfg(unix)]
+            UpstreamStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            UpstreamStream::Tcp(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            UpstreamStream::Unix(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            UpstreamStream::Tcp(stream) => Pin::new(stream.as_mut()).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            UpstreamStream::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unix_target_splits_socket_and_path() {
+        let plain = Url::parse("unix:///run/app.sock").unwrap();
+        let with_path = Url::parse("unix:///var/run/docker.sock:/v1.43").unwrap();
+
+        assert_eq!(unix_target(&plain), Some(("/run/app.sock", "")));
+        assert_eq!(unix_target(&with_path), Some(("/var/run/docker.sock", "/v1.43")));
+        assert_eq!(unix_target(&Url::parse("http://localhost:8080").unwrap()), None);
+        assert_eq!(origin(&with_path), "unix:///var/run/docker.sock");
+    }
+
+    #[test]
+    fn test_socket_path_round_trips_through_authority() {
+        let uri: Uri = format!("unix://{}/containers/json", socket_authority("/var/run/docker.sock")).parse().unwrap();
+
+        assert_eq!(socket_path(&uri).as_deref(), Some("/var/run/docker.sock"));
+    }
+}
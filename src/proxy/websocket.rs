@@ -0,0 +1,305 @@
+use hyper::upgrade::OnUpgrade;
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{info, warn};
+
+use crate::storage::LogStore;
+use crate::storage::models::{Direction, WebSocketFrame, unix_millis};
+
+/// Frames stored per exchange before further ones are only counted
+pub const MAX_FRAMES_PER_EXCHANGE: usize = 10_000;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+/// Close, ping and pong, they may come between the fragments of a message
+const OPCODE_CONTROL: u8 = 0x8;
+
+/// Where tunnelled frames are recorded
+#[derive(Clone, Debug)]
+pub struct FrameRecorder {
+    pub store: LogStore,
+    pub exchange_id: u64,
+    /// Maximum bytes of text payload kept per frame
+    pub text_limit: usize,
+}
+
+impl FrameRecorder {
+    fn record(&self, frame: WebSocketFrame) {
+        self.store.update(self.exchange_id, |e| {
+            if e.websocket_frames.len() < MAX_FRAMES_PER_EXCHANGE {
+                e.websocket_frames.push(frame);
+            } else {
+                e.websocket_frames_dropped += 1;
+            }
+        });
+    }
+}
+
+/// True if the headers request a WebSocket handshake
+pub fn is_websocket(headers: &axum::http::HeaderMap) -> bool {
+    headers
+        .get(axum::http::header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+}
+
+/// Wait for both sides of a protocol switch and pipe bytes between them untouched
+/// When `recorder` is set, the byte streams are parsed as WebSocket frames and recorded
+pub async fn tunnel(client: OnUpgrade, upstream: OnUpgrade, recorder: Option<FrameRecorder>) {
+    let (client, upstream) = match tokio::try_join!(client, upstream) {
+        Ok(io) => io,
+        Err(e) => {
+            warn!("Upgrade failed: {}", e);
+            return;
+        }
+    };
+
+    let (client_read, client_write) = tokio::io::split(TokioIo::new(client));
+    let (upstream_read, upstream_write) = tokio::io::split(TokioIo::new(upstream));
+
+    let outbound = pipe(
+        client_read,
+        upstream_write,
+        recorder.clone().map(|r| FrameParser::new(Direction::ClientToServer, r)),
+    );
+    let inbound = pipe(
+        upstream_read,
+        client_write,
+        recorder.map(|r| FrameParser::new(Direction::ServerToClient, r)),
+    );
+
+    let (sent, received) = tokio::join!(outbound, inbound);
+    info!("Tunnel closed after {} bytes sent and {} bytes received", sent, received);
+}
+
+/// Copy bytes from `reader` to `writer` until either side closes, feeding the optional parser
+pub(crate) async fn pipe<R, W>(mut reader: R, mut writer: W, mut parser: Option<FrameParser>) -> u64
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; 16 * 1024];
+    let mut total = 0u64;
+    loop {
+        let n = match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        if writer.write_all(&buf[..n]).await.is_err() {
+            break;
+        }
+        total += n as u64;
+        if let Some(parser) = parser.as_mut() {
+            parser.feed(&buf[..n]);
+        }
+    }
+    let _ = writer.shutdown().await;
+    total
+}
+
+/// Incremental WebSocket frame parser (RFC 6455 section 5.2)
+/// Only observes the stream, it never alters the bytes being forwarded
+pub struct FrameParser {
+    direction: Direction,
+    recorder: FrameRecorder,
+    header: Vec<u8>,
+    current: Option<PendingFrame>,
+    /// A fragmented text message is in progress, its continuation frames are kept as text too
+    text_message: bool,
+}
+
+struct PendingFrame {
+    frame: WebSocketFrame,
+    mask: Option<[u8; 4]>,
+    remaining: u64,
+    offset: u64,
+    text: Option<Vec<u8>>,
+}
+
+impl FrameParser {
+    pub fn new(direction: Direction, recorder: FrameRecorder) -> Self {
+        Self { direction, recorder, header: Vec::with_capacity(14), current: None, text_message: false }
+    }
+
+    pub fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            match self.current.as_mut() {
+                Some(pending) => {
+                    let take = data.len().min(pending.remaining as usize);
+                    if let Some(text) = pending.text.as_mut() {
+                        let room = self.recorder.text_limit.saturating_sub(text.len()).min(take);
+                        for (i, byte) in data[..room].iter().enumerate() {
+                            let key = pending.mask.map_or(0, |m| m[((pending.offset + i as u64) % 4) as usize]);
+                            text.push(byte ^ key);
+                        }
+                    }
+                    pending.offset += take as u64;
+                    pending.remaining -= take as u64;
+                    data = &data[take..];
+                }
+                None => {
+                    self.header.push(data[0]);
+                    data = &data[1..];
+                    if let Some(pending) = self.parse_header() {
+                        self.header.clear();
+                        if pending.frame.opcode < OPCODE_CONTROL {
+                            self.text_message = pending.text.is_some() && !pending.frame.fin;
+                        }
+                        self.current = Some(pending);
+                    }
+                }
+            }
+            if self.current.as_ref().is_some_and(|p| p.remaining == 0) {
+                self.finish_frame();
+            }
+        }
+    }
+
+    /// Parse the buffered header bytes, returning None while more are needed
+    fn parse_header(&self) -> Option<PendingFrame> {
+        let h = &self.header;
+        if h.len() < 2 {
+            return None;
+        }
+        let masked = h[1] & 0x80 != 0;
+        let (size, mut needed) = match h[1] & 0x7f {
+            126 => (None, 4),
+            127 => (None, 10),
+            n => (Some(n as u64), 2),
+        };
+        if masked {
+            needed += 4;
+        }
+        if h.len() < needed {
+            return None;
+        }
+        let size = size.unwrap_or_else(|| {
+            h[2..needed - if masked { 4 } else { 0 }]
+                .iter()
+                .fold(0u64, |acc, b| (acc << 8) | *b as u64)
+        });
+        let mask = masked.then(|| [h[needed - 4], h[needed - 3], h[needed - 2], h[needed - 1]]);
+
+        let opcode = h[0] & 0x0f;
+        let compressed = h[0] & 0x40 != 0;
+        Some(PendingFrame {
+            frame: WebSocketFrame {
+                direction: self.direction,
+                timestamp_ms: unix_millis(),
+                opcode,
+                fin: h[0] & 0x80 != 0,
+                compressed,
+                size,
+                text: None,
+            },
+            mask,
+            remaining: size,
+            offset: 0,
+            text: match opcode {
+                OPCODE_TEXT => !compressed,
+                OPCODE_CONTINUATION => self.text_message,
+                _ => false,
+            }
+            .then(Vec::new),
+        })
+    }
+
+    fn finish_frame(&mut self) {
+        if let Some(mut pending) = self.current.take() {
+            pending.frame.text = pending.text.map(|t| String::from_utf8_lossy(&t).into_owned());
+            self.recorder.record(pending.frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderMap;
+    use crate::storage::models::CapturedExchange;
+
+    fn recorder() -> FrameRecorder {
+        let store = LogStore::default();
+        let exchange_id = store.insert(CapturedExchange::new("GET", "/ws", &HeaderMap::new()));
+        FrameRecorder { store, exchange_id, text_limit: 1024 }
+    }
+
+    fn frames(recorder: &FrameRecorder) -> Vec<WebSocketFrame> {
+        recorder.store.get(recorder.exchange_id).unwrap().websocket_frames
+    }
+
+    #[test]
+    fn test_parse_masked_text_frame_split_across_reads() {
+        let recorder = recorder();
+        let mut parser = FrameParser::new(Direction::ClientToServer, recorder.clone());
+        let mask = [1u8, 2, 3, 4];
+        let payload: Vec<u8> = b"hello".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]).collect();
+        let mut bytes = vec![0x81, 0x80 | 5];
+        bytes.extend_from_slice(&mask);
+        bytes.extend_from_slice(&payload);
+
+        for chunk in bytes.chunks(3) {
+            parser.feed(chunk);
+        }
+
+        let frames = frames(&recorder);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].opcode, 1);
+        assert!(frames[0].fin);
+        assert_eq!(frames[0].size, 5);
+        assert_eq!(frames[0].text.as_deref(), Some("hello"));
+        assert_eq!(frames[0].direction, Direction::ClientToServer);
+    }
+
+    #[test]
+    fn test_parse_extended_length_binary_and_close() {
+        let recorder = recorder();
+        let mut parser = FrameParser::new(Direction::ServerToClient, recorder.clone());
+        let mut bytes = vec![0x82, 126, 0x01, 0x00];
+        bytes.extend(std::iter::repeat_n(0xab, 256));
+        bytes.extend_from_slice(&[0x88, 0x02, 0x03, 0xe8]);
+
+        parser.feed(&bytes);
+
+        let frames = frames(&recorder);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].opcode, 2);
+        assert_eq!(frames[0].size, 256);
+        assert!(frames[0].text.is_none());
+        assert_eq!(frames[1].opcode, 8);
+        assert_eq!(frames[1].size, 2);
+    }
+
+    #[test]
+    fn test_continuation_frames_of_a_text_message_keep_their_text() {
+        let recorder = recorder();
+        let mut parser = FrameParser::new(Direction::ServerToClient, recorder.clone());
+        let mut bytes = vec![0x01, 0x03];
+        bytes.extend_from_slice(b"hel");
+        // A ping may come between fragments
+        bytes.extend_from_slice(&[0x89, 0x00]);
+        bytes.extend_from_slice(&[0x80, 0x02]);
+        bytes.extend_from_slice(b"lo");
+        // Continuation of a binary message
+        bytes.extend_from_slice(&[0x02, 0x01, 0xff, 0x80, 0x01, 0xfe]);
+
+        parser.feed(&bytes);
+
+        let frames = frames(&recorder);
+        let texts: Vec<Option<&str>> = frames.iter().map(|f| f.text.as_deref()).collect();
+        assert_eq!(texts, vec![Some("hel"), None, Some("lo"), None, None]);
+        assert_eq!(frames[2].opcode, 0);
+        assert!(frames[2].fin);
+    }
+
+    #[test]
+    fn test_empty_frames_are_recorded() {
+        let recorder = recorder();
+        let mut parser = FrameParser::new(Direction::ServerToClient, recorder.clone());
+
+        parser.feed(&[0x89, 0x00, 0x8a, 0x00]);
+
+        let opcodes: Vec<u8> = frames(&recorder).iter().map(|f| f.opcode).collect();
+        assert_eq!(opcodes, vec![9, 10]);
+    }
+}
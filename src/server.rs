@@ -0,0 +1,114 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request};
+use axum::http::Version;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+use tracing::{debug, warn};
+
+/// HTTP/2 stream identifier of the request, inserted as a request extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamId(pub u32);
+
+/// Response extension asking the server to abort the connection instead of sending the response
+/// Nothing reaches the client, not even the status line (HTTP/2 streams are reset)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DropConnection;
+
+/// Scheme the client used to reach the proxy, inserted as a request extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    Http,
+    Https,
+}
+
+impl Scheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Http => "http",
+            Scheme::Https => "https",
+        }
+    }
+}
+
+/// Accept connections on `listener` and serve `app` on each of them
+/// HTTP/1.1 and prior-knowledge HTTP/2 (h2c) are detected per connection
+pub async fn serve(listener: TcpListener, app: Router) -> std::io::Result<()> {
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        tokio::spawn(serve_connection(stream, remote, Scheme::Http, app.clone()));
+    }
+}
+
+/// Same as `serve`, but every connection goes through a TLS handshake first
+pub async fn serve_tls(listener: TcpListener, acceptor: TlsAcceptor, app: Router) -> std::io::Result<()> {
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            match acceptor.accept(stream).await {
+                Ok(tls) => serve_connection(tls, remote, Scheme::Https, app).await,
+                Err(e) => debug!("TLS handshake with {} failed: {}", remote, e),
+            }
+        });
+    }
+}
+
+/// Serve a single accepted connection until the client closes it
+pub async fn serve_connection<I>(io: I, remote: SocketAddr, scheme: Scheme, app: Router)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // Client-initiated HTTP/2 streams use odd ids in increasing order and hyper
+    // hands them to the service in that order, so the id follows from a counter
+    let streams = Arc::new(AtomicU32::new(0));
+    let service = hyper::service::service_fn(move |mut req: Request<Incoming>| {
+        req.extensions_mut().insert(ConnectInfo(remote));
+        req.extensions_mut().insert(scheme);
+        if req.version() == Version::HTTP_2 {
+            let n = streams.fetch_add(1, Ordering::Relaxed);
+            req.extensions_mut().insert(StreamId(2 * n + 1));
+        }
+        let app = app.clone();
+        async move {
+            let response = match app.oneshot(req.map(Body::new)).await {
+                Ok(response) => response,
+                Err(infallible) => match infallible {},
+            };
+            // A service error makes hyper close the connection before writing anything
+            if response.extensions().get::<DropConnection>().is_some() {
+                return Err(std::io::Error::new(ErrorKind::ConnectionAborted, "Connection dropped"));
+            }
+            Ok(response)
+        }
+    });
+
+    if let Err(e) = auto::Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(TokioIo::new(io), service)
+        .await
+    {
+        debug!("Connection from {} closed with error: {}", remote, e);
+    }
+}
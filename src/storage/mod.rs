@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+pub mod models;
+
+use crate::storage::models::CapturedExchange;
+
+/// Default number of exchanges kept before the oldest ones are evicted
+pub const DEFAULT_MAX_ENTRIES: usize = 50_000;
+
+/// In-memory store for captured exchanges
+/// Cheap to clone, all clones share the same entries
+#[derive(Clone, Debug)]
+pub struct LogStore {
+    inner: Arc<RwLock<StoreInner>>,
+}
+
+#[derive(Debug)]
+struct StoreInner {
+    entries: VecDeque<CapturedExchange>,
+    next_id: u64,
+    max_entries: usize,
+}
+
+impl LogStore {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(StoreInner {
+                entries: VecDeque::new(),
+                next_id: 1,
+                max_entries: max_entries.max(1),
+            })),
+        }
+    }
+
+    /// Insert a new exchange and return the id assigned to it
+    pub fn insert(&self, mut exchange: CapturedExchange) -> u64 {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        let id = inner.next_id;
+        inner.next_id += 1;
+        exchange.id = id;
+
+        if inner.entries.len() >= inner.max_entries {
+            inner.entries.pop_front();
+        }
+        inner.entries.push_back(exchange);
+        id
+    }
+
+    /// Apply `f` to the exchange with the given id
+    /// Returns false if it was evicted or never existed
+    pub fn update<F: FnOnce(&mut CapturedExchange)>(&self, id: u64, f: F) -> bool {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        match Self::position(&inner.entries, id) {
+            Some(index) => {
+                f(&mut inner.entries[index]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<CapturedExchange> {
+        let inner = self.inner.read().unwrap_or_else(|e| e.into_inner());
+        Self::position(&inner.entries, id).map(|index| inner.entries[index].clone())
+    }
+
+    /// Most recent exchanges first, at most `limit` of them
+    pub fn recent(&self, limit: usize) -> Vec<CapturedExchange> {
+        self.recent_matching(limit, |_| true)
+    }
+
+    /// Most recent exchanges accepted by `filter` first, at most `limit` of them
+    pub fn recent_matching<F: Fn(&CapturedExchange) -> bool>(&self, limit: usize, filter: F) -> Vec<CapturedExchange> {
+        let inner = self.inner.read().unwrap_or_else(|e| e.into_inner());
+        inner.entries.iter().rev().filter(|e| filter(e)).take(limit).cloned().collect()
+    }
+
+    /// Visit every stored exchange, oldest first, without copying them
+    pub fn scan<F: FnMut(&CapturedExchange)>(&self, mut f: F) {
+        let inner = self.inner.read().unwrap_or_else(|e| e.into_inner());
+        inner.entries.iter().for_each(&mut f);
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap_or_else(|e| e.into_inner()).entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ids are handed out in increasing order, so entries stay sorted by id
+    fn position(entries: &VecDeque<CapturedExchange>, id: u64) -> Option<usize> {
+        entries.binary_search_by_key(&id, |e| e.id).ok()
+    }
+}
+
+impl Default for LogStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderMap;
+
+    fn exchange(uri: &str) -> CapturedExchange {
+        CapturedExchange::new("GET", uri, &HeaderMap::new())
+    }
+
+    #[test]
+    fn test_insert_assigns_increasing_ids() {
+        let store = LogStore::default();
+
+        let first = store.insert(exchange("/a"));
+        let second = store.insert(exchange("/b"));
+
+        assert_eq!(first, 1);
+        assert_eq!(second, 2);
+        assert_eq!(store.get(second).unwrap().uri, "/b");
+    }
+
+    #[test]
+    fn test_update_existing_exchange() {
+        let store = LogStore::default();
+        let id = store.insert(exchange("/a"));
+
+        assert!(store.update(id, |e| e.status = Some(204)));
+        assert!(!store.update(id + 1, |e| e.status = Some(500)));
+
+        assert_eq!(store.get(id).unwrap().status, Some(204));
+    }
+
+    #[test]
+    fn test_oldest_entries_are_evicted() {
+        let store = LogStore::new(2);
+        let first = store.insert(exchange("/a"));
+        store.insert(exchange("/b"));
+        store.insert(exchange("/c"));
+
+        assert_eq!(store.len(), 2);
+        assert!(store.get(first).is_none());
+        let uris: Vec<String> = store.recent(10).into_iter().map(|e| e.uri).collect();
+        assert_eq!(uris, vec!["/c", "/b"]);
+    }
+}
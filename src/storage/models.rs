@@ -0,0 +1,431 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize, Serializer};
+
+/// A single request/response pair captured by the proxy
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedExchange {
+    pub id: u64,
+    /// Unix timestamp (milliseconds) at which the request reached the proxy
+    pub timestamp_ms: u64,
+    pub method: String,
+    pub uri: String,
+    /// Protocol version spoken by the client, e.g. "HTTP/1.1" or "HTTP/2.0"
+    pub http_version: String,
+    /// HTTP/2 stream carrying the request on the client connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_id: Option<u32>,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: CapturedBody,
+    pub status: Option<u16>,
+    /// Protocol version of the upstream response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_http_version: Option<String>,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: CapturedBody,
+    /// Time until the upstream response headers arrived
+    pub duration_ms: Option<u64>,
+    pub error: Option<String>,
+    /// `[hosts]` name or `[[routes]]` name that picked the upstream
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+    /// Origin of the target or pool member the request was sent to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Frames exchanged after a WebSocket handshake, in the order they were seen
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub websocket_frames: Vec<WebSocketFrame>,
+    /// Frames seen but not stored once the per-exchange limit was reached
+    #[serde(skip_serializing_if = "is_zero")]
+    pub websocket_frames_dropped: u64,
+    /// Events of a `text/event-stream` response, in the order they were received
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sse_events: Vec<SseEvent>,
+    /// Events seen but not stored once the per-exchange limit was reached
+    #[serde(skip_serializing_if = "is_zero")]
+    pub sse_events_dropped: u64,
+    /// CONNECT tunnel opened by this request in forward-proxy mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tunnel: Option<Tunnel>,
+    /// Each try at sending the request to the upstream, in order, a single one unless it was retried
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<UpstreamAttempt>,
+    /// Failure injected by a `[[faults]]` rule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fault: Option<InjectedFault>,
+    /// Network profile simulated for this exchange
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_profile: Option<String>,
+    /// `[[mocks]]` rule that answered instead of the target
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mock: Option<String>,
+    /// Answered from the cassette given to `--replay`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub replayed: bool,
+    /// `[[rewrites]]` rules applied, request rules first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rewrites: Vec<String>,
+    /// JSON patches of those rules left out, with the reason
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_patches: Vec<String>,
+    /// Times the exchange was held at a breakpoint, and how each hold ended
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub breakpoints: Vec<BreakpointHit>,
+    /// Answer of the shadow target, set once both it and the primary response are complete
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowExchange>,
+    /// Messages and status of a gRPC call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grpc: Option<GrpcCall>,
+    /// GraphQL operations sent in the request, several for a batch, and the errors each returned
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub graphql: Vec<GraphqlOperation>,
+}
+
+impl CapturedExchange {
+    /// Create a pending exchange from the incoming request line and headers
+    pub fn new(method: &str, uri: &str, headers: &HeaderMap) -> Self {
+        Self {
+            id: 0,
+            timestamp_ms: unix_millis(),
+            method: method.to_string(),
+            uri: uri.to_string(),
+            http_version: "HTTP/1.1".to_string(),
+            stream_id: None,
+            request_headers: header_pairs(headers),
+            request_body: CapturedBody::default(),
+            status: None,
+            upstream_http_version: None,
+            response_headers: Vec::new(),
+            response_body: CapturedBody::default(),
+            duration_ms: None,
+            error: None,
+            route: None,
+            backend: None,
+            websocket_frames: Vec::new(),
+            websocket_frames_dropped: 0,
+            sse_events: Vec::new(),
+            sse_events_dropped: 0,
+            tunnel: None,
+            attempts: Vec::new(),
+            fault: None,
+            network_profile: None,
+            mock: None,
+            replayed: false,
+            rewrites: Vec::new(),
+            skipped_patches: Vec::new(),
+            breakpoints: Vec::new(),
+            shadow: None,
+            grpc: None,
+            graphql: Vec::new(),
+        }
+    }
+}
+
+/// Captured copy of a request or response body
+/// Only the first `limit` bytes are kept, `size` always counts the full body as it was sent
+/// Compressed bodies are kept decoded, `truncated` then refers to the decoded body
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CapturedBody {
+    #[serde(serialize_with = "serialize_lossy")]
+    pub data: Vec<u8>,
+    pub size: u64,
+    pub truncated: bool,
+    /// False while the body is still streaming or if the stream was aborted
+    pub complete: bool,
+    /// `Content-Encoding` undone for the stored copy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// Length of the body once decoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decoded_size: Option<u64>,
+    /// Why the body could not be decoded, `data` then holds it as sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decode_error: Option<String>,
+    /// Trailer fields sent after the body
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub trailers: Vec<(String, String)>,
+}
+
+impl CapturedBody {
+    /// Capture of a body that was read in full
+    pub fn buffered(data: &[u8], limit: usize) -> Self {
+        Self {
+            data: data[..data.len().min(limit)].to_vec(),
+            size: data.len() as u64,
+            truncated: data.len() > limit,
+            complete: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// Which way a message travelled through the proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+/// A single WebSocket frame tunnelled through the proxy
+#[derive(Debug, Clone, Serialize)]
+pub struct WebSocketFrame {
+    pub direction: Direction,
+    /// Unix timestamp (milliseconds) at which the frame header was seen
+    pub timestamp_ms: u64,
+    /// Raw opcode (0 continuation, 1 text, 2 binary, 8 close, 9 ping, 10 pong)
+    pub opcode: u8,
+    pub fin: bool,
+    /// RSV1 set, i.e. the payload is compressed by permessage-deflate
+    pub compressed: bool,
+    /// Payload length in bytes
+    pub size: u64,
+    /// Unmasked payload of uncompressed text frames, up to the body capture limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// Metadata of a CONNECT tunnel
+/// Requests inside intercepted tunnels are captured as exchanges of their own,
+/// bytes are only counted for tunnels relayed without decryption
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Tunnel {
+    /// Decrypted with a certificate issued by the local CA
+    pub intercepted: bool,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// False while the tunnel is still open
+    pub closed: bool,
+}
+
+/// One try at sending a request to the upstream
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamAttempt {
+    /// 1 for the original request, 2 for the first retry, ...
+    pub attempt: u32,
+    /// Unix timestamp (milliseconds) at which the attempt was sent
+    pub timestamp_ms: u64,
+    /// Time until the response headers or the error
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What a `[[faults]]` rule did to an exchange
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InjectedFault {
+    /// Name of the rule, or its path glob
+    pub rule: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub drop_connection: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncate_body: Option<u64>,
+}
+
+/// Side of an exchange held at a breakpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeldPhase {
+    Request,
+    Response,
+}
+
+/// How a hold at a breakpoint ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldOutcome {
+    /// Let through unchanged
+    Resumed,
+    /// Let through with changes
+    Edited,
+    /// Answered with a response written at the breakpoint
+    Answered,
+    /// Connection closed without an answer
+    Dropped,
+    /// Nobody decided in time, let through unchanged
+    TimedOut,
+}
+
+/// A hold at a breakpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakpointHit {
+    pub breakpoint: u64,
+    pub phase: HeldPhase,
+    pub outcome: HoldOutcome,
+}
+
+/// Response of the shadow target to a mirrored request
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowExchange {
+    /// Origin of the shadow target or pool member the copy was sent to
+    pub backend: String,
+    pub status: Option<u16>,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: CapturedBody,
+    /// Time until the shadow response headers arrived
+    pub duration_ms: Option<u64>,
+    pub error: Option<String>,
+    /// How the shadow response differs from the primary one, empty when they match
+    pub diff: Vec<Difference>,
+    /// A body was cut at the capture limit, its diff only covers the bytes kept
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// One difference between the primary and the shadow response
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Difference {
+    /// JSON pointer below `/status`, `/headers` or `/body`
+    pub path: String,
+    pub kind: DifferenceKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DifferenceKind {
+    /// Only in the shadow response
+    Added,
+    /// Only in the primary response
+    Removed,
+    Changed,
+}
+
+/// A gRPC call, its messages split out of the length-prefixed bodies
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GrpcCall {
+    /// Fully qualified service name, e.g. "helloworld.Greeter"
+    pub service: String,
+    pub method: String,
+    /// Messages sent by the client, in order
+    pub request_messages: Vec<GrpcMessage>,
+    /// Messages sent by the server, in order
+    pub response_messages: Vec<GrpcMessage>,
+    /// Messages seen but not stored once the per-exchange limit was reached
+    #[serde(skip_serializing_if = "is_zero")]
+    pub messages_dropped: u64,
+    /// `grpc-status` of the trailers, or of the headers of a trailers-only response
+    pub status: Option<u32>,
+    /// `grpc-message`, percent-decoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl GrpcCall {
+    pub fn new(service: &str, method: &str) -> Self {
+        Self { service: service.to_string(), method: method.to_string(), ..Self::default() }
+    }
+}
+
+/// A single length-prefixed gRPC message
+#[derive(Debug, Clone, Serialize)]
+pub struct GrpcMessage {
+    /// Unix timestamp (milliseconds) at which the message was complete
+    pub timestamp_ms: u64,
+    /// Compressed flag of the length prefix
+    pub compressed: bool,
+    /// Length of the message as framed
+    pub size: u64,
+    /// Only the first bytes up to the body capture limit were kept, or it decompresses to more, so it was not decoded
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Message decoded with the configured descriptor set, in the protobuf JSON mapping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json: Option<serde_json::Value>,
+    /// Base64 of the message when it was not decoded to JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Why a message of a known type could not be decoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decode_error: Option<String>,
+}
+
+/// A GraphQL operation, so exchanges sent to a single endpoint can be told apart
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GraphqlOperation {
+    /// `operationName` of the request, or the name of the only operation in its document
+    pub operation_name: Option<String>,
+    /// Type of the operation run, unknown when a POST sends a persisted query without its document
+    pub operation_type: Option<OperationType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variables: Option<serde_json::Value>,
+    /// Hash or id of a persisted query sent instead of the document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persisted_query: Option<String>,
+    /// Top-level `errors` of the response, of its own response for an operation of a batch
+    pub errors: Vec<GraphqlError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationType {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+/// An entry of the `errors` of a GraphQL response
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphqlError {
+    pub message: String,
+    /// Path of the field that failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<serde_json::Value>,
+    /// `extensions.code`, e.g. "UNAUTHENTICATED"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+/// A single Server-Sent Event relayed to the client
+#[derive(Debug, Clone, Serialize)]
+pub struct SseEvent {
+    /// Unix timestamp (milliseconds) at which the event was complete
+    pub timestamp_ms: u64,
+    /// `event:` field, absent for default "message" events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    /// Last `id:` seen on the stream, as the client would report it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// `data:` lines joined with newlines, up to the body capture limit
+    pub data: String,
+}
+
+/// Flatten a header map into (name, value) pairs, keeping repeated headers
+pub fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.as_str().to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Milliseconds since the Unix epoch
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+fn serialize_lossy<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&String::from_utf8_lossy(data))
+}
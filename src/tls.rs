@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use rcgen::{
+    BasicConstraints, CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa,
+    Issuer, KeyPair, KeyUsagePurpose, SanType,
+};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, aws_lc_rs, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme};
+use time::{Duration, OffsetDateTime};
+use tokio_rustls::TlsAcceptor;
+use tracing::{info, warn};
+use x509_parser::pem::parse_x509_pem;
+
+use crate::config::{TlsConfig, UpstreamTlsConfig};
+use crate::utils::errors::AppError;
+
+const CA_COMMON_NAME: &str = "Endpoint Logger Local CA";
+const CA_CERT_FILE: &str = "ca.pem";
+const CA_KEY_FILE: &str = "ca-key.pem";
+const LEAF_CERT_FILE: &str = "localhost.pem";
+const LEAF_KEY_FILE: &str = "localhost-key.pem";
+
+/// Browsers reject leaf certificates valid for longer than 398 days
+const LEAF_VALIDITY_DAYS: i64 = 397;
+const CA_VALIDITY_DAYS: i64 = 10 * 365;
+/// The persisted leaf is reissued once it gets this close to expiring
+const LEAF_RENEW_DAYS: i64 = 30;
+
+/// Per-host certificates of intercepted tunnels kept at once
+const MAX_SITE_CERTIFICATES: usize = 1024;
+
+/// Names the generated leaf certificate is valid for
+const LOCAL_NAMES: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+
+/// Certificate authority generated on first use and kept in `cert_dir`
+/// Trust `ca.pem` once and every certificate it issues is accepted
+pub struct LocalCa {
+    cert_pem: String,
+    issuer: Issuer<'static, KeyPair>,
+}
+
+impl LocalCa {
+    /// Load the CA from `dir`, generating and saving a new one if it doesn't exist yet
+    pub fn load_or_create(dir: &Path) -> Result<Self, AppError> {
+        let cert_path = dir.join(CA_CERT_FILE);
+        let key_path = dir.join(CA_KEY_FILE);
+
+        if cert_path.exists() && key_path.exists() {
+            let cert_pem = read(&cert_path)?;
+            let key = KeyPair::from_pem(&read(&key_path)?)
+                .map_err(|e| AppError::TlsError(format!("Invalid CA key '{}': {}", key_path.display(), e)))?;
+            // The issuer only needs the subject and key, which are the same for every generated CA
+            return Ok(Self { cert_pem, issuer: Issuer::new(Self::params(), key) });
+        }
+
+        let key = KeyPair::generate().map_err(|e| AppError::TlsError(e.to_string()))?;
+        let mut params = Self::params();
+        let now = OffsetDateTime::now_utc();
+        params.not_before = now - Duration::days(1);
+        params.not_after = now + Duration::days(CA_VALIDITY_DAYS);
+        let cert = params.self_signed(&key).map_err(|e| AppError::TlsError(e.to_string()))?;
+
+        write(&cert_path, &cert.pem(), false)?;
+        write(&key_path, &key.serialize_pem(), true)?;
+        info!("Generated local CA at {}, trust it to avoid certificate warnings", cert_path.display());
+
+        Ok(Self { cert_pem: cert.pem(), issuer: Issuer::new(params, key) })
+    }
+
+    /// PEM encoded CA certificate
+    pub fn cert_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    /// Whether `cert_pem` was signed by this CA and stays valid for more than `LEAF_RENEW_DAYS`
+    fn is_current(&self, cert_pem: &str) -> bool {
+        let (Ok((_, leaf)), Ok((_, ca))) = (parse_x509_pem(cert_pem.as_bytes()), parse_x509_pem(self.cert_pem.as_bytes()))
+        else {
+            return false;
+        };
+        let (Ok(leaf), Ok(ca)) = (leaf.parse_x509(), ca.parse_x509()) else {
+            return false;
+        };
+        let renew_at = OffsetDateTime::now_utc() + Duration::days(LEAF_RENEW_DAYS);
+        leaf.validity().not_after.timestamp() > renew_at.unix_timestamp()
+            && leaf.verify_signature(Some(ca.public_key())).is_ok()
+    }
+
+    /// Issue a server certificate for the given host names or IP addresses
+    pub fn issue(&self, names: &[&str]) -> Result<(String, String), AppError> {
+        let key = KeyPair::generate().map_err(|e| AppError::TlsError(e.to_string()))?;
+        let mut params = CertificateParams::new(Vec::new()).map_err(|e| AppError::TlsError(e.to_string()))?;
+        params.subject_alt_names = names
+            .iter()
+            .map(|name| match name.parse() {
+                Ok(ip) => Ok(SanType::IpAddress(ip)),
+                Err(_) => name.to_string().try_into().map(SanType::DnsName),
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|e| AppError::TlsError(format!("Invalid certificate name: {}", e)))?;
+        params.distinguished_name.push(DnType::CommonName, names.first().copied().unwrap_or("localhost"));
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        params.use_authority_key_identifier_extension = true;
+        let now = OffsetDateTime::now_utc();
+        params.not_before = now - Duration::days(1);
+        params.not_after = now + Duration::days(LEAF_VALIDITY_DAYS);
+
+        let cert = params
+            .signed_by(&key, &self.issuer)
+            .map_err(|e| AppError::TlsError(e.to_string()))?;
+        // Serve the chain so clients trusting the CA can verify the leaf
+        Ok((format!("{}{}", cert.pem(), self.cert_pem), key.serialize_pem()))
+    }
+
+    fn params() -> CertificateParams {
+        let mut params = CertificateParams::default();
+        let mut name = DistinguishedName::new();
+        name.push(DnType::CommonName, CA_COMMON_NAME);
+        name.push(DnType::OrganizationName, "Endpoint Logger");
+        params.distinguished_name = name;
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign, KeyUsagePurpose::DigitalSignature];
+        params
+    }
+}
+
+/// Certificates presented to clients of intercepted CONNECT tunnels
+/// Each host gets a certificate issued by the local CA on first use, which is then reused
+pub struct SiteCertificates {
+    ca: Arc<LocalCa>,
+    acceptors: Mutex<SiteCache>,
+}
+
+impl SiteCertificates {
+    pub fn new(ca: LocalCa) -> Self {
+        Self { ca: Arc::new(ca), acceptors: Mutex::new(SiteCache::new(MAX_SITE_CERTIFICATES)) }
+    }
+
+    /// TLS acceptor presenting a certificate for `host`
+    pub async fn acceptor(&self, host: &str) -> Result<TlsAcceptor, AppError> {
+        if let Some(acceptor) = self.cache().get(host) {
+            return Ok(acceptor);
+        }
+        // Key generation is slow, so it runs off the runtime threads and without the lock held
+        let (ca, name) = (self.ca.clone(), host.to_string());
+        let acceptor = tokio::task::spawn_blocking(move || {
+            let (cert_pem, key_pem) = ca.issue(&[&name])?;
+            Ok::<_, AppError>(TlsAcceptor::from(Arc::new(server_config(&cert_pem, &key_pem)?)))
+        })
+        .await
+        .map_err(|e| AppError::TlsError(format!("Failed to issue a certificate for '{}': {}", host, e)))??;
+        self.cache().insert(host.to_string(), acceptor.clone());
+        Ok(acceptor)
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, SiteCache> {
+        self.acceptors.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Acceptors by host, the least recently used one is dropped once `capacity` is reached
+/// so clients naming endless hosts can't grow it without bound
+struct SiteCache {
+    capacity: usize,
+    entries: HashMap<String, (TlsAcceptor, u64)>,
+    clock: u64,
+}
+
+impl SiteCache {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, entries: HashMap::new(), clock: 0 }
+    }
+
+    fn get(&mut self, host: &str) -> Option<TlsAcceptor> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(host).map(|(acceptor, used)| {
+            *used = clock;
+            acceptor.clone()
+        })
+    }
+
+    fn insert(&mut self, host: String, acceptor: TlsAcceptor) {
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&host) {
+            let oldest = self.entries.iter().min_by_key(|(_, (_, used))| *used).map(|(host, _)| host.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(host, (acceptor, self.clock));
+    }
+}
+
+/// Build the TLS acceptor for the proxy listener
+/// Uses the configured certificate, or a localhost certificate issued by the local CA
+pub fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor, AppError> {
+    let (cert_pem, key_pem) = match (&config.cert_path, &config.key_path) {
+        (Some(cert), Some(key)) => (read(Path::new(cert))?, read(Path::new(key))?),
+        (None, None) => local_certificate(Path::new(&config.cert_dir))?,
+        _ => {
+            return Err(AppError::TlsError(
+                "Both cert_path and key_path must be set in [tls], or neither to generate a local certificate"
+                    .to_string(),
+            ));
+        }
+    };
+    Ok(TlsAcceptor::from(Arc::new(server_config(&cert_pem, &key_pem)?)))
+}
+
+/// rustls server config advertising HTTP/2 and HTTP/1.1 through ALPN
+pub fn server_config(cert_pem: &str, key_pem: &str) -> Result<ServerConfig, AppError> {
+    let certs = CertificateDer::pem_slice_iter(cert_pem.as_bytes())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::TlsError(format!("Invalid certificate: {}", e)))?;
+    let key = PrivateKeyDer::from_pem_slice(key_pem.as_bytes())
+        .map_err(|e| AppError::TlsError(format!("Invalid private key: {}", e)))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| AppError::TlsError(e.to_string()))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| AppError::TlsError(e.to_string()))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// rustls client config used for https targets
+/// Trusts the public web roots plus `ca_bundle`, and presents the client certificate if configured
+pub fn client_config(config: &UpstreamTlsConfig) -> Result<ClientConfig, AppError> {
+    let provider = Arc::new(aws_lc_rs::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| AppError::TlsError(e.to_string()))?;
+
+    let builder = if config.insecure_skip_verify {
+        warn!("insecure_skip_verify is enabled: upstream TLS certificates are NOT verified");
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+    } else {
+        let mut roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        if let Some(bundle) = &config.ca_bundle {
+            let pem = read(Path::new(bundle))?;
+            for cert in CertificateDer::pem_slice_iter(pem.as_bytes()) {
+                let cert = cert.map_err(|e| AppError::TlsError(format!("Invalid CA bundle '{}': {}", bundle, e)))?;
+                roots
+                    .add(cert)
+                    .map_err(|e| AppError::TlsError(format!("Invalid CA bundle '{}': {}", bundle, e)))?;
+            }
+        }
+        builder.with_root_certificates(roots)
+    };
+
+    match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => {
+            let certs = CertificateDer::pem_slice_iter(read(Path::new(cert))?.as_bytes())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| AppError::TlsError(format!("Invalid client certificate '{}': {}", cert, e)))?;
+            let key = PrivateKeyDer::from_pem_slice(read(Path::new(key))?.as_bytes())
+                .map_err(|e| AppError::TlsError(format!("Invalid client key '{}': {}", key, e)))?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .map_err(|e| AppError::TlsError(e.to_string()))
+        }
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => Err(AppError::TlsError(
+            "Both client_cert and client_key must be set in [upstream.tls]".to_string(),
+        )),
+    }
+}
+
+/// Certificate verifier for `insecure_skip_verify`
+/// Any certificate is accepted, but handshake signatures are still checked
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Load the persisted localhost certificate, issuing it from the local CA the first time
+/// It is reissued when it is about to expire or wasn't signed by the current CA
+fn local_certificate(dir: &Path) -> Result<(String, String), AppError> {
+    let ca = LocalCa::load_or_create(dir)?;
+    let cert_path: PathBuf = dir.join(LEAF_CERT_FILE);
+    let key_path: PathBuf = dir.join(LEAF_KEY_FILE);
+    let renewing = cert_path.exists() && key_path.exists();
+    if renewing {
+        let cert_pem = read(&cert_path)?;
+        if ca.is_current(&cert_pem) {
+            return Ok((cert_pem, read(&key_path)?));
+        }
+    }
+
+    let (cert_pem, key_pem) = ca.issue(&LOCAL_NAMES)?;
+    write(&cert_path, &cert_pem, false)?;
+    write(&key_path, &key_pem, true)?;
+    if renewing {
+        info!("Reissued expiring or outdated localhost certificate at {}", cert_path.display());
+    } else {
+        info!("Generated localhost certificate at {}", cert_path.display());
+    }
+    Ok((cert_pem, key_pem))
+}
+
+fn read(path: &Path) -> Result<String, AppError> {
+    fs::read_to_string(path).map_err(|e| AppError::TlsError(format!("Failed to read '{}': {}", path.display(), e)))
+}
+
+/// Write a PEM file, creating its directory; private keys are only readable by the owner
+fn write(path: &Path, contents: &str, private: bool) -> Result<(), AppError> {
+    let err = |e: std::io::Error| AppError::TlsError(format!("Failed to write '{}': {}", path.display(), e));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(err)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    options.open(path).and_then(|mut f| f.write_all(contents.as_bytes())).map_err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("endpoint-logger-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    #[test]
+    fn test_local_certificate_is_generated_once() {
+        let dir = temp_dir("tls-once");
+
+        let (first_cert, first_key) = local_certificate(&dir).expect("Should generate certificate");
+        let (second_cert, second_key) = local_certificate(&dir).expect("Should reuse certificate");
+
+        assert_eq!(first_cert, second_cert);
+        assert_eq!(first_key, second_key);
+        assert!(dir.join(CA_CERT_FILE).exists());
+        assert!(server_config(&first_cert, &first_key).is_ok());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_expired_local_certificate_is_reissued() {
+        let dir = temp_dir("tls-expired");
+        let ca = LocalCa::load_or_create(&dir).expect("Should create CA");
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        let now = OffsetDateTime::now_utc();
+        params.not_before = now - Duration::days(LEAF_VALIDITY_DAYS + 1);
+        params.not_after = now - Duration::days(1);
+        let expired = params.signed_by(&key, &ca.issuer).unwrap().pem();
+        write(&dir.join(LEAF_CERT_FILE), &expired, false).unwrap();
+        write(&dir.join(LEAF_KEY_FILE), &key.serialize_pem(), true).unwrap();
+        assert!(!ca.is_current(&expired));
+
+        let (cert, key) = local_certificate(&dir).expect("Should reissue certificate");
+
+        assert_ne!(cert, expired);
+        assert!(ca.is_current(&cert));
+        assert_eq!(read(&dir.join(LEAF_CERT_FILE)).unwrap(), cert);
+        assert!(server_config(&cert, &key).is_ok());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_local_certificate_from_another_ca_is_reissued() {
+        let dir = temp_dir("tls-foreign");
+        let (first_cert, _) = local_certificate(&dir).expect("Should generate certificate");
+        fs::remove_file(dir.join(CA_CERT_FILE)).unwrap();
+        fs::remove_file(dir.join(CA_KEY_FILE)).unwrap();
+
+        let (second_cert, _) = local_certificate(&dir).expect("Should reissue certificate");
+        let ca = LocalCa::load_or_create(&dir).expect("Should load CA");
+
+        assert_ne!(first_cert, second_cert);
+        assert!(!ca.is_current(&first_cert));
+        assert!(ca.is_current(&second_cert));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_site_cache_drops_least_recently_used() {
+        let dir = temp_dir("tls-site-cache");
+        let (cert, key) = LocalCa::load_or_create(&dir).unwrap().issue(&["a.test"]).unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config(&cert, &key).unwrap()));
+        let mut cache = SiteCache::new(2);
+
+        cache.insert("a.test".to_string(), acceptor.clone());
+        cache.insert("b.test".to_string(), acceptor.clone());
+        assert!(cache.get("a.test").is_some());
+        cache.insert("c.test".to_string(), acceptor);
+
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get("a.test").is_some());
+        assert!(cache.get("b.test").is_none());
+        assert!(cache.get("c.test").is_some());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_reloaded_ca_keeps_issuing() {
+        let dir = temp_dir("tls-reload");
+        let ca = LocalCa::load_or_create(&dir).expect("Should create CA");
+        let reloaded = LocalCa::load_or_create(&dir).expect("Should load CA");
+
+        assert_eq!(ca.cert_pem(), reloaded.cert_pem());
+        let (cert, key) = reloaded.issue(&["api.localtest.me"]).expect("Should issue certificate");
+        assert!(server_config(&cert, &key).is_ok());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_client_config_requires_both_client_files() {
+        let config = UpstreamTlsConfig {
+            client_key: Some("client-key.pem".to_string()),
+            ..UpstreamTlsConfig::default()
+        };
+
+        assert!(client_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_client_config_loads_ca_bundle() {
+        let dir = temp_dir("tls-bundle");
+        let ca = LocalCa::load_or_create(&dir).expect("Should create CA");
+        let config = UpstreamTlsConfig {
+            ca_bundle: Some(dir.join(CA_CERT_FILE).to_string_lossy().into_owned()),
+            ..UpstreamTlsConfig::default()
+        };
+
+        assert!(!ca.cert_pem().is_empty());
+        assert!(client_config(&config).is_ok());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_acceptor_requires_both_paths() {
+        let config = TlsConfig {
+            cert_path: Some("cert.pem".to_string()),
+            ..TlsConfig::default()
+        };
+
+        assert!(acceptor(&config).is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use thiserror::Error;
 
 // Error types for future use in the application
@@ -27,5 +29,55 @@ pub enum AppError {
     #[error("Fail to read Cargo.toml")]
     CargoTomlError,
     #[error("Graceful shutdown error: {0}")]
-    GracefulShutdownError(String)
-}
\ No newline at end of file
+    GracefulShutdownError(String),
+    #[error("Fail to build upstream request: {0}")]
+    UpstreamRequestError(String),
+    #[error("Upstream unreachable: {0}")]
+    UpstreamUnreachable(String),
+    #[error("TLS error: {0}")]
+    TlsError(String),
+    #[error("No route matches {0}")]
+    NoRouteMatched(String),
+    #[error("Upstream connect timed out after {0} ms")]
+    UpstreamConnectTimeout(u64),
+    #[error("Upstream response headers timed out after {0} ms")]
+    UpstreamResponseTimeout(u64),
+    #[error("Upstream request timed out after {0} ms")]
+    UpstreamRequestTimeout(u64),
+    #[error("Network profile error: {0}")]
+    NetworkProfileError(String),
+    #[error("Cassette error: {0}")]
+    CassetteError(String),
+    #[error("No recorded interaction matches {0}")]
+    NoRecordedInteraction(String),
+    #[error("Breakpoint error: {0}")]
+    BreakpointError(String),
+    #[error("No breakpoint {0}")]
+    BreakpointNotFound(u64),
+    #[error("No held message {0}")]
+    HeldMessageNotFound(u64),
+    #[error("Descriptor set error: {0}")]
+    DescriptorSetError(String),
+    #[error("Not mirrored: {0}")]
+    NotMirrored(String),
+}
+
+impl IntoResponse for AppError {
+    /// Map errors surfacing from proxy handlers to HTTP responses
+    /// Upstream failures are reported as 502 so clients can tell them apart from app errors,
+    /// and upstream timeouts as 504
+    fn into_response(self) -> Response {
+        let status = match self {
+            AppError::UpstreamRequestError(_) => StatusCode::BAD_REQUEST,
+            AppError::UpstreamUnreachable(_) => StatusCode::BAD_GATEWAY,
+            AppError::NoRouteMatched(_) | AppError::NoRecordedInteraction(_) => StatusCode::NOT_FOUND,
+            AppError::NetworkProfileError(_) | AppError::BreakpointError(_) => StatusCode::BAD_REQUEST,
+            AppError::BreakpointNotFound(_) | AppError::HeldMessageNotFound(_) => StatusCode::NOT_FOUND,
+            AppError::UpstreamConnectTimeout(_)
+            | AppError::UpstreamResponseTimeout(_)
+            | AppError::UpstreamRequestTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use axum::Router;
+use axum::http::StatusCode;
+use axum::routing::get;
+use endpoint_logger::config::{BalanceStrategy, HealthCheckConfig, PoolConfig};
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn round_robin_pool_records_chosen_backend() {
+    //Arrange
+    let first = spawn_upstream(Router::new().fallback(|| async { "first" })).await;
+    let second = spawn_upstream(Router::new().fallback(|| async { "second" })).await;
+    let mut config = config_for("pool:replicas".to_string());
+    config.pools = pools(&[first, second], None);
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let mut bodies = Vec::new();
+    for _ in 0..4 {
+        bodies.push(fetch(proxy).await.1);
+    }
+
+    //Assert
+    assert_eq!(bodies, vec!["first", "second", "first", "second"]);
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 4).await;
+    assert_eq!(logs[0]["backend"], format!("http://{}", second));
+    assert_eq!(logs[1]["backend"], format!("http://{}", first));
+}
+
+#[tokio::test]
+async fn failing_health_checks_take_member_out_of_rotation() {
+    //Arrange
+    let healthy = Arc::new(AtomicBool::new(false));
+    let flag = healthy.clone();
+    let flaky = spawn_upstream(
+        Router::new()
+            .route(
+                "/health",
+                get(move || {
+                    let healthy = flag.load(Ordering::Relaxed);
+                    async move { if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE } }
+                }),
+            )
+            .fallback(|| async { "flaky" }),
+    )
+    .await;
+    let stable = spawn_upstream(Router::new().fallback(|| async { "stable" })).await;
+    let mut config = config_for("pool:replicas".to_string());
+    config.pools = pools(
+        &[flaky, stable],
+        Some(HealthCheckConfig { path: "/health".to_string(), interval_ms: 20, timeout_ms: 500 }),
+    );
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    //Act
+    let mut out_of_rotation = Vec::new();
+    for _ in 0..4 {
+        out_of_rotation.push(fetch(proxy).await.1);
+    }
+    healthy.store(true, Ordering::Relaxed);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut recovered = Vec::new();
+    for _ in 0..4 {
+        recovered.push(fetch(proxy).await.1);
+    }
+
+    //Assert
+    assert!(out_of_rotation.iter().all(|b| b == "stable"), "{:?}", out_of_rotation);
+    assert!(recovered.iter().any(|b| b == "flaky"), "{:?}", recovered);
+}
+
+#[tokio::test]
+async fn pool_without_healthy_members_returns_bad_gateway() {
+    //Arrange
+    let down = spawn_upstream(Router::new().fallback(|| async { StatusCode::SERVICE_UNAVAILABLE })).await;
+    let mut config = config_for("pool:replicas".to_string());
+    config.pools = pools(
+        &[down],
+        Some(HealthCheckConfig { path: "/".to_string(), interval_ms: 20, timeout_ms: 500 }),
+    );
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    //Act
+    let (status, body) = fetch(proxy).await;
+
+    //Assert
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert!(body.contains("No healthy member in pool 'replicas'"), "{}", body);
+}
+
+fn pools(members: &[SocketAddr], health_check: Option<HealthCheckConfig>) -> BTreeMap<String, PoolConfig> {
+    let pool = PoolConfig {
+        members: members.iter().map(|m| format!("http://{}", m)).collect(),
+        strategy: BalanceStrategy::RoundRobin,
+        health_check,
+    };
+    BTreeMap::from([("replicas".to_string(), pool)])
+}
+
+async fn fetch(proxy: SocketAddr) -> (StatusCode, String) {
+    let response = reqwest::get(format!("http://{}/", proxy))
+        .await
+        .expect("Failed to execute request");
+    let status = response.status();
+    (status, response.text().await.expect("Failed to read body"))
+}
@@ -0,0 +1,198 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use axum::Router;
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::routing::{get, post};
+use serde_json::{Value, json};
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn held_request_is_edited_and_resumed() {
+    //Arrange
+    let app = Router::new().route(
+        "/v2/orders",
+        post(|uri: Uri, headers: HeaderMap, body: String| async move {
+            let token = headers.get("x-token").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+            format!("{} {} {}", uri, token, body)
+        }),
+    );
+    let upstream = spawn_upstream(app).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+    let breakpoint = set_breakpoint(proxy, json!({ "filter": "method == POST && path ~ /v1/**" })).await;
+
+    //Act
+    let request = tokio::spawn(client.post(format!("http://{}/v1/orders", proxy)).body("qty=1").send());
+    let held = wait_for_held(proxy).await;
+    assert_eq!(held["breakpoint"], breakpoint["id"]);
+    assert_eq!(held["phase"], "request");
+    assert_eq!(held["url"], "/v1/orders");
+    assert_eq!(held["body"], "qty=1");
+    let decision = json!({
+        "action": "resume",
+        "url": "/v2/orders",
+        "headers": [["x-token", "edited"]],
+        "body": "qty=5",
+    });
+    let decided = decide(proxy, &held, decision).await;
+
+    //Assert
+    assert_eq!(decided, StatusCode::NO_CONTENT);
+    let response = request.await.unwrap().expect("Failed to execute request");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "/v2/orders edited qty=5");
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["response_body"]["complete"] == true).await;
+    assert_eq!(logs[0]["uri"], "/v2/orders");
+    assert_eq!(logs[0]["request_body"]["data"], "qty=5");
+    let hit = json!({ "breakpoint": breakpoint["id"], "phase": "request", "outcome": "edited" });
+    assert_eq!(logs[0]["breakpoints"], json!([hit]));
+}
+
+#[tokio::test]
+async fn held_response_is_answered_or_dropped() {
+    //Arrange
+    let app = Router::new().route("/fail", get(|| async { (StatusCode::INTERNAL_SERVER_ERROR, "boom") }));
+    let upstream = spawn_upstream(app).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+    set_breakpoint(proxy, json!({ "filter": "status >= 500", "on": "response" })).await;
+
+    //Act
+    let answered = tokio::spawn(client.get(format!("http://{}/fail", proxy)).send());
+    let held = wait_for_held(proxy).await;
+    assert_eq!(held["status"], 500);
+    assert_eq!(held["body"], "boom");
+    decide(proxy, &held, json!({ "action": "respond", "status": 202, "body": "patched" })).await;
+    let answered = answered.await.unwrap().expect("Failed to execute request");
+
+    let dropped = tokio::spawn(client.get(format!("http://{}/fail", proxy)).send());
+    let held = wait_for_held(proxy).await;
+    decide(proxy, &held, json!({ "action": "drop" })).await;
+    let dropped = dropped.await.unwrap();
+
+    //Assert
+    assert_eq!(answered.status(), StatusCode::ACCEPTED);
+    assert_eq!(answered.text().await.unwrap(), "patched");
+    assert!(dropped.is_err());
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 2 && logs[0]["error"].is_string()).await;
+    assert_eq!(logs[0]["error"], "Connection dropped at breakpoint");
+    assert_eq!(logs[0]["breakpoints"][0]["outcome"], "dropped");
+    assert_eq!(logs[1]["status"], 202);
+    assert_eq!(logs[1]["breakpoints"][0]["outcome"], "answered");
+}
+
+#[tokio::test]
+async fn held_request_goes_on_unchanged_after_timeout() {
+    //Arrange
+    let app = Router::new().route("/slow", get(|| async { "through" }));
+    let upstream = spawn_upstream(app).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+    set_breakpoint(proxy, json!({ "filter": "path == /slow", "timeout_ms": 100 })).await;
+
+    //Act
+    let response = reqwest::get(format!("http://{}/slow", proxy)).await.expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.text().await.unwrap(), "through");
+    assert_eq!(held(proxy).await, json!([]));
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1).await;
+    assert_eq!(logs[0]["breakpoints"][0]["outcome"], "timed_out");
+}
+
+#[tokio::test]
+async fn large_request_body_is_held_without_being_read() {
+    //Arrange
+    let app = Router::new().route("/upload", post(|body: String| async move { body.len().to_string() }));
+    let upstream = spawn_upstream(app).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.logging.max_body_size_kb = 1;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let breakpoint = set_breakpoint(proxy, json!({ "filter": "path == /upload" })).await;
+
+    //Act
+    let upload = reqwest::Client::new().post(format!("http://{}/upload", proxy)).body("a".repeat(4096));
+    let request = tokio::spawn(upload.send());
+    let held = wait_for_held(proxy).await;
+    let edit = decide(proxy, &held, json!({ "action": "resume", "body": "small" })).await;
+    let resume = decide(proxy, &held, json!({ "action": "resume" })).await;
+
+    //Assert
+    // Held messages are numbered apart from breakpoints
+    assert_eq!(breakpoint["id"], 1);
+    assert_eq!(held["id"], 1);
+    assert_eq!(held["body_editable"], false);
+    assert_eq!(held["body"], "");
+    assert_eq!(edit, StatusCode::BAD_REQUEST);
+    assert_eq!(resume, StatusCode::NO_CONTENT);
+    let response = request.await.unwrap().expect("Failed to execute request");
+    assert_eq!(response.text().await.unwrap(), "4096");
+}
+
+#[tokio::test]
+async fn invalid_breakpoints_and_decisions_are_rejected() {
+    //Arrange
+    let app = Router::new().route("/", get(|| async { "ok" }));
+    let upstream = spawn_upstream(app).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+    let api = format!("http://{}/__logger/api/breakpoints", proxy);
+
+    //Act
+    let invalid = client.post(&api).json(&json!({ "filter": "verb == GET" })).send().await.unwrap();
+    let unknown_breakpoint = client.delete(format!("{}/42", api)).send().await.unwrap();
+    let resume = json!({ "action": "resume" });
+    let unknown_held = client.post(format!("{}/held/42", api)).json(&resume).send().await.unwrap();
+
+    //Assert
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(unknown_breakpoint.status(), StatusCode::NOT_FOUND);
+    assert_eq!(unknown_held.status(), StatusCode::NOT_FOUND);
+}
+
+async fn set_breakpoint(proxy: SocketAddr, breakpoint: Value) -> Value {
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/__logger/api/breakpoints", proxy))
+        .json(&breakpoint)
+        .send()
+        .await
+        .expect("Failed to set breakpoint");
+    assert_eq!(response.status(), StatusCode::CREATED);
+    response.json().await.unwrap()
+}
+
+async fn held(proxy: SocketAddr) -> Value {
+    reqwest::get(format!("http://{}/__logger/api/breakpoints/held", proxy))
+        .await
+        .expect("Failed to list held messages")
+        .json()
+        .await
+        .unwrap()
+}
+
+/// Wait until exactly one message is held and return it
+async fn wait_for_held(proxy: SocketAddr) -> Value {
+    for _ in 0..50 {
+        if let Value::Array(mut held) = held(proxy).await
+            && held.len() == 1
+        {
+            return held.remove(0);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("No message was held");
+}
+
+async fn decide(proxy: SocketAddr, held: &Value, decision: Value) -> StatusCode {
+    reqwest::Client::new()
+        .post(format!("http://{}/__logger/api/breakpoints/held/{}", proxy, held["id"]))
+        .json(&decision)
+        .send()
+        .await
+        .expect("Failed to decide")
+        .status()
+}
@@ -0,0 +1,137 @@
+use std::io::Write;
+use axum::Router;
+use axum::body::Bytes;
+use axum::http::header;
+use axum::routing::post;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn captures_request_and_response_bodies() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().route("/echo", post(|body: Bytes| async move { body }))).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+
+    //Act
+    let response = client
+        .post(format!("http://{}/echo?x=1", proxy))
+        .header("x-trace", "abc")
+        .body("ping")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.text().await.unwrap(), "ping");
+
+    //Assert
+    let logs = wait_for_logs(proxy, |logs| {
+        logs.len() == 1 && logs[0]["response_body"]["complete"] == true
+    })
+    .await;
+    let exchange = &logs[0];
+    assert_eq!(exchange["method"], "POST");
+    assert_eq!(exchange["uri"], "/echo?x=1");
+    assert_eq!(exchange["status"], 200);
+    assert!(exchange["request_headers"].as_array().unwrap().iter().any(|h| h[0] == "x-trace" && h[1] == "abc"));
+    assert_eq!(exchange["request_body"]["data"], "ping");
+    assert_eq!(exchange["response_body"]["data"], "ping");
+    assert_eq!(exchange["response_body"]["truncated"], false);
+}
+
+#[tokio::test]
+async fn large_bodies_stream_through_and_capture_is_truncated() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().route("/echo", post(|body: Bytes| async move { body }))).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.logging.max_body_size_kb = 1;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let payload = "x".repeat(256 * 1024);
+
+    //Act
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/echo", proxy))
+        .body(payload.clone())
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let body = response.text().await.unwrap();
+
+    //Assert
+    assert_eq!(body.len(), payload.len());
+    let logs = wait_for_logs(proxy, |logs| {
+        logs.len() == 1 && logs[0]["response_body"]["complete"] == true
+    })
+    .await;
+    for side in ["request_body", "response_body"] {
+        let captured = &logs[0][side];
+        assert_eq!(captured["size"], payload.len() as u64);
+        assert_eq!(captured["truncated"], true);
+        assert_eq!(captured["data"].as_str().unwrap().len(), 1024);
+    }
+}
+
+#[tokio::test]
+async fn compressed_bodies_are_relayed_as_is_and_captured_decoded() {
+    //Arrange
+    let json = r#"{"users": [{"name": "Ada"}]}"#;
+    let zstd_json = zstd::encode_all(json.as_bytes(), 3).unwrap();
+    let response_body = zstd_json.clone();
+    let app = Router::new().route(
+        "/users",
+        post(move || async move { ([(header::CONTENT_ENCODING, "zstd")], response_body) }),
+    );
+    let upstream = spawn_upstream(app).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(br#"{"name": "Grace"}"#).unwrap();
+    let gzip_request = encoder.finish().unwrap();
+
+    //Act
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/users", proxy))
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body(gzip_request.clone())
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "zstd");
+    assert_eq!(response.bytes().await.unwrap(), zstd_json);
+
+    let logs = wait_for_logs(proxy, |logs| {
+        logs.len() == 1 && logs[0]["response_body"]["complete"] == true
+    })
+    .await;
+    let request = &logs[0]["request_body"];
+    assert_eq!(request["data"], r#"{"name": "Grace"}"#);
+    assert_eq!(request["encoding"], "gzip");
+    assert_eq!(request["size"], gzip_request.len() as u64);
+    assert_eq!(request["decoded_size"], 17);
+    let response = &logs[0]["response_body"];
+    assert_eq!(response["data"], json);
+    assert_eq!(response["encoding"], "zstd");
+    assert_eq!(response["size"], zstd_json.len() as u64);
+    assert_eq!(response["decoded_size"], json.len() as u64);
+}
+
+#[tokio::test]
+async fn failed_upstream_is_recorded() {
+    //Arrange
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let closed = listener.local_addr().unwrap();
+    drop(listener);
+    let proxy = spawn_proxy(config_for(format!("http://{}", closed))).await.expect("Failed to spawn our app");
+
+    //Act
+    reqwest::get(format!("http://{}/down", proxy)).await.expect("Failed to execute request");
+
+    //Assert
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1).await;
+    assert!(logs[0]["status"].is_null());
+    assert!(logs[0]["error"].as_str().unwrap().contains("Upstream unreachable"));
+}
@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use axum::Router;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use endpoint_logger::config::{AppConfig, CassetteConfig, CassetteMatch, CassetteMiss, MockConfig};
+use serde_json::{Value, json};
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn recorded_cassette_is_replayed_without_target() {
+    //Arrange
+    let cassette = cassette_path("round-trip");
+    let app = Router::new()
+        .route("/users", get(|| async { r#"[{"id": 1}]"# }))
+        .route("/echo", post(|body: String| async move { (StatusCode::CREATED, format!("echo {}", body)) }));
+    let upstream = spawn_upstream(app).await;
+    let mut recording = config_for(format!("http://{}", upstream));
+    recording.cassette.record = Some(cassette.to_string_lossy().into_owned());
+    let recorder = spawn_proxy(recording).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+    client.get(format!("http://{}/users?page=1&size=10", recorder)).send().await.unwrap().text().await.unwrap();
+    client.post(format!("http://{}/echo", recorder)).body("hi").send().await.unwrap().text().await.unwrap();
+    wait_for_lines(&cassette, 2).await;
+
+    // Strict replay needs no target at all
+    let replaying = AppConfig {
+        cassette: CassetteConfig { replay: Some(cassette.to_string_lossy().into_owned()), ..CassetteConfig::default() },
+        ..AppConfig::default()
+    };
+    let proxy = spawn_proxy(replaying).await.expect("Failed to spawn our app");
+
+    //Act
+    let users = client.get(format!("http://{}/users?size=10&page=1", proxy)).send().await.unwrap();
+    let echo = client.post(format!("http://{}/echo", proxy)).body("something else").send().await.unwrap();
+    let missing = client.get(format!("http://{}/orders", proxy)).send().await.unwrap();
+
+    //Assert
+    assert_eq!(users.status(), StatusCode::OK);
+    assert_eq!(users.text().await.unwrap(), r#"[{"id": 1}]"#);
+    assert_eq!(echo.status(), StatusCode::CREATED);
+    assert_eq!(echo.text().await.unwrap(), "echo hi");
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    assert_eq!(missing.text().await.unwrap(), "No recorded interaction matches GET /orders");
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 3).await;
+    assert!(logs[0].get("replayed").is_none());
+    assert_eq!(logs[1]["replayed"], true);
+    assert_eq!(logs[2]["replayed"], true);
+    std::fs::remove_file(&cassette).ok();
+}
+
+#[tokio::test]
+async fn only_forwarded_exchanges_are_recorded() {
+    //Arrange
+    let cassette = cassette_path("forwarded-only");
+    let app = Router::new().route("/users", get(|| async { "live" }));
+    let upstream = spawn_upstream(app).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.cassette.record = Some(cassette.to_string_lossy().into_owned());
+    config.mocks = vec![MockConfig { path: "/mocked".to_string(), ..MockConfig::default() }];
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+
+    //Act
+    client.get(format!("http://{}/mocked", proxy)).send().await.unwrap().text().await.unwrap();
+    client.get(format!("http://{}/users", proxy)).send().await.unwrap().text().await.unwrap();
+
+    //Assert
+    wait_for_lines(&cassette, 1).await;
+    let content = std::fs::read_to_string(&cassette).unwrap();
+    let interaction: Value = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+    assert_eq!(interaction["request"]["uri"], "/users");
+    assert_eq!(interaction["response"]["body"], "live");
+    std::fs::remove_file(&cassette).ok();
+}
+
+#[tokio::test]
+async fn passthrough_forwards_requests_with_other_bodies() {
+    //Arrange
+    let cassette = cassette_path("passthrough");
+    let interaction = json!({
+        "request": {
+            "method": "POST",
+            "uri": "/search",
+            "headers": [],
+            "body": "{\"q\":\"rust\"}",
+            "body_sha256": "5f896bb89986a7348805029ea0329739ce22e6f918c24351ee07ff2a1430c1a1"
+        },
+        "response": {
+            "status": 200,
+            "headers": [["content-type", "application/json"]],
+            "body_base64": "WyJjYWNoZWQiXQ=="
+        }
+    });
+    std::fs::write(&cassette, format!("{}\n", interaction)).unwrap();
+    let app = Router::new().route("/search", post(|| async { "live" }));
+    let upstream = spawn_upstream(app).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.cassette = CassetteConfig {
+        replay: Some(cassette.to_string_lossy().into_owned()),
+        match_on: vec![CassetteMatch::Method, CassetteMatch::Path, CassetteMatch::Body],
+        on_miss: CassetteMiss::Passthrough,
+        ..CassetteConfig::default()
+    };
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+
+    //Act
+    let replayed = client.post(format!("http://{}/search", proxy)).body(r#"{"q":"rust"}"#).send().await.unwrap();
+    let forwarded = client.post(format!("http://{}/search", proxy)).body(r#"{"q":"go"}"#).send().await.unwrap();
+
+    //Assert
+    assert_eq!(replayed.headers()["content-type"], "application/json");
+    assert_eq!(replayed.json::<Value>().await.unwrap(), json!(["cached"]));
+    assert_eq!(forwarded.text().await.unwrap(), "live");
+    std::fs::remove_file(&cassette).ok();
+}
+
+#[tokio::test]
+async fn unreadable_cassette_is_rejected_at_startup() {
+    //Arrange
+    let cassette = cassette_path("invalid");
+    std::fs::write(&cassette, "not json\n").unwrap();
+    let mut config = config_for("http://127.0.0.1:1".to_string());
+    config.cassette.replay = Some(cassette.to_string_lossy().into_owned());
+
+    //Act
+    let result = spawn_proxy(config).await;
+
+    //Assert
+    assert!(result.is_err());
+    std::fs::remove_file(&cassette).ok();
+}
+
+fn cassette_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("endpoint-logger-cassette-{}-{}.jsonl", name, std::process::id()))
+}
+
+/// Wait until the recorder wrote `count` interactions to `cassette`
+async fn wait_for_lines(cassette: &Path, count: usize) {
+    for _ in 0..50 {
+        let content = std::fs::read_to_string(cassette).unwrap_or_default();
+        if content.lines().count() == count {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Cassette never reached {} interactions", count);
+}
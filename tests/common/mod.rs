@@ -0,0 +1,59 @@
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use axum::Router;
+use serde_json::Value;
+use tokio::net::TcpListener;
+use endpoint_logger::config::AppConfig;
+
+/// Serve `app` on a random local port, standing in for the target application
+pub async fn spawn_upstream(app: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind address");
+    let address = listener.local_addr().expect("Failed to get port");
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    address
+}
+
+/// Start the proxy on a random local port with the given configuration
+pub async fn spawn_proxy(config: AppConfig) -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind address");
+    let address = listener.local_addr().expect("Failed to get port");
+    endpoint_logger::run(listener, config).await?;
+    Ok(address)
+}
+
+/// Configuration proxying to `target_url` with everything else left at defaults
+pub fn config_for(target_url: String) -> AppConfig {
+    AppConfig {
+        target_url,
+        ..AppConfig::default()
+    }
+}
+
+/// Fetch captured exchanges from the logs API, waiting until `ready` accepts them
+pub async fn wait_for_logs<F: Fn(&[Value]) -> bool>(proxy: SocketAddr, ready: F) -> Vec<Value> {
+    let client = reqwest::Client::new();
+    let mut logs = Vec::new();
+    for _ in 0..50 {
+        logs = client
+            .get(format!("http://{}/__logger/api/logs", proxy))
+            .send()
+            .await
+            .expect("Failed to query logs")
+            .json::<Vec<Value>>()
+            .await
+            .expect("Logs should be JSON");
+        if ready(&logs) {
+            return logs;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Captured logs never reached the expected state: {:?}", logs);
+}
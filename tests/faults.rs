@@ -0,0 +1,104 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use axum::Router;
+use axum::http::StatusCode;
+use axum::routing::get;
+use endpoint_logger::config::FaultConfig;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn injects_status_without_reaching_upstream() {
+    //Arrange
+    let proxy = proxy_with(FaultConfig {
+        name: Some("users-down".to_string()),
+        method: Some("GET".to_string()),
+        path: "/users/*".to_string(),
+        status: Some(503),
+        ..FaultConfig::default()
+    })
+    .await;
+
+    //Act
+    let faulted = reqwest::get(format!("http://{}/users/42", proxy)).await.unwrap();
+    let deeper = reqwest::get(format!("http://{}/users/42/orders", proxy)).await.unwrap();
+    let posted = reqwest::Client::new().post(format!("http://{}/users/42", proxy)).send().await.unwrap();
+
+    //Assert
+    assert_eq!(faulted.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(deeper.status(), StatusCode::OK);
+    assert_eq!(posted.status(), StatusCode::OK);
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 3 && logs.iter().all(|l| !l["status"].is_null())).await;
+    let faulted: Vec<_> = logs.iter().filter(|l| l.get("fault").is_some()).collect();
+    assert_eq!(faulted.len(), 1);
+    assert_eq!(faulted[0]["uri"], "/users/42");
+    assert_eq!(faulted[0]["fault"]["rule"], "users-down");
+    assert_eq!(faulted[0]["fault"]["status"], 503);
+}
+
+#[tokio::test]
+async fn injects_latency_before_forwarding() {
+    //Arrange
+    let proxy = proxy_with(FaultConfig { latency_ms: Some(300), ..FaultConfig::default() }).await;
+
+    //Act
+    let started = Instant::now();
+    let response = reqwest::get(format!("http://{}/users/42", proxy)).await.unwrap();
+
+    //Assert
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "user 42");
+}
+
+#[tokio::test]
+async fn drops_connection_without_answer() {
+    //Arrange
+    let proxy = proxy_with(FaultConfig { drop_connection: true, ..FaultConfig::default() }).await;
+
+    //Act
+    let result = reqwest::get(format!("http://{}/users/42", proxy)).await;
+    let mut stream = TcpStream::connect(proxy).await.unwrap();
+    stream.write_all(b"GET /users/42 HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n").await.unwrap();
+    let mut answer = Vec::new();
+    let read = stream.read_to_end(&mut answer).await;
+
+    //Assert
+    assert!(result.is_err());
+    // Closed before the status line, not after the headers
+    assert!(read.is_err() || answer.is_empty(), "{}", String::from_utf8_lossy(&answer));
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 2 && logs.iter().all(|l| !l["error"].is_null())).await;
+    assert_eq!(logs[0]["fault"]["drop_connection"], true);
+    assert!(logs.iter().all(|l| l["status"].is_null()));
+}
+
+#[tokio::test]
+async fn truncates_response_body() {
+    //Arrange
+    let proxy = proxy_with(FaultConfig { truncate_body: Some(4), ..FaultConfig::default() }).await;
+
+    //Act
+    let response = reqwest::get(format!("http://{}/users/42", proxy)).await.unwrap();
+    let body = response.bytes().await;
+
+    //Assert
+    assert!(body.is_err());
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["response_body"]["size"] == 4).await;
+    assert_eq!(logs[0]["response_body"]["data"], "user");
+    assert_eq!(logs[0]["response_body"]["complete"], false);
+    assert_eq!(logs[0]["fault"]["truncate_body"], 4);
+}
+
+async fn proxy_with(fault: FaultConfig) -> SocketAddr {
+    let app = Router::new().route("/users/{id}", get(|| async { "user 42" }).post(|| async { "created" }));
+    let app = app.route("/users/{id}/orders", get(|| async { "orders" }));
+    let upstream = spawn_upstream(app).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.faults = vec![fault];
+    spawn_proxy(config).await.expect("Failed to spawn our app")
+}
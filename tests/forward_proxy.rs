@@ -0,0 +1,151 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use axum::Router;
+use axum::routing::get;
+use endpoint_logger::config::{AppConfig, ProxyMode};
+use endpoint_logger::tls::{LocalCa, server_config};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+mod common;
+
+use common::{spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn forwards_absolute_form_requests() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().route("/hello", get(|| async { "hello" }))).await;
+    let proxy = spawn_proxy(forward_config()).await.expect("Failed to spawn our app");
+    let client = proxied_client(proxy, None);
+
+    //Act
+    let response = client
+        .get(format!("http://{}/hello", upstream))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.text().await.unwrap(), "hello");
+    let expected = format!("http://{}/hello", upstream);
+    let logs = wait_for_logs(proxy, |logs| logs.iter().any(|l| l["uri"] == expected.as_str())).await;
+    assert_eq!(logs[0]["status"], 200);
+    assert_eq!(logs[0]["response_body"]["data"], "hello");
+}
+
+#[tokio::test]
+async fn relays_connect_tunnels_without_decrypting() {
+    //Arrange
+    let dir = temp_dir("relay");
+    let ca = LocalCa::load_or_create(&dir).unwrap();
+    let upstream = spawn_https_upstream(&ca).await;
+    let proxy = spawn_proxy(forward_config()).await.expect("Failed to spawn our app");
+    let client = proxied_client(proxy, Some(ca.cert_pem()));
+
+    //Act
+    let response = client
+        .get(format!("https://localhost:{}/secure", upstream.port()))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let body = response.text().await.unwrap();
+    drop(client);
+
+    //Assert
+    assert_eq!(body, "secure");
+    let logs = wait_for_logs(proxy, |logs| logs.iter().any(|l| l["tunnel"]["closed"] == true)).await;
+    assert_eq!(logs.len(), 1, "Tunnelled requests are not visible without interception");
+    assert_eq!(logs[0]["method"], "CONNECT");
+    assert_eq!(logs[0]["uri"], format!("localhost:{}", upstream.port()));
+    assert_eq!(logs[0]["tunnel"]["intercepted"], false);
+    assert!(logs[0]["tunnel"]["bytes_sent"].as_u64().unwrap() > 0);
+    assert!(logs[0]["tunnel"]["bytes_received"].as_u64().unwrap() > 0);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn intercepts_connect_tunnels_with_local_ca() {
+    //Arrange
+    let dir = temp_dir("intercept");
+    let ca = LocalCa::load_or_create(&dir).unwrap();
+    let upstream = spawn_https_upstream(&ca).await;
+    let mut config = forward_config();
+    config.forward.intercept_tls = true;
+    config.forward.cert_dir = dir.to_string_lossy().into_owned();
+    config.upstream.tls.ca_bundle = Some(dir.join("ca.pem").to_string_lossy().into_owned());
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let client = proxied_client(proxy, Some(ca.cert_pem()));
+
+    //Act
+    let response = client
+        .get(format!("https://localhost:{}/secure?page=1", upstream.port()))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.text().await.unwrap(), "secure");
+    let expected = format!("https://localhost:{}/secure?page=1", upstream.port());
+    let logs = wait_for_logs(proxy, |logs| logs.iter().any(|l| l["uri"] == expected.as_str())).await;
+    let inner = logs.iter().find(|l| l["uri"] == expected.as_str()).unwrap();
+    assert_eq!(inner["method"], "GET");
+    assert_eq!(inner["response_body"]["data"], "secure");
+    let connect = logs.iter().find(|l| l["method"] == "CONNECT").expect("Tunnel should be recorded");
+    assert_eq!(connect["tunnel"]["intercepted"], true);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn unreachable_connect_target_returns_bad_gateway() {
+    //Arrange
+    let proxy = spawn_proxy(forward_config()).await.expect("Failed to spawn our app");
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let client = proxied_client(proxy, None);
+
+    //Act
+    let result = client.get(format!("https://{}/", closed)).send().await;
+
+    //Assert
+    assert!(result.is_err());
+    let logs = wait_for_logs(proxy, |logs| !logs.is_empty()).await;
+    assert_eq!(logs[0]["method"], "CONNECT");
+    assert!(logs[0]["error"].is_string());
+}
+
+fn forward_config() -> AppConfig {
+    AppConfig {
+        mode: ProxyMode::Forward,
+        ..AppConfig::default()
+    }
+}
+
+/// Client sending every request through the proxy, optionally trusting only `ca_pem`
+fn proxied_client(proxy: SocketAddr, ca_pem: Option<&str>) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder().proxy(reqwest::Proxy::all(format!("http://{}", proxy)).unwrap());
+    if let Some(ca_pem) = ca_pem {
+        builder = builder.tls_certs_only([reqwest::Certificate::from_pem(ca_pem.as_bytes()).unwrap()]);
+    }
+    builder.build().unwrap()
+}
+
+/// Serve a fixed body over TLS with a `localhost` certificate issued by `ca`
+async fn spawn_https_upstream(ca: &LocalCa) -> SocketAddr {
+    let (cert, key) = ca.issue(&["localhost"]).unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(server_config(&cert, &key).unwrap()));
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind address");
+    let address = listener.local_addr().expect("Failed to get port");
+    let app = Router::new().route("/secure", get(|| async { "secure" }));
+    tokio::spawn(endpoint_logger::server::serve_tls(listener, acceptor, app));
+    address
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("endpoint-logger-forward-{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    dir
+}
@@ -0,0 +1,142 @@
+use tokio::net::TcpListener;
+use axum::Router;
+use axum::body::Bytes;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::IntoResponse;
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream};
+
+#[tokio::test]
+async fn forwards_method_path_query_headers_and_body() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().fallback(echo)).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+
+    //Act
+    let response = client
+        .put(format!("http://{}/users/42?expand=true", proxy))
+        .header("x-custom", "endpoint-logger")
+        .body("hello upstream")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["x-upstream"], "echo");
+    let body = response.text().await.expect("Failed to read body");
+    assert_eq!(body, "PUT /users/42?expand=true endpoint-logger hello upstream");
+}
+
+#[tokio::test]
+async fn prefixes_target_path() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().fallback(echo)).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}/api", upstream))).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+
+    //Act
+    let response = client
+        .get(format!("http://{}/users", proxy))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    let body = response.text().await.expect("Failed to read body");
+    assert_eq!(body, "GET /api/users  ");
+}
+
+#[tokio::test]
+async fn unreachable_upstream_returns_bad_gateway() {
+    //Arrange
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("Failed to bind address");
+    let closed = listener.local_addr().expect("Failed to get port");
+    drop(listener);
+    let proxy = spawn_proxy(config_for(format!("http://{}", closed))).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/anything", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test]
+async fn rewrites_host_and_adds_forwarded_headers() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().fallback(echo_headers)).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+
+    //Act
+    let headers = fetch_echoed_headers(proxy).await;
+
+    //Assert
+    assert_eq!(headers["host"], upstream.to_string());
+    assert_eq!(headers["x-forwarded-for"], "127.0.0.1");
+    assert_eq!(headers["x-forwarded-proto"], "http");
+    assert_eq!(headers["x-forwarded-host"], proxy.to_string());
+    assert_eq!(headers["forwarded"], format!("for=127.0.0.1;proto=http;host=\"{}\"", proxy));
+    assert!(headers.get("keep-alive").is_none());
+    assert!(headers.get("x-hop").is_none());
+    assert_eq!(headers["x-end-to-end"], "kept");
+}
+
+#[tokio::test]
+async fn preserves_host_when_configured() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().fallback(echo_headers)).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.preserve_host = true;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let headers = fetch_echoed_headers(proxy).await;
+
+    //Assert
+    assert_eq!(headers["host"], proxy.to_string());
+}
+
+async fn fetch_echoed_headers(proxy: std::net::SocketAddr) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!("http://{}/headers", proxy))
+        .header("connection", "keep-alive, x-hop")
+        .header("keep-alive", "timeout=5")
+        .header("x-hop", "dropped")
+        .header("x-end-to-end", "kept")
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .expect("Upstream should answer with JSON")
+}
+
+/// Answer with the received headers as a JSON object
+async fn echo_headers(headers: HeaderMap) -> axum::Json<serde_json::Value> {
+    let map = headers
+        .iter()
+        .map(|(k, v)| (k.to_string(), serde_json::Value::from(v.to_str().unwrap_or_default())))
+        .collect();
+    axum::Json(serde_json::Value::Object(map))
+}
+
+/// Echo the request line, a custom header and the body back to the caller
+async fn echo(method: Method, uri: Uri, headers: HeaderMap, body: Bytes) -> impl IntoResponse {
+    let custom = headers
+        .get("x-custom")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let status = if method == Method::PUT { StatusCode::CREATED } else { StatusCode::OK };
+    (
+        status,
+        [("x-upstream", "echo")],
+        format!("{} {} {} {}", method, uri, custom, String::from_utf8_lossy(&body)),
+    )
+}
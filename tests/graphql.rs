@@ -0,0 +1,151 @@
+use axum::Router;
+use axum::Json;
+use axum::routing::post;
+use serde_json::{Value, json};
+use url::Url;
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn graphql_operations_are_captured_and_queryable() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().route("/graphql", post(answer).get(answer))).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+    let graphql = format!("http://{}/graphql", proxy);
+
+    //Act
+    for (query, variables) in [
+        ("query GetUser($id: ID!) { user(id: $id) { name } }", json!({"id": 1})),
+        ("mutation DeleteUser($id: ID!) { deleteUser(id: $id) }", json!({"id": 2})),
+        ("query GetUser($id: ID!) { user(id: $id) { name } }", json!({"id": 3})),
+    ] {
+        client
+            .post(&graphql)
+            .json(&json!({"query": query, "variables": variables}))
+            .send()
+            .await
+            .expect("Failed to execute request");
+    }
+    let persisted = Url::parse_with_params(
+        &graphql,
+        [
+            ("operationName", "GetFeed"),
+            ("extensions", r#"{"persistedQuery":{"version":1,"sha256Hash":"ecf4edb4"}}"#),
+        ],
+    )
+    .unwrap();
+    client
+        .get(persisted)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    let logs = wait_for_logs(proxy, |logs| {
+        logs.len() == 4 && logs.iter().all(|l| l["response_body"]["complete"] == true)
+    })
+    .await;
+    assert_eq!(logs[0]["graphql"][0]["operation_name"], "GetFeed");
+    assert_eq!(logs[0]["graphql"][0]["operation_type"], "query");
+    assert_eq!(logs[0]["graphql"][0]["persisted_query"], "ecf4edb4");
+    assert_eq!(logs[2]["graphql"][0]["operation_type"], "mutation");
+    assert_eq!(logs[2]["graphql"][0]["variables"], json!({"id": 2}));
+    assert_eq!(logs[2]["graphql"][0]["errors"][0]["code"], "FORBIDDEN");
+
+    let get_user = query_logs(proxy, "operation=GetUser").await;
+    let failed = query_logs(proxy, "graphql_errors=true").await;
+    let mutations = query_logs(proxy, "operation_type=mutation&limit=1").await;
+    assert_eq!(get_user.len(), 2);
+    assert!(get_user.iter().all(|l| l["graphql"][0]["operation_name"] == "GetUser"));
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0]["graphql"][0]["operation_name"], "DeleteUser");
+    assert_eq!(mutations[0]["id"], failed[0]["id"]);
+
+    let operations: Vec<Value> = reqwest::get(format!("http://{}/__logger/api/graphql/operations", proxy))
+        .await
+        .expect("Failed to query operations")
+        .json()
+        .await
+        .unwrap();
+    let summary: Vec<_> = operations.iter().map(|o| (o["operation_name"].as_str(), o["count"].as_u64())).collect();
+    assert_eq!(summary[0], (Some("GetUser"), Some(2)));
+    assert_eq!(operations.len(), 3);
+    assert_eq!(operations.iter().map(|o| o["errors"].as_u64().unwrap()).sum::<u64>(), 1);
+}
+
+#[tokio::test]
+async fn every_operation_of_a_batch_is_captured() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().route("/graphql", post(answer))).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+
+    //Act
+    let batch = json!([
+        {"query": "query GetUser($id: ID!) { user(id: $id) { name } }", "variables": {"id": 1}},
+        {"query": "mutation DeleteUser($id: ID!) { deleteUser(id: $id) }", "variables": {"id": 2}},
+    ]);
+    reqwest::Client::new()
+        .post(format!("http://{}/graphql", proxy))
+        .json(&batch)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["response_body"]["complete"] == true).await;
+    let operations = logs[0]["graphql"].as_array().unwrap();
+    assert_eq!(operations.len(), 2);
+    assert_eq!(operations[0]["operation_name"], "GetUser");
+    assert_eq!(operations[0]["errors"], json!([]));
+    assert_eq!(operations[1]["operation_name"], "DeleteUser");
+    assert_eq!(operations[1]["errors"][0]["code"], "FORBIDDEN");
+
+    assert_eq!(query_logs(proxy, "operation=DeleteUser").await.len(), 1);
+    assert_eq!(query_logs(proxy, "operation_type=mutation&graphql_errors=true").await.len(), 1);
+
+    let operations: Vec<Value> = reqwest::get(format!("http://{}/__logger/api/graphql/operations", proxy))
+        .await
+        .expect("Failed to query operations")
+        .json()
+        .await
+        .unwrap();
+    let summary: Vec<_> = operations
+        .iter()
+        .map(|o| (o["operation_name"].as_str().unwrap(), o["count"].as_u64(), o["errors"].as_u64()))
+        .collect();
+    assert_eq!(summary.len(), 2);
+    assert!(summary.contains(&("GetUser", Some(1), Some(0))));
+    assert!(summary.contains(&("DeleteUser", Some(1), Some(1))));
+}
+
+/// Answer a request, or each request of a batch in order
+async fn answer(body: Option<Json<Value>>) -> Json<Value> {
+    match body {
+        Some(Json(Value::Array(batch))) => Json(batch.iter().map(response).collect()),
+        body => Json(response(body.as_ref().map_or(&Value::Null, |b| &b.0))),
+    }
+}
+
+/// Refuse mutations the way GraphQL servers do: 200 with top-level errors
+fn response(request: &Value) -> Value {
+    let query = request["query"].as_str().unwrap_or_default();
+    if query.starts_with("mutation") {
+        return json!({
+            "data": null,
+            "errors": [{"message": "Not allowed", "path": ["deleteUser"], "extensions": {"code": "FORBIDDEN"}}]
+        });
+    }
+    json!({"data": {"user": {"name": "Ada"}}})
+}
+
+async fn query_logs(proxy: std::net::SocketAddr, query: &str) -> Vec<Value> {
+    reqwest::get(format!("http://{}/__logger/api/logs?{}", proxy, query))
+        .await
+        .expect("Failed to query logs")
+        .json()
+        .await
+        .expect("Logs should be JSON")
+}
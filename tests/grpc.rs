@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::Response;
+use endpoint_logger::config::UpstreamHttpVersion;
+use http_body::Frame;
+use prost_reflect::prost::Message;
+use prost_reflect::prost_types::field_descriptor_proto::{Label, Type};
+use prost_reflect::prost_types::{
+    DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet, MethodDescriptorProto,
+    ServiceDescriptorProto,
+};
+use serde_json::json;
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn grpc_messages_are_decoded_with_the_descriptor_set() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().fallback(|| async {
+        // message = "Hello Ada"
+        grpc_response(vec![framed(b"\x0a\x09Hello Ada")], &[("grpc-status", "0")])
+    }))
+    .await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.upstream.http_version = UpstreamHttpVersion::Http2;
+    config.grpc.descriptor_set = Some(write_descriptor_set("decoded"));
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    // name = "Ada"
+    let response = call(proxy, "/helloworld.Greeter/SayHello", framed(b"\x0a\x03Ada")).await;
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.bytes().await.unwrap().as_ref(), framed(b"\x0a\x09Hello Ada"));
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["response_body"]["complete"] == true).await;
+    let grpc = &logs[0]["grpc"];
+    assert_eq!(grpc["service"], "helloworld.Greeter");
+    assert_eq!(grpc["method"], "SayHello");
+    assert_eq!(grpc["request_messages"][0]["json"], json!({"name": "Ada"}));
+    assert_eq!(grpc["response_messages"][0]["json"], json!({"message": "Hello Ada"}));
+    assert_eq!(grpc["status"], 0);
+    assert_eq!(logs[0]["response_body"]["trailers"], json!([["grpc-status", "0"]]));
+}
+
+#[tokio::test]
+async fn grpc_errors_are_recorded_without_descriptor_set() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().fallback(|| async {
+        grpc_response(Vec::new(), &[("grpc-status", "5"), ("grpc-message", "user%20not%20found")])
+    }))
+    .await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.upstream.http_version = UpstreamHttpVersion::Http2;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = call(proxy, "/helloworld.Greeter/SayHello", framed(b"\x0a\x03Ada")).await;
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["response_body"]["complete"] == true).await;
+    let grpc = &logs[0]["grpc"];
+    assert_eq!(grpc["request_messages"][0]["data"], "CgNBZGE=");
+    assert!(grpc["request_messages"][0].get("json").is_none());
+    assert_eq!(grpc["response_messages"], json!([]));
+    assert_eq!(grpc["status"], 5);
+    assert_eq!(grpc["message"], "user not found");
+}
+
+async fn call(proxy: std::net::SocketAddr, path: &str, body: Vec<u8>) -> reqwest::Response {
+    reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap()
+        .post(format!("http://{}{}", proxy, path))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+fn framed(message: &[u8]) -> Vec<u8> {
+    let mut framed = vec![0];
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
+fn grpc_response(messages: Vec<Vec<u8>>, trailers: &[(&'static str, &'static str)]) -> Response {
+    let mut frames: VecDeque<_> = messages.into_iter().map(|m| Frame::data(Bytes::from(m))).collect();
+    let trailers = trailers.iter().map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)));
+    frames.push_back(Frame::trailers(HeaderMap::from_iter(trailers)));
+    Response::builder()
+        .header("content-type", "application/grpc")
+        .body(Body::new(Frames(frames)))
+        .unwrap()
+}
+
+/// Body made of fixed frames, trailers included
+struct Frames(VecDeque<Frame<Bytes>>);
+
+impl http_body::Body for Frames {
+    type Data = Bytes;
+    type Error = std::convert::Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        Poll::Ready(self.0.pop_front().map(Ok))
+    }
+}
+
+/// Descriptor set of a `helloworld.Greeter` service, like `protoc --descriptor_set_out` writes it
+fn write_descriptor_set(name: &str) -> String {
+    let message = |name: &str, field: &str| DescriptorProto {
+        name: Some(name.to_string()),
+        field: vec![FieldDescriptorProto {
+            name: Some(field.to_string()),
+            number: Some(1),
+            label: Some(Label::Optional as i32),
+            r#type: Some(Type::String as i32),
+            ..FieldDescriptorProto::default()
+        }],
+        ..DescriptorProto::default()
+    };
+    let file = FileDescriptorProto {
+        name: Some("greeter.proto".to_string()),
+        package: Some("helloworld".to_string()),
+        syntax: Some("proto3".to_string()),
+        message_type: vec![message("HelloRequest", "name"), message("HelloReply", "message")],
+        service: vec![ServiceDescriptorProto {
+            name: Some("Greeter".to_string()),
+            method: vec![MethodDescriptorProto {
+                name: Some("SayHello".to_string()),
+                input_type: Some(".helloworld.HelloRequest".to_string()),
+                output_type: Some(".helloworld.HelloReply".to_string()),
+                ..MethodDescriptorProto::default()
+            }],
+            ..ServiceDescriptorProto::default()
+        }],
+        ..FileDescriptorProto::default()
+    };
+    let dir = temp_dir(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("greeter.binpb");
+    std::fs::write(&path, FileDescriptorSet { file: vec![file] }.encode_to_vec()).unwrap();
+    path.display().to_string()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("endpoint-logger-grpc-{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    dir
+}
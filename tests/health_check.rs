@@ -1,10 +1,11 @@
-use tokio::net::TcpListener;
-use std::net::SocketAddr;
+mod common;
+
+use common::{config_for, spawn_proxy};
 
 #[tokio::test]
 async fn health_check_works() {
     //Arrange
-    let server = spawn_app().await.expect("Failed to spawn our app");
+    let server = spawn_proxy(config_for("http://127.0.0.1:9".to_string())).await.expect("Failed to spawn our app");
     let client = reqwest::Client::new();
 
     //Act
@@ -18,12 +19,3 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
-
-async fn spawn_app() -> anyhow::Result<SocketAddr> {
-    let listener = TcpListener::bind("127.0.0.1:0")
-        .await
-        .expect("Failed to bind address");
-    let address = listener.local_addr().expect("Failed to get port");
-    endpoint_logger::run(listener).await?;
-    Ok(address)
-}
\ No newline at end of file
@@ -0,0 +1,68 @@
+use axum::Router;
+use axum::http::Version;
+use endpoint_logger::config::UpstreamHttpVersion;
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn accepts_prior_knowledge_h2c_and_records_stream_ids() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().fallback(report_version)).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+
+    //Act
+    let mut bodies = Vec::new();
+    for path in ["/first", "/second"] {
+        let response = client
+            .get(format!("http://{}{}", proxy, path))
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_eq!(response.version(), reqwest::Version::HTTP_2);
+        bodies.push(response.text().await.unwrap());
+    }
+
+    //Assert
+    assert_eq!(bodies, vec!["HTTP/1.1", "HTTP/1.1"]);
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 2).await;
+    // Most recent first
+    assert_eq!(logs[1]["uri"], "/first");
+    assert_eq!(logs[1]["http_version"], "HTTP/2.0");
+    assert_eq!(logs[1]["stream_id"], 1);
+    assert_eq!(logs[1]["upstream_http_version"], "HTTP/1.1");
+    assert_eq!(logs[0]["uri"], "/second");
+    assert_eq!(logs[0]["stream_id"], 3);
+}
+
+#[tokio::test]
+async fn speaks_h2c_to_upstream_when_configured() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().fallback(report_version)).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.upstream.http_version = UpstreamHttpVersion::Http2;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/h2", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.version(), reqwest::Version::HTTP_11);
+    assert_eq!(response.text().await.unwrap(), "HTTP/2.0");
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1).await;
+    assert_eq!(logs[0]["http_version"], "HTTP/1.1");
+    assert!(logs[0].get("stream_id").is_none());
+    assert_eq!(logs[0]["upstream_http_version"], "HTTP/2.0");
+}
+
+/// Answer with the HTTP version the request arrived with
+async fn report_version(version: Version) -> String {
+    format!("{:?}", version)
+}
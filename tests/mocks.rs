@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+use axum::http::StatusCode;
+use endpoint_logger::config::MockConfig;
+use serde_json::{Value, json};
+
+mod common;
+
+use common::{config_for, spawn_proxy, wait_for_logs};
+
+#[tokio::test]
+async fn answers_from_template_without_contacting_target() {
+    //Arrange
+    // Nothing listens on the target, only mocked requests can succeed
+    let mut config = config_for("http://127.0.0.1:1".to_string());
+    config.mocks = vec![MockConfig {
+        name: Some("create-user".to_string()),
+        method: Some("POST".to_string()),
+        path: "/api/teams/*/users".to_string(),
+        status: 201,
+        headers: BTreeMap::from([
+            ("content-type".to_string(), "application/json".to_string()),
+            ("location".to_string(), "/api/teams/{{segments.2}}/users/7".to_string()),
+        ]),
+        body: Some(r#"{"id": 7, "team": "{{segments.2}}", "name": "{{body.name}}"}"#.to_string()),
+        ..MockConfig::default()
+    }];
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+
+    //Act
+    let mocked = client
+        .post(format!("http://{}/api/teams/core/users", proxy))
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let forwarded = client
+        .get(format!("http://{}/api/teams/core/users", proxy))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(mocked.status(), StatusCode::CREATED);
+    assert_eq!(mocked.headers()["location"], "/api/teams/core/users/7");
+    let body: Value = mocked.json().await.unwrap();
+    assert_eq!(body, json!({ "id": 7, "team": "core", "name": "Ada" }));
+    assert_eq!(forwarded.status(), StatusCode::BAD_GATEWAY);
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 2 && !logs[0]["error"].is_null()).await;
+    assert!(logs[0].get("mock").is_none());
+    assert_eq!(logs[1]["mock"], "create-user");
+    assert_eq!(logs[1]["status"], 201);
+    assert_eq!(logs[1]["request_body"]["data"], r#"{"name":"Ada"}"#);
+    assert!(logs[1].get("backend").is_none());
+}
+
+#[tokio::test]
+async fn serves_body_from_file() {
+    //Arrange
+    let file = std::env::temp_dir().join(format!("endpoint-logger-mock-{}.json", std::process::id()));
+    std::fs::write(&file, r#"{"method": "{{method}}", "page": "{{query.page}}"}"#).unwrap();
+    let mut config = config_for("http://127.0.0.1:1".to_string());
+    config.mocks = vec![MockConfig {
+        path: "/api/**".to_string(),
+        body_file: Some(file.to_string_lossy().into_owned()),
+        ..MockConfig::default()
+    }];
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    std::fs::remove_file(&file).ok();
+
+    //Act
+    let response = reqwest::get(format!("http://{}/api/orders?page=3", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), r#"{"method": "GET", "page": "3"}"#);
+}
+
+#[tokio::test]
+async fn missing_body_file_is_rejected_at_startup() {
+    //Arrange
+    let mut config = config_for("http://127.0.0.1:1".to_string());
+    config.mocks = vec![MockConfig { body_file: Some("./does-not-exist.json".to_string()), ..MockConfig::default() }];
+
+    //Act
+    let result = spawn_proxy(config).await;
+
+    //Assert
+    assert!(result.is_err());
+}
@@ -0,0 +1,70 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use axum::Router;
+use axum::http::StatusCode;
+use axum::routing::get;
+use endpoint_logger::config::NetworkProfile;
+use serde_json::{Value, json};
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn profile_adds_latency_and_throttles_response() {
+    //Arrange
+    let proxy = proxy_with_profile(Some("tiny")).await;
+
+    //Act
+    let started = Instant::now();
+    let response = reqwest::get(format!("http://{}/download", proxy)).await.unwrap();
+    let body = response.bytes().await.unwrap();
+    let elapsed = started.elapsed();
+
+    //Assert
+    assert_eq!(body.len(), 5_000);
+    // 200 ms of latency, then 5000 bytes at 10 kB/s
+    assert!(elapsed >= Duration::from_millis(600), "took {:?}", elapsed);
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["response_body"]["complete"] == true).await;
+    assert_eq!(logs[0]["network_profile"], "tiny");
+}
+
+#[tokio::test]
+async fn profile_is_switched_through_the_api() {
+    //Arrange
+    let proxy = proxy_with_profile(None).await;
+    let client = reqwest::Client::new();
+    let network = format!("http://{}/__logger/api/network", proxy);
+
+    //Act
+    let selected = client.put(&network).json(&json!({ "profile": "3g" })).send().await.unwrap();
+    let selected: Value = selected.json().await.unwrap();
+    client.get(format!("http://{}/download", proxy)).send().await.unwrap();
+    client.put(&network).json(&json!({ "profile": null })).send().await.unwrap();
+    client.get(format!("http://{}/download", proxy)).send().await.unwrap();
+    let unknown = client.put(&network).json(&json!({ "profile": "dial-up" })).send().await.unwrap();
+    let status: Value = client.get(&network).send().await.unwrap().json().await.unwrap();
+
+    //Assert
+    assert_eq!(selected["profile"], "3g");
+    assert_eq!(unknown.status(), StatusCode::BAD_REQUEST);
+    assert!(status["profile"].is_null());
+    assert_eq!(status["profiles"]["satellite"]["latency_ms"], 600);
+    assert_eq!(status["profiles"]["tiny"]["bandwidth_kbps"], 80);
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 2).await;
+    assert!(logs[0].get("network_profile").is_none());
+    assert_eq!(logs[1]["network_profile"], "3g");
+}
+
+async fn proxy_with_profile(profile: Option<&str>) -> SocketAddr {
+    let app = Router::new().route("/download", get(|| async { "x".repeat(5_000) }));
+    let upstream = spawn_upstream(app).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.network.profile = profile.map(str::to_string);
+    config
+        .network
+        .profiles
+        .insert("tiny".to_string(), NetworkProfile { latency_ms: 200, bandwidth_kbps: 80 });
+    spawn_proxy(config).await.expect("Failed to spawn our app")
+}
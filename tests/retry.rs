@@ -0,0 +1,144 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use axum::Router;
+use axum::http::StatusCode;
+use axum::routing::{get, put};
+use endpoint_logger::config::{AppConfig, JsonPatchKind, JsonPatchOp, RetryConfig, RewriteConfig};
+use serde_json::json;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+
+mod common;
+
+use common::{config_for, spawn_proxy, wait_for_logs};
+
+#[tokio::test]
+async fn retries_idempotent_request_until_upstream_is_back() {
+    //Arrange
+    let upstream = free_address().await;
+    let proxy = spawn_proxy(retrying(upstream, 10)).await.expect("Failed to spawn our app");
+    tokio::spawn(async move {
+        // Comes back while the proxy is still retrying, like a backend rebuilt by cargo-watch
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let listener = TcpListener::bind(upstream).await.expect("Failed to bind address");
+        let app = Router::new().route("/users/42", put(|body: String| async move { body }));
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    //Act
+    let response = reqwest::Client::new()
+        .put(format!("http://{}/users/42", proxy))
+        .body("{\"name\":\"Ada\"}")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "{\"name\":\"Ada\"}");
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["status"] == 200).await;
+    let attempts = logs[0]["attempts"].as_array().unwrap();
+    assert!(attempts.len() > 1);
+    let (last, failed) = attempts.split_last().unwrap();
+    assert_eq!(last["attempt"], attempts.len());
+    assert_eq!(last["status"], 200);
+    assert!(failed.iter().all(|a| a["error"].as_str().unwrap().starts_with("Upstream unreachable")));
+}
+
+#[tokio::test]
+async fn gives_up_after_configured_attempts() {
+    //Arrange
+    let upstream = free_address().await;
+    let proxy = spawn_proxy(retrying(upstream, 3)).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && !logs[0]["error"].is_null()).await;
+    let attempts = logs[0]["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 3);
+    assert_eq!(attempts[2]["attempt"], 3);
+}
+
+#[tokio::test]
+async fn records_the_single_attempt_of_post() {
+    //Arrange
+    let upstream = free_address().await;
+    let proxy = spawn_proxy(retrying(upstream, 3)).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/orders", proxy))
+        .body("{}")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && !logs[0]["error"].is_null()).await;
+    let attempts = logs[0]["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 1);
+    assert_eq!(attempts[0]["attempt"], 1);
+    assert!(attempts[0]["error"].as_str().unwrap().starts_with("Upstream unreachable"));
+}
+
+#[tokio::test]
+async fn retries_json_patched_request_after_connection_reset() {
+    //Arrange
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("Failed to bind address");
+    let upstream = listener.local_addr().expect("Failed to get port");
+    tokio::spawn(async move {
+        // The first connection is reset once the request arrived, the next ones are served
+        let (mut stream, _) = listener.accept().await.expect("Failed to accept connection");
+        let _ = stream.read(&mut [0; 4096]).await;
+        #[allow(deprecated)]
+        stream.set_linger(Some(Duration::ZERO)).unwrap();
+        drop(stream);
+        let app = Router::new().route("/users/42", get(|body: String| async move { body }));
+        axum::serve(listener, app).await.unwrap();
+    });
+    let mut config = retrying(upstream, 3);
+    config.rewrites = vec![RewriteConfig {
+        path: "/users/*".to_string(),
+        json_patch: vec![JsonPatchOp { op: JsonPatchKind::Remove, path: "/token".to_string(), value: None }],
+        ..RewriteConfig::default()
+    }];
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/users/42", proxy))
+        .json(&json!({ "name": "Ada", "token": "secret" }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), r#"{"name":"Ada"}"#);
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["status"] == 200).await;
+    let attempts = logs[0]["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 2);
+    assert!(attempts[0]["error"].is_string());
+    assert_eq!(attempts[1]["status"], 200);
+}
+
+fn retrying(upstream: SocketAddr, attempts: u32) -> AppConfig {
+    let mut config = config_for(format!("http://{}", upstream));
+    config.upstream.retry = RetryConfig { attempts, backoff_ms: 50, max_backoff_ms: 100 };
+    config
+}
+
+/// Local address nothing listens on, so connections to it are refused
+async fn free_address() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind address");
+    listener.local_addr().expect("Failed to get port")
+}
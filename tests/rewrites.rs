@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use axum::http::{HeaderMap, StatusCode, Uri, header};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use endpoint_logger::config::{JsonPatchKind, JsonPatchOp, PathRewrite, RewriteConfig, RewritePhase};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde_json::{Value, json};
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn request_is_rewritten_before_forwarding() {
+    //Arrange
+    let app = Router::new().route(
+        "/v2/users",
+        post(|uri: Uri, headers: HeaderMap, Json(body): Json<Value>| async move {
+            Json(json!({
+                "uri": uri.to_string(),
+                "debug": headers.get("x-debug").and_then(|v| v.to_str().ok()),
+                "cookie": headers.contains_key("cookie"),
+                "body": body,
+            }))
+        }),
+    );
+    let upstream = spawn_upstream(app).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.rewrites = vec![RewriteConfig {
+        name: Some("api-v2".to_string()),
+        path: "/v1/**".to_string(),
+        set_headers: BTreeMap::from([("x-debug".to_string(), "1".to_string())]),
+        remove_headers: vec!["cookie".to_string()],
+        rewrite_path: Some(PathRewrite { pattern: "^/v1/(.*)$".to_string(), replacement: "/v2/$1".to_string() }),
+        json_patch: vec![JsonPatchOp {
+            op: JsonPatchKind::Replace,
+            path: "/name".to_string(),
+            value: Some(json!("Grace")),
+        }],
+        ..RewriteConfig::default()
+    }];
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/v1/users?team=core", proxy))
+        .header("cookie", "session=abc")
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let seen: Value = response.json().await.unwrap();
+    assert_eq!(seen["uri"], "/v2/users?team=core");
+    assert_eq!(seen["debug"], "1");
+    assert_eq!(seen["cookie"], false);
+    assert_eq!(seen["body"], json!({ "name": "Grace" }));
+
+    // The request is captured as the target saw it
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["response_body"]["complete"] == true).await;
+    assert_eq!(logs[0]["uri"], "/v2/users?team=core");
+    assert_eq!(logs[0]["rewrites"], json!(["api-v2"]));
+    assert_eq!(logs[0]["request_body"]["data"], r#"{"name":"Grace"}"#);
+}
+
+#[tokio::test]
+async fn response_is_rewritten_before_reaching_the_client() {
+    //Arrange
+    let app = Router::new().route(
+        "/api/users/{id}",
+        get(|| async {
+            let user = json!({ "user": { "name": "Ada", "token": "secret" } });
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(user))
+        }),
+    );
+    let upstream = spawn_upstream(app).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.rewrites = vec![RewriteConfig {
+        phase: RewritePhase::Response,
+        path: "/api/users/*".to_string(),
+        add_headers: BTreeMap::from([("x-rewritten".to_string(), "yes".to_string())]),
+        status: Some(200),
+        json_patch: vec![JsonPatchOp { op: JsonPatchKind::Remove, path: "/user/token".to_string(), value: None }],
+        ..RewriteConfig::default()
+    }];
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/api/users/1", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-rewritten"], "yes");
+    assert_eq!(response.json::<Value>().await.unwrap(), json!({ "user": { "name": "Ada" } }));
+
+    // The response is captured as the target sent it
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["response_body"]["complete"] == true).await;
+    assert_eq!(logs[0]["status"], 500);
+    assert_eq!(logs[0]["rewrites"], json!(["/api/users/*"]));
+    assert!(logs[0]["response_body"]["data"].as_str().unwrap().contains("secret"));
+}
+
+#[tokio::test]
+async fn encoded_json_body_is_not_patched() {
+    //Arrange
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(br#"{"user":{"name":"Ada","token":"secret"}}"#).unwrap();
+    let gzipped = encoder.finish().unwrap();
+    let sent = gzipped.clone();
+    let app = Router::new().route(
+        "/api/users/{id}",
+        get(move || async move {
+            ([(header::CONTENT_TYPE, "application/json"), (header::CONTENT_ENCODING, "gzip")], sent)
+        }),
+    );
+    let upstream = spawn_upstream(app).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.rewrites = vec![RewriteConfig {
+        phase: RewritePhase::Response,
+        path: "/api/users/*".to_string(),
+        json_patch: vec![JsonPatchOp { op: JsonPatchKind::Remove, path: "/user/token".to_string(), value: None }],
+        ..RewriteConfig::default()
+    }];
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/api/users/1", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], gzipped.len().to_string());
+    assert_eq!(response.bytes().await.unwrap(), gzipped);
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["response_body"]["complete"] == true).await;
+    assert_eq!(logs[0]["skipped_patches"], json!(["response body with content-encoding 'gzip'"]));
+}
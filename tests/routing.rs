@@ -0,0 +1,105 @@
+use axum::Router;
+use axum::http::{StatusCode, Uri};
+use endpoint_logger::config::RouteConfig;
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn routes_path_prefixes_to_their_targets() {
+    //Arrange
+    let users = spawn_upstream(Router::new().fallback(|uri: Uri| async move { format!("users {}", uri) })).await;
+    let web = spawn_upstream(Router::new().fallback(|uri: Uri| async move { format!("web {}", uri) })).await;
+    let mut config = config_for(format!("http://{}", web));
+    config.routes = vec![
+        route(Some("users"), "/api/users", format!("http://{}", users), true),
+        route(None, "/api", format!("http://{}/v1", users), false),
+    ];
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let stripped = get(format!("http://{}/api/users/42?expand=true", proxy)).await;
+    let prefixed = get(format!("http://{}/api/orders", proxy)).await;
+    let fallback = get(format!("http://{}/apis", proxy)).await;
+
+    //Assert
+    assert_eq!(stripped, "users /42?expand=true");
+    assert_eq!(prefixed, "users /v1/api/orders");
+    assert_eq!(fallback, "web /apis");
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 3).await;
+    let routes: Vec<_> = logs.iter().map(|l| l["route"].as_str()).collect();
+    assert_eq!(routes, vec![None, Some("/api"), Some("users")]);
+}
+
+#[tokio::test]
+async fn routes_by_host_header_before_path_routes() {
+    //Arrange
+    let api = spawn_upstream(Router::new().fallback(|uri: Uri| async move { format!("api {}", uri) })).await;
+    let web = spawn_upstream(Router::new().fallback(|uri: Uri| async move { format!("web {}", uri) })).await;
+    let mut config = config_for(String::new());
+    config.hosts.insert("api.localtest.me".to_string(), format!("http://{}", api));
+    config.hosts.insert("web.localtest.me".to_string(), format!("http://{}", web));
+    config.routes = vec![route(None, "/", format!("http://{}", web), false)];
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+
+    //Act
+    let mut bodies = Vec::new();
+    for host in ["api.localtest.me:3000", "WEB.localtest.me", "other.localtest.me"] {
+        let response = client
+            .get(format!("http://{}/users", proxy))
+            .header("host", host)
+            .send()
+            .await
+            .expect("Failed to execute request");
+        bodies.push(response.text().await.unwrap());
+    }
+
+    //Assert
+    assert_eq!(bodies, vec!["api /users", "web /users", "web /users"]);
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 3).await;
+    let routes: Vec<_> = logs.iter().map(|l| l["route"].as_str()).collect();
+    assert_eq!(routes, vec![Some("/"), Some("web.localtest.me"), Some("api.localtest.me")]);
+}
+
+#[tokio::test]
+async fn unmatched_path_without_target_returns_not_found() {
+    //Arrange
+    let users = spawn_upstream(Router::new().fallback(|| async { "users" })).await;
+    let mut config = config_for(String::new());
+    config.routes = vec![route(None, "/api", format!("http://{}", users), false)];
+    config.validate().expect("Routes should make target_url optional");
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/other", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let logs = wait_for_logs(proxy, |logs| !logs.is_empty()).await;
+    let error = logs[0]["error"].as_str().unwrap();
+    assert!(error.starts_with("No route matches") && error.ends_with("/other"), "{}", error);
+}
+
+fn route(name: Option<&str>, prefix: &str, target: String, strip_prefix: bool) -> RouteConfig {
+    RouteConfig {
+        name: name.map(str::to_string),
+        prefix: prefix.to_string(),
+        target,
+        strip_prefix,
+        network_profile: None,
+        shadow_target: None,
+    }
+}
+
+async fn get(url: String) -> String {
+    reqwest::get(url)
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .expect("Failed to read body")
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::routing::post;
+use axum::{Json, Router};
+use endpoint_logger::config::{BalanceStrategy, HealthCheckConfig, PoolConfig, RouteConfig};
+use serde_json::{Value, json};
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn mirrored_request_is_diffed_against_the_primary_response() {
+    //Arrange
+    let primary = Router::new().route(
+        "/users",
+        post(|Json(body): Json<Value>| async move { Json(json!({ "name": body["name"], "version": 1 })) }),
+    );
+    let shadow = Router::new().route(
+        "/users",
+        post(|uri: Uri, headers: HeaderMap, Json(body): Json<Value>| async move {
+            let forwarded = headers.contains_key("x-forwarded-for");
+            let user = json!({ "name": body["name"], "version": 2, "uri": uri.to_string(), "forwarded": forwarded });
+            (StatusCode::CREATED, Json(user))
+        }),
+    );
+    let primary = spawn_upstream(primary).await;
+    let shadow = spawn_upstream(shadow).await;
+    let mut config = config_for(format!("http://{}", primary));
+    config.shadow_target_url = Some(format!("http://{}", shadow));
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/users?team=core", proxy))
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    // The client only ever sees the primary response
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.json::<Value>().await.unwrap(), json!({ "name": "Ada", "version": 1 }));
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["shadow"].is_object()).await;
+    let mirrored = &logs[0]["shadow"];
+    assert_eq!(mirrored["backend"], format!("http://{}", shadow));
+    assert_eq!(mirrored["status"], 201);
+    assert_eq!(mirrored["response_body"]["complete"], true);
+    let paths: Vec<&str> = mirrored["diff"].as_array().unwrap().iter().map(|d| d["path"].as_str().unwrap()).collect();
+    assert_eq!(paths, ["/status", "/headers/content-length", "/body/forwarded", "/body/uri", "/body/version"]);
+    let version = &mirrored["diff"][4];
+    assert_eq!(version["kind"], "changed");
+    assert_eq!(version["primary"], 1);
+    assert_eq!(version["shadow"], 2);
+    let uri = &mirrored["diff"][3];
+    assert_eq!(uri["kind"], "added");
+    assert_eq!(uri["shadow"], "/users?team=core");
+    assert_eq!(mirrored["diff"][2]["shadow"], true);
+}
+
+#[tokio::test]
+async fn unreachable_route_shadow_does_not_affect_the_client() {
+    //Arrange
+    let primary = Router::new().route("/users", post(|body: String| async move { body }));
+    let primary = spawn_upstream(primary).await;
+    let mut config = config_for("http://127.0.0.1:1".to_string());
+    config.routes = vec![RouteConfig {
+        name: Some("users".to_string()),
+        prefix: "/api".to_string(),
+        target: format!("http://{}", primary),
+        strip_prefix: true,
+        network_profile: None,
+        shadow_target: Some("http://127.0.0.1:1".to_string()),
+    }];
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/api/users", proxy))
+        .body("streamed once, sent twice")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "streamed once, sent twice");
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["shadow"].is_object()).await;
+    let mirrored = &logs[0]["shadow"];
+    assert!(mirrored["status"].is_null());
+    assert!(mirrored["error"].as_str().unwrap().contains("unreachable"), "{}", mirrored["error"]);
+    assert_eq!(mirrored["diff"][0]["path"], "/status");
+    assert_eq!(mirrored["diff"][0]["primary"], 200);
+}
+
+#[tokio::test]
+async fn large_shadow_response_is_truncated_and_large_request_is_not_mirrored() {
+    //Arrange
+    let primary = Router::new().route("/files", post(|| async { "stored" }));
+    let shadow = Router::new().route("/files", post(|| async { "x".repeat(64 * 1024) }));
+    let primary = spawn_upstream(primary).await;
+    let shadow = spawn_upstream(shadow).await;
+    let mut config = config_for(format!("http://{}", primary));
+    config.shadow_target_url = Some(format!("http://{}", shadow));
+    config.logging.max_body_size_kb = 1;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let client = reqwest::Client::new();
+
+    //Act
+    let small = client
+        .post(format!("http://{}/files", proxy))
+        .body("small")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let large = client
+        .post(format!("http://{}/files", proxy))
+        .body(vec![b'a'; 2 * 1024 * 1024])
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(small.status(), StatusCode::OK);
+    assert_eq!(large.status(), StatusCode::OK);
+    let logs = wait_for_logs(proxy, |logs| {
+        logs.len() == 2 && logs.iter().all(|l| l["shadow"].is_object()) && logs[0]["response_body"]["complete"] == true
+    })
+    .await;
+    // Newest first: the large request was only sent to the primary
+    assert!(logs[0]["shadow"]["status"].is_null());
+    assert_eq!(logs[0]["shadow"]["error"], "Not mirrored: body of unknown length or over 1 MiB");
+    assert!(logs[0]["shadow"]["diff"].as_array().unwrap().is_empty());
+    let mirrored = &logs[1]["shadow"];
+    assert_eq!(mirrored["truncated"], true);
+    assert_eq!(mirrored["response_body"]["truncated"], true);
+    assert_eq!(mirrored["response_body"]["size"], 64 * 1024);
+}
+
+#[tokio::test]
+async fn shadow_pool_without_healthy_members_does_not_affect_the_client() {
+    //Arrange
+    let primary = spawn_upstream(Router::new().route("/users", post(|| async { "created" }))).await;
+    let down = spawn_upstream(Router::new().fallback(|| async { StatusCode::SERVICE_UNAVAILABLE })).await;
+    let mut config = config_for(format!("http://{}", primary));
+    config.shadow_target_url = Some("pool:shadows".to_string());
+    config.pools = BTreeMap::from([(
+        "shadows".to_string(),
+        PoolConfig {
+            members: vec![format!("http://{}", down)],
+            strategy: BalanceStrategy::RoundRobin,
+            health_check: Some(HealthCheckConfig { path: "/".to_string(), interval_ms: 20, timeout_ms: 500 }),
+        },
+    )]);
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    //Act
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/users", proxy))
+        .body("{}")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "created");
+
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["shadow"].is_object()).await;
+    let mirrored = &logs[0]["shadow"];
+    assert_eq!(mirrored["backend"], "pool:shadows");
+    assert!(mirrored["error"].as_str().unwrap().contains("No healthy member in pool 'shadows'"), "{}", mirrored["error"]);
+}
@@ -0,0 +1,77 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use tokio::sync::{Mutex, mpsc};
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn relays_events_immediately_and_records_each_one() {
+    //Arrange
+    let (release_tx, release_rx) = mpsc::channel::<()>(1);
+    let release_rx = Arc::new(Mutex::new(Some(release_rx)));
+    let app = Router::new().route(
+        "/events",
+        get(move || {
+            let release_rx = release_rx.clone();
+            async move { event_stream(release_rx.lock().await.take().unwrap()) }
+        }),
+    );
+    let upstream = spawn_upstream(app).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+
+    //Act
+    let mut response = reqwest::get(format!("http://{}/events", proxy))
+        .await
+        .expect("Failed to execute request");
+    // The upstream holds the rest of the stream until released, so the first event
+    // can only arrive here if the proxy flushes it straight away
+    let first = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+        .await
+        .expect("First event was buffered by the proxy")
+        .unwrap()
+        .unwrap();
+    release_tx.send(()).await.unwrap();
+    let mut rest = Vec::new();
+    while let Some(chunk) = response.chunk().await.unwrap() {
+        rest.extend_from_slice(&chunk);
+    }
+
+    //Assert
+    assert_eq!(first, "event: token\nid: 1\ndata: hello\n\n");
+    assert_eq!(String::from_utf8(rest).unwrap(), ": ping\n\ndata: line one\ndata: line two\n\n");
+
+    let logs = wait_for_logs(proxy, |logs| {
+        logs.len() == 1 && logs[0]["sse_events"].as_array().is_some_and(|e| e.len() == 2)
+    })
+    .await;
+    let events = logs[0]["sse_events"].as_array().unwrap();
+    assert_eq!(events[0]["event"], "token");
+    assert_eq!(events[0]["id"], "1");
+    assert_eq!(events[0]["data"], "hello");
+    assert!(events[1].get("event").is_none());
+    assert_eq!(events[1]["data"], "line one\nline two");
+    assert!(events[1]["timestamp_ms"].as_u64() >= events[0]["timestamp_ms"].as_u64());
+}
+
+fn event_stream(release: mpsc::Receiver<()>) -> impl IntoResponse {
+    let stream = futures_util::stream::unfold((0, release), |(step, mut release)| async move {
+        let chunk = match step {
+            0 => "event: token\nid: 1\ndata: hello\n\n",
+            1 => {
+                release.recv().await;
+                ": ping\n\n"
+            }
+            2 => "data: line one\ndata: line two\n\n",
+            _ => return None,
+        };
+        Some((Ok::<_, Infallible>(Bytes::from_static(chunk.as_bytes())), (step + 1, release)))
+    });
+    ([("content-type", "text/event-stream")], Body::from_stream(stream))
+}
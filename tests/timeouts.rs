@@ -0,0 +1,88 @@
+use std::convert::Infallible;
+use std::time::Duration;
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::http::StatusCode;
+use axum::routing::get;
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn slow_response_headers_time_out_with_504() {
+    //Arrange
+    let app = Router::new().route(
+        "/slow",
+        get(|| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "too late"
+        }),
+    );
+    let upstream = spawn_upstream(app).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.upstream.response_header_timeout_ms = 200;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/slow", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(response.text().await.unwrap(), "Upstream response headers timed out after 200 ms");
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && !logs[0]["error"].is_null()).await;
+    assert_eq!(logs[0]["error"], "Upstream response headers timed out after 200 ms");
+}
+
+#[tokio::test]
+async fn request_timeout_cuts_off_stalled_body() {
+    //Arrange
+    let app = Router::new().route("/stall", get(|| async { Body::from_stream(stalled_stream()) }));
+    let upstream = spawn_upstream(app).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.upstream.request_timeout_ms = 300;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/stall", proxy))
+        .await
+        .expect("Failed to execute request");
+    let status = response.status();
+    let body = tokio::time::timeout(Duration::from_secs(5), response.bytes())
+        .await
+        .expect("Stalled body was never cut off");
+
+    //Assert
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.is_err());
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1 && logs[0]["response_body"]["size"] == 5).await;
+    assert_eq!(logs[0]["response_body"]["complete"], false);
+}
+
+#[tokio::test]
+async fn unreachable_upstream_is_still_a_502() {
+    //Arrange
+    let mut config = config_for("http://127.0.0.1:1".to_string());
+    config.upstream.connect_timeout_ms = 200;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+}
+
+/// Send a first chunk, then never finish
+fn stalled_stream() -> impl futures_util::Stream<Item = Result<Bytes, Infallible>> {
+    futures_util::stream::unfold(false, |sent| async move {
+        if sent {
+            std::future::pending::<()>().await;
+        }
+        Some((Ok(Bytes::from_static(b"start")), true))
+    })
+}
@@ -0,0 +1,50 @@
+use axum::Router;
+use axum::http::HeaderMap;
+use endpoint_logger::config::TlsConfig;
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream};
+
+#[tokio::test]
+async fn serves_https_with_generated_local_certificate() {
+    //Arrange
+    let cert_dir = std::env::temp_dir().join(format!("endpoint-logger-tls-test-{}", std::process::id()));
+    std::fs::remove_dir_all(&cert_dir).ok();
+    let upstream = spawn_upstream(Router::new().fallback(forwarded_proto)).await;
+    let mut config = config_for(format!("http://{}", upstream));
+    config.tls = Some(TlsConfig {
+        cert_dir: cert_dir.to_string_lossy().into_owned(),
+        ..TlsConfig::default()
+    });
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    let ca = std::fs::read(cert_dir.join("ca.pem")).expect("Local CA should be persisted");
+    assert!(cert_dir.join("localhost.pem").exists());
+    let client = reqwest::Client::builder()
+        .tls_certs_only([reqwest::Certificate::from_pem(&ca).unwrap()])
+        .build()
+        .unwrap();
+
+    //Act
+    let response = client
+        .get(format!("https://localhost:{}/secure", proxy.port()))
+        .send()
+        .await
+        .expect("TLS handshake with the local certificate failed");
+
+    //Assert
+    assert!(response.status().is_success());
+    assert_eq!(response.text().await.unwrap(), "https");
+
+    std::fs::remove_dir_all(&cert_dir).ok();
+}
+
+/// Answer with the X-Forwarded-Proto header set by the proxy
+async fn forwarded_proto(headers: HeaderMap) -> String {
+    headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
@@ -0,0 +1,74 @@
+#![cfg(unix)]
+
+use std::path::PathBuf;
+use axum::Router;
+use axum::http::{HeaderMap, StatusCode, Uri};
+use tokio::net::UnixListener;
+
+mod common;
+
+use common::{config_for, spawn_proxy, wait_for_logs};
+
+#[tokio::test]
+async fn forwards_to_target_listening_on_unix_socket() {
+    //Arrange
+    let socket = spawn_unix_upstream(
+        "forward",
+        Router::new().fallback(|uri: Uri, headers: HeaderMap| async move {
+            format!("{} host={}", uri, headers["host"].to_str().unwrap())
+        }),
+    )
+    .await;
+    let proxy = spawn_proxy(config_for(format!("unix://{}:/api", socket.display())))
+        .await
+        .expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/containers/json?all=true", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "/api/containers/json?all=true host=localhost");
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1).await;
+    assert_eq!(logs[0]["backend"], format!("unix://{}", socket.display()));
+    assert_eq!(logs[0]["status"], 200);
+}
+
+#[tokio::test]
+async fn missing_socket_returns_bad_gateway() {
+    //Arrange
+    let socket = temp_dir("missing").join("app.sock");
+    let proxy = spawn_proxy(config_for(format!("unix://{}", socket.display())))
+        .await
+        .expect("Failed to spawn our app");
+
+    //Act
+    let response = reqwest::get(format!("http://{}/health", proxy))
+        .await
+        .expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 1).await;
+    assert!(logs[0]["error"].is_string());
+}
+
+/// Serve `app` on a socket in a fresh temporary directory
+async fn spawn_unix_upstream(name: &str, app: Router) -> PathBuf {
+    let dir = temp_dir(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    let socket = dir.join("app.sock");
+    let listener = UnixListener::bind(&socket).expect("Failed to bind socket");
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    socket
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("endpoint-logger-unix-{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    dir
+}
@@ -0,0 +1,235 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use axum::Router;
+use axum::http::StatusCode;
+use axum::routing::get;
+use endpoint_logger::config::UpstreamTlsConfig;
+use endpoint_logger::tls::{LocalCa, server_config};
+use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair};
+use rustls::RootCertStore;
+use rustls::crypto::aws_lc_rs;
+use rustls::pki_types::CertificateDer;
+use rustls::pki_types::pem::PemObject;
+use rustls::server::WebPkiClientVerifier;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+mod common;
+
+use common::{config_for, spawn_proxy, wait_for_logs};
+
+#[tokio::test]
+async fn trusts_upstream_signed_by_ca_bundle() {
+    //Arrange
+    let dir = temp_dir("ca-bundle");
+    let ca = LocalCa::load_or_create(&dir).unwrap();
+    let upstream = spawn_https_upstream(acceptor(&ca, &["localhost"])).await;
+    let mut config = config_for(format!("https://localhost:{}", upstream.port()));
+    config.upstream.tls.ca_bundle = Some(path(&dir, "ca.pem"));
+    let trusted = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let untrusted = spawn_proxy(config_for(format!("https://localhost:{}", upstream.port())))
+        .await
+        .expect("Failed to spawn our app");
+
+    //Act
+    let trusted = fetch(trusted).await;
+    let untrusted = fetch(untrusted).await;
+
+    //Assert
+    assert_eq!(trusted.status(), StatusCode::OK);
+    assert_eq!(trusted.text().await.unwrap(), "secure");
+    assert_eq!(untrusted.status(), StatusCode::BAD_GATEWAY);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn insecure_skip_verify_accepts_untrusted_upstream() {
+    //Arrange
+    let dir = temp_dir("insecure");
+    let ca = LocalCa::load_or_create(&dir).unwrap();
+    let upstream = spawn_https_upstream(acceptor(&ca, &["localhost"])).await;
+    let mut config = config_for(format!("https://localhost:{}", upstream.port()));
+    config.upstream.tls.insecure_skip_verify = true;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = fetch(proxy).await;
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn auto_version_uses_http2_negotiated_with_the_target_for_every_client() {
+    //Arrange
+    let dir = temp_dir("auto-version");
+    let ca = LocalCa::load_or_create(&dir).unwrap();
+    let upstream = spawn_https_upstream(acceptor(&ca, &["localhost"])).await;
+    let mut config = config_for(format!("https://localhost:{}", upstream.port()));
+    config.upstream.tls.insecure_skip_verify = true;
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let h2c = reqwest::Client::builder().http2_prior_knowledge().build().unwrap();
+
+    //Act
+    let http1 = fetch(proxy).await;
+    let http2 = h2c.get(format!("http://{}/", proxy)).send().await.expect("Failed to execute request");
+
+    //Assert
+    assert_eq!(http1.status(), StatusCode::OK);
+    assert_eq!(http2.status(), StatusCode::OK);
+    let logs = wait_for_logs(proxy, |logs| logs.len() == 2).await;
+    assert_eq!(logs[1]["http_version"], "HTTP/1.1");
+    assert_eq!(logs[0]["http_version"], "HTTP/2.0");
+    assert!(logs.iter().all(|l| l["upstream_http_version"] == "HTTP/2.0"), "{:?}", logs);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn server_name_overrides_target_host() {
+    //Arrange
+    let dir = temp_dir("server-name");
+    let ca = LocalCa::load_or_create(&dir).unwrap();
+    let upstream = spawn_https_upstream(acceptor(&ca, &["api.internal"])).await;
+    let mut config = config_for(format!("https://{}", upstream));
+    config.upstream.tls = UpstreamTlsConfig {
+        ca_bundle: Some(path(&dir, "ca.pem")),
+        server_name: Some("api.internal".to_string()),
+        ..UpstreamTlsConfig::default()
+    };
+    let proxy = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let response = fetch(proxy).await;
+
+    //Assert
+    assert_eq!(response.status(), StatusCode::OK);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn presents_client_certificate_to_upstream() {
+    //Arrange
+    let dir = temp_dir("mtls");
+    let ca = LocalCa::load_or_create(&dir).unwrap();
+    let (client_ca_pem, client_cert_pem, client_key_pem) = client_certificate();
+    std::fs::write(dir.join("client.pem"), client_cert_pem).unwrap();
+    std::fs::write(dir.join("client-key.pem"), client_key_pem).unwrap();
+
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_slice(client_ca_pem.as_bytes()).unwrap()).unwrap();
+    let verifier = WebPkiClientVerifier::builder_with_provider(
+        Arc::new(roots),
+        Arc::new(aws_lc_rs::default_provider()),
+    )
+    .build()
+    .unwrap();
+    let mut server = server_config_with(&ca, verifier);
+    server.alpn_protocols = vec![b"http/1.1".to_vec()];
+    let upstream = spawn_https_upstream(TlsAcceptor::from(Arc::new(server))).await;
+
+    let target = format!("https://localhost:{}", upstream.port());
+    let mut config = config_for(target.clone());
+    config.upstream.tls = UpstreamTlsConfig {
+        ca_bundle: Some(path(&dir, "ca.pem")),
+        client_cert: Some(path(&dir, "client.pem")),
+        client_key: Some(path(&dir, "client-key.pem")),
+        ..UpstreamTlsConfig::default()
+    };
+    let with_cert = spawn_proxy(config).await.expect("Failed to spawn our app");
+    let mut config = config_for(target);
+    config.upstream.tls.ca_bundle = Some(path(&dir, "ca.pem"));
+    let without_cert = spawn_proxy(config).await.expect("Failed to spawn our app");
+
+    //Act
+    let accepted = fetch(with_cert).await;
+    let rejected = fetch(without_cert).await;
+
+    //Assert
+    assert_eq!(accepted.status(), StatusCode::OK);
+    assert_eq!(rejected.status(), StatusCode::BAD_GATEWAY);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn client_cert_without_key_is_rejected_at_startup() {
+    //Arrange
+    let mut config = config_for("https://localhost:1".to_string());
+    config.upstream.tls.client_cert = Some("client.pem".to_string());
+
+    //Act
+    let result = spawn_proxy(config).await;
+
+    //Assert
+    assert!(result.is_err());
+}
+
+/// Serve a fixed body over TLS, standing in for an https target
+async fn spawn_https_upstream(acceptor: TlsAcceptor) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind address");
+    let address = listener.local_addr().expect("Failed to get port");
+    let app = Router::new().route("/", get(|| async { "secure" }));
+    tokio::spawn(endpoint_logger::server::serve_tls(listener, acceptor, app));
+    address
+}
+
+fn acceptor(ca: &LocalCa, names: &[&str]) -> TlsAcceptor {
+    let (cert, key) = ca.issue(names).unwrap();
+    TlsAcceptor::from(Arc::new(server_config(&cert, &key).unwrap()))
+}
+
+/// Server config for `localhost` that requires client certificates accepted by `verifier`
+fn server_config_with(
+    ca: &LocalCa,
+    verifier: Arc<dyn rustls::server::danger::ClientCertVerifier>,
+) -> rustls::ServerConfig {
+    let (cert, key) = ca.issue(&["localhost"]).unwrap();
+    rustls::ServerConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(
+            CertificateDer::pem_slice_iter(cert.as_bytes()).map(Result::unwrap).collect(),
+            rustls::pki_types::PrivateKeyDer::from_pem_slice(key.as_bytes()).unwrap(),
+        )
+        .unwrap()
+}
+
+/// Generate a client CA and a client certificate it signs, as PEM
+fn client_certificate() -> (String, String, String) {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+    let issuer = Issuer::new(ca_params, ca_key);
+
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(vec!["client".to_string()]).unwrap();
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let cert = params.signed_by(&key, &issuer).unwrap();
+    (ca_cert.pem(), cert.pem(), key.serialize_pem())
+}
+
+async fn fetch(proxy: SocketAddr) -> reqwest::Response {
+    reqwest::get(format!("http://{}/", proxy))
+        .await
+        .expect("Failed to execute request")
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("endpoint-logger-upstream-{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    dir
+}
+
+fn path(dir: &std::path::Path, file: &str) -> String {
+    dir.join(file).to_string_lossy().into_owned()
+}
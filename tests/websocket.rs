@@ -0,0 +1,64 @@
+use axum::Router;
+use axum::extract::ws::{Message as AxumMessage, WebSocketUpgrade};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+mod common;
+
+use common::{config_for, spawn_proxy, spawn_upstream, wait_for_logs};
+
+#[tokio::test]
+async fn tunnels_websocket_and_records_frames() {
+    //Arrange
+    let upstream = spawn_upstream(Router::new().route("/ws", get(echo_ws))).await;
+    let proxy = spawn_proxy(config_for(format!("http://{}", upstream))).await.expect("Failed to spawn our app");
+
+    //Act
+    let (mut socket, response) = tokio_tungstenite::connect_async(format!("ws://{}/ws", proxy))
+        .await
+        .expect("Handshake through the proxy failed");
+    socket.send(Message::text("hello")).await.unwrap();
+    let echoed = socket.next().await.unwrap().unwrap();
+    socket.send(Message::binary(vec![1u8, 2, 3])).await.unwrap();
+    let echoed_binary = socket.next().await.unwrap().unwrap();
+
+    //Assert
+    assert_eq!(response.status(), 101);
+    assert_eq!(echoed, Message::text("echo: hello"));
+    assert_eq!(echoed_binary, Message::binary(vec![1u8, 2, 3]));
+
+    let logs = wait_for_logs(proxy, |logs| {
+        logs.len() == 1 && logs[0]["websocket_frames"].as_array().is_some_and(|f| f.len() >= 4)
+    })
+    .await;
+    let exchange = &logs[0];
+    assert_eq!(exchange["status"], 101);
+    let frames = exchange["websocket_frames"].as_array().unwrap();
+    assert_eq!(frames[0]["direction"], "client_to_server");
+    assert_eq!(frames[0]["opcode"], 1);
+    assert_eq!(frames[0]["size"], 5);
+    assert_eq!(frames[0]["text"], "hello");
+    assert_eq!(frames[1]["direction"], "server_to_client");
+    assert_eq!(frames[1]["text"], "echo: hello");
+    assert_eq!(frames[2]["opcode"], 2);
+    assert_eq!(frames[2]["size"], 3);
+    assert!(frames[2]["timestamp_ms"].as_u64().unwrap() > 0);
+}
+
+async fn echo_ws(ws: WebSocketUpgrade) -> impl IntoResponse {
+    ws.on_upgrade(|mut socket| async move {
+        while let Some(Ok(message)) = socket.recv().await {
+            let reply = match message {
+                AxumMessage::Text(text) => AxumMessage::text(format!("echo: {}", text.as_str())),
+                AxumMessage::Binary(data) => AxumMessage::Binary(data),
+                AxumMessage::Close(_) => break,
+                _ => continue,
+            };
+            if socket.send(reply).await.is_err() {
+                break;
+            }
+        }
+    })
+}